@@ -33,19 +33,11 @@ if [ ! -x "$EXTRACTOR" ]; then
     exit 1
 fi
 
-# Find all .sol files
+# Discover the project's Solidity files (Foundry layouts, imported
+# dependencies) and extract them. The extractor applies the code scanning
+# `paths`/`paths-ignore` filters it is given in LGTM_INDEX_FILTERS.
 SOURCE_ROOT="${LGTM_SRC:-.}"
 
-# Create file list
-FILE_LIST=$(mktemp)
-find "$SOURCE_ROOT" -name "*.sol" -type f > "$FILE_LIST"
-
-if [ ! -s "$FILE_LIST" ]; then
-    echo "No Solidity files found in $SOURCE_ROOT"
-    rm "$FILE_LIST"
-    exit 0
-fi
-
 # Set up directories
 TRAP_DIR="${CODEQL_EXTRACTOR_SOLIDITY_TRAP_DIR:-trap}"
 SRC_ARCHIVE="${CODEQL_EXTRACTOR_SOLIDITY_SOURCE_ARCHIVE_DIR:-src-archive}"
@@ -53,10 +45,9 @@ SRC_ARCHIVE="${CODEQL_EXTRACTOR_SOLIDITY_SOURCE_ARCHIVE_DIR:-src-archive}"
 mkdir -p "$TRAP_DIR" "$SRC_ARCHIVE"
 
 # Run extractor
-"$EXTRACTOR" extract \
-    --file-list "$FILE_LIST" \
+"$EXTRACTOR" autobuild \
+    --root "$SOURCE_ROOT" \
     --trap-dir "$TRAP_DIR" \
     --source-archive-dir "$SRC_ARCHIVE"
 
-rm "$FILE_LIST"
 echo "Extraction complete"
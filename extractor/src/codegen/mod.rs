@@ -116,10 +116,210 @@ class File extends @file {
     /** Gets the name/path of this file */
     string getName() { files(this, result) }
 
+    /** Gets the number of syntax errors found when extracting this file */
+    int getNumberOfParseErrors() { solidity_parse_error_count(this, result) }
+
+    /** Gets the role of this file in its project: `source`, `test`, `script` or `library` */
+    string getRole() { solidity_file_role(this, result) }
+
+    /** Holds if this file is a dependency rather than part of the project */
+    predicate isLibrary() { this.getRole() = "library" }
+
+    /** Holds if this file is a test or a script */
+    predicate isTestOrScript() { this.getRole() = ["test", "script"] }
+
+    /**
+     * Gets why this file was extracted in diff mode: `changed`, `importer` or
+     * `dependency`. Has no result for a full extraction.
+     */
+    string getDiffScope() { solidity_diff_scope(this, result) }
+
+    /** Gets the build project this file belongs to */
+    Project getProject() { solidity_project_file(this, result) }
+
+    /**
+     * Gets the compiler settings this file was given, if it was extracted from
+     * a solc standard-JSON input.
+     */
+    CompilerSettings getCompilerSettings() { solidity_compiler_settings_file(this, result) }
+
+    /** Gets a string representation */
+    string toString() { result = this.getName() }
+}
+
+/** A build project (Foundry, Hardhat, Truffle or unconfigured) in the source tree */
+class Project extends @solidity_project {
+    /** Gets the absolute path of the project directory */
+    string getRoot() { solidity_project(this, result, _) }
+
+    /** Gets the build framework: `foundry`, `hardhat`, `truffle` or `none` */
+    string getFramework() { solidity_project(this, _, result) }
+
+    /** Gets a file belonging to this project */
+    File getAFile() { solidity_project_file(result, this) }
+
+    /** Gets a string representation */
+    string toString() { result = this.getRoot() }
+}
+
+/** The compiler settings of a solc standard-JSON input or verified-source bundle */
+class CompilerSettings extends @solidity_compiler_settings {
+    /** Gets the path of the standard-JSON input file */
+    string getInput() { solidity_compiler_settings(this, result) }
+
+    /** Gets the `i`th import remapping, as written */
+    string getRemapping(int i) { solidity_compiler_remapping(this, i, result) }
+
+    /** Holds if the optimizer is enabled */
+    predicate isOptimizerEnabled() { solidity_compiler_optimizer(this, 1, _) }
+
+    /** Gets the number of optimizer runs, if the optimizer is configured */
+    int getOptimizerRuns() { solidity_compiler_optimizer(this, _, result) }
+
+    /** Gets the target EVM version, if one is set */
+    string getEvmVersion() { solidity_compiler_evm_version(this, result) }
+
+    /** Holds if code is generated through the IR (`viaIR`) */
+    predicate isViaIR() { solidity_compiler_via_ir(this, 1) }
+
+    /** Gets the compiler version, if it is known */
+    string getCompilerVersion() { solidity_compiler_version(this, result) }
+
+    /** Gets a file extracted from the input */
+    File getAFile() { solidity_compiler_settings_file(result, this) }
+
+    /** Gets the deployed contract compiled with these settings, for a bundle */
+    DeployedContract getDeployedContract() { solidity_deployed_contract(result, _, this) }
+
+    /** Gets a string representation */
+    string toString() { result = this.getInput() }
+}
+
+/** A deployed contract whose verified source was extracted from a bundle */
+class DeployedContract extends @solidity_deployed_contract {
+    /** Gets the name of the contract, or "" if it is not known */
+    string getName() { solidity_deployed_contract(this, result, _) }
+
+    /** Gets the compiler settings of the contract */
+    CompilerSettings getCompilerSettings() { solidity_deployed_contract(this, _, result) }
+
+    /** Gets the address of the contract, if it is known */
+    string getAddress() { solidity_deployed_contract_address(this, result) }
+
+    /** Gets the chain id of the chain the contract is deployed on, if it is known */
+    int getChainId() { solidity_deployed_contract_chain(this, result) }
+
+    /** Gets the ABI-encoded constructor arguments, in hex, if they are known */
+    string getConstructorArguments() { solidity_deployed_contract_constructor_args(this, result) }
+
+    /** Gets a string representation */
+    string toString() {
+        if exists(this.getAddress()) then result = this.getAddress() else result = this.getName()
+    }
+}
+
+/** A build-info file: compiler output that semantic information was imported from */
+class BuildInfo extends @solidity_build_info {
+    /** Gets the path of the build-info file */
+    string getPath() { solidity_build_info(this, result, _) }
+
+    /** Gets the version of the compiler */
+    string getSolcVersion() { solidity_build_info(this, _, result) }
+
+    /** Gets a string representation */
+    string toString() { result = this.getPath() }
+}
+
+/** A node of the compiler's AST, matched to a syntax tree node */
+class CompilerNode extends @solidity_solc_node {
+    /** Gets the build this node comes from */
+    BuildInfo getBuildInfo() { solidity_solc_node(this, result, _, _, _) }
+
+    /** Gets the compiler's id of this node */
+    int getSolcId() { solidity_solc_node(this, _, result, _, _) }
+
+    /** Gets the compiler's node type, such as `Identifier` or `FunctionDefinition` */
+    string getNodeType() { solidity_solc_node(this, _, _, result, _) }
+
+    /** Gets the syntax tree node this node matches */
+    Solidity::AstNode getAstNode() { solidity_solc_node(this, _, _, _, result) }
+
+    /** Gets the declaration this name refers to, as resolved by the compiler */
+    CompilerNode getReferencedDeclaration() {
+        exists(int id |
+            solidity_solc_referenced_declaration(this, id) and
+            solidity_solc_node(result, this.getBuildInfo(), id, _, _)
+        )
+    }
+
+    /** Gets the `i`th candidate declaration of this overloaded name */
+    CompilerNode getOverloadedDeclaration(int i) {
+        exists(int id |
+            solidity_solc_overloaded_declaration(this, i, id) and
+            solidity_solc_node(result, this.getBuildInfo(), id, _, _)
+        )
+    }
+
+    /** Gets the type of this expression or declaration, as the compiler prints it */
+    string getTypeString() { solidity_solc_type(this, result, _) }
+
+    /** Gets the compiler's identifier of the type of this expression or declaration */
+    string getTypeIdentifier() { solidity_solc_type(this, _, result) }
+
+    /** Gets the `i`th linearized base contract of this contract (0 is the contract itself) */
+    CompilerNode getLinearizedBase(int i) {
+        exists(int id |
+            solidity_solc_linearized_base(this, i, id) and
+            solidity_solc_node(result, this.getBuildInfo(), id, _, _)
+        )
+    }
+
+    /** Gets a string representation */
+    string toString() { result = this.getNodeType() }
+}
+
+/** A lexical scope of name resolution */
+class Scope extends @solidity_scope {
+    /** Gets the kind of this scope: `file`, `contract`, `function`, `block` or `yul_block` */
+    string getKind() { solidity_scope(this, result, _) }
+
+    /** Gets the node introducing this scope */
+    Solidity::AstNode getNode() { solidity_scope(this, _, result) }
+
+    /** Gets the scope enclosing this scope */
+    Scope getParent() { solidity_scope_parent(this, result) }
+
+    /** Gets a declaration of `name` in this scope */
+    BindingTarget getDeclaration(string name) { solidity_scope_declaration(this, name, result) }
+
+    /** Gets the location of this scope */
+    Location getLocation() { result = this.getNode().getLocation() }
+
+    /** Gets a string representation */
+    string toString() { result = this.getKind() + " scope" }
+}
+
+/** A builtin name, such as `msg`, or a member of one, such as `msg.sender` */
+class Builtin extends @solidity_builtin {
+    /** Gets the name of this builtin */
+    string getName() { solidity_builtin(this, result) }
+
     /** Gets a string representation */
     string toString() { result = this.getName() }
 }
 
+/** What a name can be bound to: a declaration, a builtin or an imported file */
+class BindingTarget extends @solidity_binding_target {
+    /** Gets a string representation */
+    string toString() {
+        result = this.(Solidity::AstNode).toString()
+        or
+        result = this.(Builtin).toString()
+        or
+        result = this.(File).toString()
+    }
+}
+
 /** A source location */
 class Location extends @location_default {
     /** Gets the file containing this location */
@@ -143,6 +343,27 @@ class Location extends @location_default {
     }
 }
 
+/** A diagnostic reported by the extractor, such as a parse error */
+class Diagnostic extends @diagnostic {
+    /** Gets the severity (10 = debug, 20 = info, 30 = warning, 40 = error) */
+    int getSeverity() { diagnostics(this, result, _, _, _, _) }
+
+    /** Gets the tag identifying the kind of diagnostic (e.g. `parse_error`) */
+    string getTag() { diagnostics(this, _, result, _, _, _) }
+
+    /** Gets the short error message */
+    string getMessage() { diagnostics(this, _, _, result, _, _) }
+
+    /** Gets the full error message */
+    string getFullMessage() { diagnostics(this, _, _, _, result, _) }
+
+    /** Gets the location of this diagnostic */
+    Location getLocation() { diagnostics(this, _, _, _, _, result) }
+
+    /** Gets a string representation */
+    string toString() { result = this.getMessage() }
+}
+
 "#
     .to_string()
 }
@@ -209,6 +430,42 @@ fn generate_base_class() -> String {
         File getFile() {
             result = this.getLocation().getFile()
         }
+
+        /** Gets a node of the compiler's AST matching this node, if the project was built */
+        CompilerNode getACompilerNode() {
+            result.getAstNode() = this
+        }
+
+        /** Gets the declaration this name refers to, as resolved by the compiler */
+        AstNode getReferencedDeclaration() {
+            result = this.getACompilerNode().getReferencedDeclaration().getAstNode()
+        }
+
+        /** Gets the type of this expression or declaration, as the compiler prints it */
+        string getCompilerType() {
+            result = this.getACompilerNode().getTypeString()
+        }
+
+        /** Gets what this name is bound to (a call to an overloaded function may have several) */
+        BindingTarget getBinding() { solidity_binding(this, result) }
+
+        /** Gets a declaration that this declaration hides, in an enclosing scope or a base contract */
+        BindingTarget getShadowedDeclaration() { solidity_shadowing(this, result) }
+
+        /** Gets the scope this node introduces */
+        Scope getScope() { solidity_scope(result, _, this) }
+
+        /**
+         * Gets the type of this expression inferred from the syntax, as a
+         * canonical type string such as `uint256` or `contract IERC20`.
+         */
+        string getInferredType() { solidity_expr_type(this, result) }
+
+        /** Gets the base at `index` in the C3 linearization of this contract, which is itself at index 0 */
+        AstNode getLinearizedBase(int index) { solidity_contract_linearization(this, index, result) }
+
+        /** Gets a function or modifier this one overrides */
+        AstNode getAnOverriddenFunction() { solidity_function_override(this, result) }
     }
 
 "#
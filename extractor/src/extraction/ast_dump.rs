@@ -0,0 +1,405 @@
+//! Printing of syntax trees, for query authors.
+//!
+//! Each node is shown as the TRAP records it: its `_def` table, the kind id in
+//! `solidity_ast_node_info`, the field it is in within its parent (with its
+//! index in the field table), its index in `solidity_ast_node_parent`, its
+//! 1-based location and, for tokens, the text in `solidity_tokeninfo`. ERROR
+//! and MISSING nodes are shown, but marked: they are recorded as diagnostics,
+//! not nodes. The named children of an ERROR node are shown under it, though
+//! they are recorded as children of the node enclosing it (of a `source_file`
+//! node standing in for the root, if the root is an ERROR).
+//!
+//! Three forms are supported:
+//!
+//! ```text
+//! indented:  0 name[0]: solidity_identifier_def (identifier, kind 1) 1:10-1:11 "A"
+//! sexp:      (solidity_identifier_def @0 name[0]: (identifier, kind 1) 1:10-1:11 "A")
+//! json:      [{"id": 2, "parent": 1, "depth": 2, "table": "solidity_identifier_def", ...}]
+//! ```
+//!
+//! The leading number is the child index; `name[0]:` is the field and the
+//! index within it.
+//!
+//! The JSON form is a flat array of nodes in pre-order, linked by `parent`,
+//! so that arbitrarily deep trees can be printed (and processed with `jq`).
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use tree_sitter::{Node, Tree};
+
+use super::extractor::{def_table, field_table, new_parser, node_span};
+use super::source_text::LineIndex;
+
+/// Form of a printed syntax tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AstFormat {
+    /// One node per line, indented by depth (default)
+    #[default]
+    Indented,
+    /// S-expression, one node per line
+    Sexp,
+    /// JSON array of nodes in pre-order
+    Json,
+}
+
+impl FromStr for AstFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "indented" | "tree" => Ok(AstFormat::Indented),
+            "sexp" | "s-expression" => Ok(AstFormat::Sexp),
+            "json" => Ok(AstFormat::Json),
+            _ => Err(format!(
+                "Unknown AST format: {}. Valid values: indented, sexp, json",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AstFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AstFormat::Indented => write!(f, "indented"),
+            AstFormat::Sexp => write!(f, "sexp"),
+            AstFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// A node of a syntax tree, as recorded in TRAP.
+#[derive(Debug, Clone, Serialize)]
+pub struct AstNode {
+    /// Pre-order index of the node
+    pub id: usize,
+    /// Pre-order index of the parent (None for the root)
+    pub parent: Option<usize>,
+    /// Depth in the tree (0 for the root)
+    pub depth: usize,
+    /// `_def` table of the node (None for ERROR and MISSING nodes)
+    pub table: Option<String>,
+    /// Tree-sitter kind name
+    pub kind: String,
+    /// Kind id recorded in `solidity_ast_node_info`
+    pub kind_id: u16,
+    /// Index among the parent's children, as in `solidity_ast_node_parent`
+    pub child_index: Option<usize>,
+    /// Field the node is in within its parent
+    pub field: Option<String>,
+    /// Table recording the field (`solidity_<parent>_<field>`)
+    pub field_table: Option<String>,
+    /// Index within the field, as in the field table
+    pub field_index: Option<usize>,
+    /// Start position (1-based line and column)
+    pub start: (u32, u32),
+    /// End position (1-based line and column)
+    pub end: (u32, u32),
+    /// Text of a token, as in `solidity_tokeninfo`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Whether the node is an ERROR or MISSING node (not extracted)
+    pub error: bool,
+}
+
+/// Parse source code into the list of its nodes, in pre-order.
+///
+/// As when extracting, the stray tokens of an ERROR node are not recorded,
+/// so only its named children are listed.
+pub fn parse_ast(source: &str) -> Result<Vec<AstNode>> {
+    let tree = new_parser()?
+        .parse(source, None)
+        .context("Failed to parse source code")?;
+    Ok(collect_nodes(&tree, source))
+}
+
+/// Print the syntax tree of source code in the given form.
+pub fn dump_ast(source: &str, format: AstFormat, out: &mut impl Write) -> Result<()> {
+    let nodes = parse_ast(source)?;
+    match format {
+        AstFormat::Indented => {
+            for node in &nodes {
+                writeln!(out, "{}{}", "  ".repeat(node.depth), describe(node, false))?;
+            }
+        }
+        AstFormat::Sexp => {
+            for (i, node) in nodes.iter().enumerate() {
+                // Close this node and its ancestors that end here
+                let next_depth = nodes.get(i + 1).map_or(0, |next| next.depth);
+                let closing = if next_depth > node.depth {
+                    0
+                } else {
+                    node.depth - next_depth + 1
+                };
+                writeln!(
+                    out,
+                    "{}({}{}",
+                    "  ".repeat(node.depth),
+                    describe(node, true),
+                    ")".repeat(closing)
+                )?;
+            }
+        }
+        AstFormat::Json => {
+            writeln!(out, "{}", serde_json::to_string_pretty(&nodes)?)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Describe a node on one line.
+fn describe(node: &AstNode, sexp: bool) -> String {
+    let mut parts = Vec::new();
+    let table = node.table.as_deref().unwrap_or("ERROR");
+    let kind = if node.error {
+        format!("({}, not extracted)", node.kind)
+    } else {
+        format!("({}, kind {})", node.kind, node.kind_id)
+    };
+    if sexp {
+        parts.push(table.to_string());
+        if let Some(index) = node.child_index {
+            parts.push(format!("@{}", index));
+        }
+    } else if let Some(index) = node.child_index {
+        parts.push(index.to_string());
+    }
+    if let (Some(field), Some(index)) = (&node.field, node.field_index) {
+        parts.push(format!("{}[{}]:", field, index));
+    }
+    if !sexp {
+        parts.push(table.to_string());
+    }
+    parts.push(kind);
+    parts.push(format!(
+        "{}:{}-{}:{}",
+        node.start.0, node.start.1, node.end.0, node.end.1
+    ));
+    if let Some(text) = &node.text {
+        parts.push(format!("{:?}", text));
+    }
+    parts.join(" ")
+}
+
+/// A node whose children are being collected.
+struct OpenNode<'tree> {
+    /// Pre-order index of the node
+    id: usize,
+    node: Node<'tree>,
+    /// Index of the child being visited
+    child_index: usize,
+    /// Index in `solidity_ast_node_parent` of the next child recorded under
+    /// the node (None for an ERROR node other than the root, whose children
+    /// are recorded under the node enclosing it)
+    next_index: Option<usize>,
+    /// Number of children listed so far in each field
+    field_indices: HashMap<&'static str, usize>,
+}
+
+impl<'tree> OpenNode<'tree> {
+    fn new(id: usize, node: Node<'tree>, error: bool) -> Self {
+        OpenNode {
+            id,
+            node,
+            child_index: 0,
+            next_index: (!error).then_some(0),
+            field_indices: HashMap::new(),
+        }
+    }
+}
+
+/// Collect the nodes of a tree in pre-order, visiting them as `extract_tree` does.
+fn collect_nodes(tree: &Tree, source: &str) -> Vec<AstNode> {
+    let line_index = LineIndex::new(source);
+    let root = ast_node(&line_index, tree.root_node(), source, 0, None);
+    // A root ERROR is recorded as a `source_file`, with the recovered nodes in it
+    let mut stack = vec![OpenNode::new(0, tree.root_node(), false)];
+    let mut nodes = vec![root];
+    let mut cursor = tree.walk();
+    if !cursor.goto_first_child() {
+        return nodes;
+    }
+    loop {
+        let depth = stack.len();
+        let parent = stack.last_mut().expect("the root is open");
+        let in_error = parent.node.is_error();
+        let child = cursor.node();
+
+        // The stray tokens of a syntax error are not recorded
+        if child.is_named() || !in_error {
+            let mut node = ast_node(&line_index, child, source, depth, Some(parent.id));
+            let field = match in_error {
+                true => None,
+                false => parent.node.field_name_for_child(parent.child_index as u32),
+            };
+            if let Some(field) = field {
+                node.field = Some(field.to_string());
+                // ERROR and MISSING nodes are not recorded in field tables
+                if !node.error {
+                    let index = parent.field_indices.entry(field).or_insert(0);
+                    node.field_table = Some(field_table(&parent.node, field));
+                    node.field_index = Some(*index);
+                    *index += 1;
+                }
+            }
+            let enclosing = stack.iter_mut().rev().find_map(|o| o.next_index.as_mut());
+            if let Some(next_index) = enclosing {
+                node.child_index = Some(*next_index);
+                *next_index += 1;
+            }
+            let id = nodes.len();
+            node.id = id;
+            let descend = !child.is_missing();
+            nodes.push(node);
+
+            if descend && cursor.goto_first_child() {
+                stack.push(OpenNode::new(id, child, child.is_error()));
+                continue;
+            }
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                stack.last_mut().expect("the root is open").child_index += 1;
+                break;
+            }
+            if !cursor.goto_parent() || stack.len() == 1 {
+                return nodes;
+            }
+            stack.pop();
+        }
+    }
+}
+
+/// Describe a single node (but not its place in the tree).
+fn ast_node(
+    line_index: &LineIndex,
+    node: Node,
+    source: &str,
+    depth: usize,
+    parent: Option<usize>,
+) -> AstNode {
+    let error = node.is_error() || node.is_missing();
+    let (start, end) = node_span(line_index, &node);
+    AstNode {
+        id: 0,
+        parent,
+        depth,
+        table: (!error).then(|| def_table(&node)),
+        kind: node.kind().to_string(),
+        kind_id: node.kind_id(),
+        child_index: None,
+        field: None,
+        field_table: None,
+        field_index: None,
+        start,
+        end,
+        text: (!error && node.child_count() == 0)
+            .then(|| node.utf8_text(source.as_bytes()).unwrap_or("").to_string()),
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::Extractor;
+
+    const SOURCE: &str = "contract A {\n    function f(uint x) public { x = x + 1 }\n}\n";
+
+    #[test]
+    fn test_nodes_match_trap() {
+        let nodes = parse_ast(SOURCE).unwrap();
+        let mut extractor = Extractor::new("A.sol");
+        extractor.extract(SOURCE).unwrap();
+        let trap = extractor.trap_text();
+
+        // The same nodes, in the same order, with the same kind ids and locations
+        let extracted: Vec<&AstNode> = nodes.iter().filter(|n| !n.error).collect();
+        assert_eq!(extracted.len(), extractor.node_count());
+        let tables: Vec<&str> = trap
+            .lines()
+            .filter(|l| l.contains("_def(#"))
+            .map(|l| l.split('(').next().unwrap())
+            .collect();
+        let expected: Vec<&str> = extracted
+            .iter()
+            .map(|n| n.table.as_deref().unwrap())
+            .collect();
+        assert_eq!(tables, expected);
+        let tuple = |line: &str, table: &str| -> Option<Vec<String>> {
+            let values = line
+                .strip_prefix(table)?
+                .strip_prefix('(')?
+                .strip_suffix(')')?;
+            Some(values.split(", ").map(str::to_string).collect())
+        };
+        let coordinates: HashMap<String, String> = trap
+            .lines()
+            .filter_map(|l| tuple(l, "locations_default"))
+            .map(|values| (values[0].clone(), values[2..].join(", ")))
+            .collect();
+        let locations: Vec<&String> = trap
+            .lines()
+            .filter_map(|l| tuple(l, "solidity_ast_node_location"))
+            .map(|values| &coordinates[&values[1]])
+            .collect();
+        assert_eq!(locations.len(), extracted.len());
+        for (node, location) in extracted.iter().zip(locations) {
+            let (start, end) = (node.start, node.end);
+            assert_eq!(
+                location,
+                &format!("{}, {}, {}, {}", start.0, start.1, end.0, end.1)
+            );
+        }
+        for node in &extracted {
+            assert!(
+                trap.contains(&format!(", {})", node.kind_id)),
+                "{}",
+                node.kind
+            );
+            if let (Some(table), Some(index)) = (&node.field_table, node.field_index) {
+                assert!(trap.contains(&format!("{}(#", table)), "{}", table);
+                assert!(trap.contains(&format!(", {}, #", index)));
+            }
+        }
+
+        // Parents come before their children
+        assert!(nodes.iter().all(|n| n.parent.is_none_or(|p| p < n.id)));
+        assert_eq!(nodes[0].depth, 0);
+    }
+
+    #[test]
+    fn test_formats() {
+        let mut indented = Vec::new();
+        dump_ast(SOURCE, AstFormat::Indented, &mut indented).unwrap();
+        let indented = String::from_utf8(indented).unwrap();
+        let nodes = parse_ast(SOURCE).unwrap();
+        assert_eq!(indented.lines().count(), nodes.len());
+        assert!(indented.starts_with(nodes[0].table.as_deref().unwrap()));
+
+        // Parentheses balance
+        let mut sexp = Vec::new();
+        dump_ast(SOURCE, AstFormat::Sexp, &mut sexp).unwrap();
+        let sexp = String::from_utf8(sexp).unwrap();
+        let depth = sexp.chars().fold(0i64, |depth, c| match c {
+            '(' => depth + 1,
+            ')' => depth - 1,
+            _ => depth,
+        });
+        assert_eq!(depth, 0);
+
+        let mut json = Vec::new();
+        dump_ast(SOURCE, AstFormat::Json, &mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), nodes.len());
+        assert_eq!(json[1]["parent"], 0);
+
+        assert_eq!("SEXP".parse::<AstFormat>(), Ok(AstFormat::Sexp));
+        assert!("xml".parse::<AstFormat>().is_err());
+    }
+}
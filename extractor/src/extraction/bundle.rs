@@ -0,0 +1,440 @@
+//! Verified-source bundles of deployed contracts.
+//!
+//! The source of a deployed contract is usually obtained from a block explorer
+//! or from Sourcify and saved locally. Two kinds of bundle are read:
+//!
+//! - an Etherscan `getsourcecode` response saved as JSON (the whole response,
+//!   its `result` array or a single result). `SourceCode` holds a single file,
+//!   a map of sources, or a whole standard-JSON input wrapped in `{{ }}`;
+//! - a Sourcify contract directory, holding `metadata.json`, the sources below
+//!   `sources/` and optionally `constructor-args.txt`, as in Sourcify's
+//!   repository (`full_match/<chain id>/<address>/`).
+//!
+//! The sources are extracted below the bundle's file (a Sourcify directory's
+//! `metadata.json`), in a directory named after the contract's address (or the
+//! bundle, if the address is not known), so they collide neither with files on
+//! disk nor with the sources of other bundles. Like a standard-JSON input's, their
+//! imports are resolved among themselves; the compiler settings and the
+//! deployment (address, chain, constructor arguments) are recorded in the
+//! `solidity_compiler_*` and `solidity_deployed_contract*` tables.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use super::standard_json::{unit_path, StandardJsonInput};
+use crate::trap::{Label, TrapValue, TrapWriter};
+
+/// Name of Sourcify's metadata file.
+const SOURCIFY_METADATA: &str = "metadata.json";
+
+/// Directory of the sources in a Sourcify contract directory.
+const SOURCIFY_SOURCES: &str = "sources";
+
+/// File holding the constructor arguments in a Sourcify contract directory.
+const SOURCIFY_CONSTRUCTOR_ARGS: &str = "constructor-args.txt";
+
+/// A deployed contract whose verified source is in a bundle.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeployedContract {
+    /// Name of the contract (None = unknown)
+    pub name: Option<String>,
+    /// Address, `0x` followed by 40 hex digits (None = unknown)
+    pub address: Option<String>,
+    /// Chain id (None = unknown)
+    pub chain_id: Option<u64>,
+    /// ABI-encoded constructor arguments, in hex without `0x` (None = unknown)
+    pub constructor_args: Option<String>,
+}
+
+/// A verified-source bundle.
+#[derive(Debug, Clone)]
+pub struct VerifiedBundle {
+    /// Sources and compiler settings
+    pub input: StandardJsonInput,
+    /// The deployment
+    pub contract: DeployedContract,
+    /// Directory the sources are extracted below, relative to the bundle's
+    /// file (see `file`)
+    pub prefix: String,
+}
+
+impl VerifiedBundle {
+    /// Read a bundle: a Sourcify contract directory (or its `metadata.json`),
+    /// or a saved Etherscan response.
+    pub fn read(path: &Path) -> Result<Self> {
+        if path.is_dir() {
+            Self::read_sourcify(path)
+        } else if path
+            .file_name()
+            .is_some_and(|name| name == SOURCIFY_METADATA)
+        {
+            Self::read_sourcify(path.parent().unwrap_or(Path::new(".")))
+        } else {
+            Self::read_etherscan(path)
+        }
+    }
+
+    /// Get the file a bundle is read from: the `metadata.json` of a Sourcify
+    /// contract directory, or the path itself.
+    pub fn file(path: &Path) -> PathBuf {
+        if path.is_dir() {
+            path.join(SOURCIFY_METADATA)
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    /// Read a saved Etherscan `getsourcecode` response.
+    fn read_etherscan(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let value: Value = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid Etherscan response {}", path.display()))?;
+        let result = value.get("result").unwrap_or(&value);
+        let entry = match result {
+            Value::Array(entries) => entries.first().context("Empty Etherscan result")?,
+            entry => entry,
+        };
+        let field = |name: &str| {
+            entry
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+
+        let Some(source_code) = field("SourceCode") else {
+            bail!(
+                "No source code in {}: the contract is not verified",
+                path.display()
+            );
+        };
+        let name = field("ContractName");
+        let mut input = if let Some(inner) = source_code
+            .strip_prefix("{{")
+            .and_then(|s| s.strip_suffix("}}"))
+        {
+            // A whole standard-JSON input
+            StandardJsonInput::parse(&format!("{{{}}}", inner))?
+        } else if source_code.starts_with('{') {
+            // A map of sources, or (in some responses) a standard-JSON input
+            let value: Value = serde_json::from_str(source_code)?;
+            if value.get("sources").is_some() {
+                StandardJsonInput::from_value(value)?
+            } else {
+                StandardJsonInput::from_value(json!({ "sources": value }))?
+            }
+        } else {
+            StandardJsonInput::from_value(json!({
+                "sources": { format!("{}.sol", name.unwrap_or("Contract")): { "content": source_code } }
+            }))?
+        };
+
+        // Settings outside a standard-JSON input are in the response itself
+        let settings = &mut input.settings;
+        if settings.optimizer.is_none() {
+            if let Some(used) = field("OptimizationUsed") {
+                let runs = field("Runs").and_then(|runs| runs.parse().ok());
+                settings.optimizer = Some((used == "1", runs.unwrap_or(200)));
+            }
+        }
+        if settings.evm_version.is_none() {
+            settings.evm_version = field("EVMVersion")
+                .filter(|version| !version.eq_ignore_ascii_case("default"))
+                .map(str::to_string);
+        }
+        settings.compiler_version = field("CompilerVersion").map(normalize_version);
+
+        // Responses do not include the address, but files are usually named after it
+        let address = ["ContractAddress", "Address"]
+            .into_iter()
+            .find_map(field)
+            .or_else(|| path.file_stem().and_then(|stem| stem.to_str()))
+            .filter(|address| is_address(address))
+            .map(str::to_string);
+        let contract = DeployedContract {
+            name: name.map(str::to_string),
+            address,
+            chain_id: None,
+            constructor_args: field("ConstructorArguments").map(normalize_hex),
+        };
+        let prefix = bundle_prefix(&contract, path)?;
+        Ok(VerifiedBundle {
+            input,
+            contract,
+            prefix,
+        })
+    }
+
+    /// Read a Sourcify contract directory.
+    fn read_sourcify(dir: &Path) -> Result<Self> {
+        let metadata_path = dir.join(SOURCIFY_METADATA);
+        let text = fs::read_to_string(&metadata_path)
+            .with_context(|| format!("Failed to read {}", metadata_path.display()))?;
+        let metadata: Value = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid Sourcify metadata {}", metadata_path.display()))?;
+
+        // Sources are embedded in the metadata or saved below `sources/`
+        let mut sources = Map::new();
+        if let Some(entries) = metadata.get("sources").and_then(Value::as_object) {
+            for (unit, source) in entries {
+                let content = match source.get("content").and_then(Value::as_str) {
+                    Some(content) => Some(content.to_string()),
+                    None => unit_path(unit).and_then(|path| {
+                        fs::read_to_string(dir.join(SOURCIFY_SOURCES).join(path)).ok()
+                    }),
+                };
+                match content {
+                    Some(content) => {
+                        sources.insert(unit.clone(), json!({ "content": content }));
+                    }
+                    None => warn!("Source missing from {}: {}", dir.display(), unit),
+                }
+            }
+        }
+        let mut input = StandardJsonInput::from_value(json!({
+            "language": metadata.get("language").cloned().unwrap_or(json!("Solidity")),
+            "sources": sources,
+            "settings": metadata.get("settings").cloned().unwrap_or(json!({})),
+        }))?;
+        input.settings.compiler_version = metadata
+            .pointer("/compiler/version")
+            .and_then(Value::as_str)
+            .map(normalize_version);
+
+        // The compilation target maps the main source to the contract's name
+        let name = metadata
+            .pointer("/settings/compilationTarget")
+            .and_then(Value::as_object)
+            .and_then(|target| target.values().next())
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let dir_name = |dir: Option<&Path>| {
+            dir.and_then(Path::file_name)
+                .and_then(|name| name.to_str())
+                .map(str::to_string)
+        };
+        // Below the chain's directory only if named after the address
+        let address = dir_name(Some(dir)).filter(|name| is_address(name));
+        let chain_id = address
+            .as_ref()
+            .and_then(|_| dir_name(dir.parent()))
+            .and_then(|name| name.parse().ok());
+        let constructor_args = fs::read_to_string(dir.join(SOURCIFY_CONSTRUCTOR_ARGS))
+            .ok()
+            .map(|args| normalize_hex(args.trim()))
+            .filter(|args| !args.is_empty());
+        let contract = DeployedContract {
+            name,
+            address,
+            chain_id,
+            constructor_args,
+        };
+        let prefix = bundle_prefix(&contract, dir)?;
+        Ok(VerifiedBundle {
+            input,
+            contract,
+            prefix,
+        })
+    }
+}
+
+/// Get the directory a bundle's sources are extracted below: the contract's
+/// address, or the bundle's name.
+fn bundle_prefix(contract: &DeployedContract, path: &Path) -> Result<String> {
+    if let Some(address) = &contract.address {
+        return Ok(address.clone());
+    }
+    let name = path
+        .canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(unit_path);
+    name.with_context(|| format!("Cannot name the bundle {}", path.display()))
+}
+
+/// Check whether a string is a contract address.
+fn is_address(s: &str) -> bool {
+    s.len() == 42 && s.starts_with("0x") && s[2..].bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Normalize a compiler version to solc's form (`0.8.19+commit.7dd6d404`).
+fn normalize_version(version: &str) -> String {
+    version.trim().trim_start_matches('v').to_string()
+}
+
+/// Normalize hex data to lowercase without `0x`.
+fn normalize_hex(hex: &str) -> String {
+    hex.trim_start_matches("0x").to_lowercase()
+}
+
+/// Record the deployment of a contract compiled with the given settings.
+///
+/// `input` is the database path of the bundle.
+pub fn write_deployed_contract(
+    trap: &mut TrapWriter,
+    input: &str,
+    settings: &Label,
+    contract: &DeployedContract,
+) {
+    let label = trap.string_label(&format!("deployed_contract;{}", input));
+    trap.emit(
+        "solidity_deployed_contract",
+        vec![
+            TrapValue::Label(label.clone()),
+            TrapValue::String(contract.name.clone().unwrap_or_default()),
+            TrapValue::Label(settings.clone()),
+        ],
+    );
+    if let Some(address) = &contract.address {
+        trap.emit(
+            "solidity_deployed_contract_address",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::String(address.clone()),
+            ],
+        );
+    }
+    if let Some(chain_id) = contract.chain_id {
+        trap.emit(
+            "solidity_deployed_contract_chain",
+            vec![TrapValue::Label(label.clone()), TrapValue::UInt(chain_id)],
+        );
+    }
+    if let Some(args) = &contract.constructor_args {
+        trap.emit(
+            "solidity_deployed_contract_constructor_args",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::String(args.clone()),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchDir;
+
+    const ADDRESS: &str = "0x6b175474e89094c44da98b954eedeac495271d0f";
+
+    #[test]
+    fn test_read_etherscan() {
+        let dir = ScratchDir::new("etherscan");
+
+        // A single file, in a whole response named after the address
+        let single = dir.join(format!("{}.json", ADDRESS));
+        let response = json!({
+            "status": "1",
+            "result": [{
+                "SourceCode": "contract Dai {}",
+                "ContractName": "Dai",
+                "CompilerVersion": "v0.5.12+commit.7709ece9",
+                "OptimizationUsed": "1",
+                "Runs": "999",
+                "ConstructorArguments": "0000000000000000000000000000000000000000000000000000000000000001",
+                "EVMVersion": "Default"
+            }]
+        });
+        fs::write(&single, response.to_string()).unwrap();
+        let bundle = VerifiedBundle::read(&single).unwrap();
+        assert_eq!(bundle.prefix, ADDRESS);
+        assert_eq!(
+            bundle.input.sources,
+            vec![("Dai.sol".to_string(), "contract Dai {}".to_string())]
+        );
+        let settings = &bundle.input.settings;
+        assert_eq!(settings.optimizer, Some((true, 999)));
+        assert_eq!(settings.evm_version, None);
+        assert_eq!(
+            settings.compiler_version.as_deref(),
+            Some("0.5.12+commit.7709ece9")
+        );
+        assert_eq!(bundle.contract.address.as_deref(), Some(ADDRESS));
+        assert!(bundle.contract.constructor_args.unwrap().ends_with("01"));
+
+        // A standard-JSON input, in a single result
+        let wrapped = dir.join("token.json");
+        let input = json!({
+            "language": "Solidity",
+            "sources": { "src/Token.sol": { "content": "contract Token {}" } },
+            "settings": { "optimizer": { "enabled": false, "runs": 200 }, "evmVersion": "paris" }
+        });
+        let result = json!({
+            "SourceCode": format!("{{{}}}", input),
+            "ContractName": "Token",
+            "OptimizationUsed": "1",
+            "EVMVersion": "shanghai"
+        });
+        fs::write(&wrapped, result.to_string()).unwrap();
+        let bundle = VerifiedBundle::read(&wrapped).unwrap();
+        assert_eq!(bundle.prefix, "token");
+        assert_eq!(bundle.input.sources[0].0, "src/Token.sol");
+        // The input's own settings take precedence
+        assert_eq!(bundle.input.settings.optimizer, Some((false, 200)));
+        assert_eq!(bundle.input.settings.evm_version.as_deref(), Some("paris"));
+        assert_eq!(bundle.contract.address, None);
+
+        // Unverified contracts have no source
+        let unverified = dir.join("unverified.json");
+        fs::write(&unverified, r#"{"result": [{"SourceCode": ""}]}"#).unwrap();
+        assert!(VerifiedBundle::read(&unverified).is_err());
+    }
+
+    #[test]
+    fn test_read_sourcify() {
+        let root = ScratchDir::new("sourcify");
+        let dir = root.join("full_match/1").join(ADDRESS);
+        fs::create_dir_all(dir.join("sources/contracts")).unwrap();
+        fs::write(dir.join("sources/contracts/Dai.sol"), "contract Dai {}").unwrap();
+        fs::write(dir.join("constructor-args.txt"), "0xABCD\n").unwrap();
+        let metadata = json!({
+            "compiler": { "version": "0.8.19+commit.7dd6d404" },
+            "language": "Solidity",
+            "settings": {
+                "compilationTarget": { "contracts/Dai.sol": "Dai" },
+                "optimizer": { "enabled": true, "runs": 10000 },
+                "remappings": []
+            },
+            "sources": {
+                "contracts/Dai.sol": { "keccak256": "0x00" },
+                "contracts/Lib.sol": { "content": "library Lib {}" },
+                "contracts/Missing.sol": { "keccak256": "0x00" }
+            }
+        });
+        fs::write(dir.join("metadata.json"), metadata.to_string()).unwrap();
+
+        for path in [dir.clone(), dir.join("metadata.json")] {
+            let bundle = VerifiedBundle::read(&path).unwrap();
+            assert_eq!(bundle.prefix, ADDRESS);
+            assert_eq!(
+                bundle.input.sources,
+                vec![
+                    (
+                        "contracts/Dai.sol".to_string(),
+                        "contract Dai {}".to_string()
+                    ),
+                    (
+                        "contracts/Lib.sol".to_string(),
+                        "library Lib {}".to_string()
+                    ),
+                ]
+            );
+            assert_eq!(bundle.input.settings.optimizer, Some((true, 10000)));
+            assert_eq!(
+                bundle.contract,
+                DeployedContract {
+                    name: Some("Dai".to_string()),
+                    address: Some(ADDRESS.to_string()),
+                    chain_id: Some(1),
+                    constructor_args: Some("abcd".to_string()),
+                }
+            );
+        }
+    }
+}
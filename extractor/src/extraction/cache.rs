@@ -0,0 +1,372 @@
+//! Incremental extraction cache.
+//!
+//! TRAP files are stored in a cache directory under a key that covers
+//! everything their contents depend on: the source text, the path recorded
+//! for the file, its project, how its imports resolve, the build-info file
+//! its compiler AST comes from, the TRAP compression and its level, the
+//! extractor build and the grammar. When a later run computes the same
+//! key, the stored TRAP file is copied instead of parsing the file again, so a
+//! cached run produces a byte-identical database.
+//!
+//! Status-page diagnostics reported for a file are stored next to its TRAP
+//! file and reported again on a cache hit.
+//!
+//! The names each file declares and imports, which the files importing it
+//! are bound against, are cached too, under a key of the file's own inputs,
+//! so a run in which nothing changed parses no file at all.
+//!
+//! Entries are never evicted; delete the directory to reclaim space.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use tracing::debug;
+
+use super::diagnostics::Diagnostic;
+use super::names::FileSymbols;
+use crate::trap::Compression;
+
+/// Version of the cache layout; bump to invalidate existing caches.
+const CACHE_FORMAT_VERSION: &str = "1";
+
+/// A cache key: the hex SHA-256 of all inputs of a TRAP file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+    /// Start a key for the running extractor and grammar.
+    pub fn builder() -> CacheKeyBuilder {
+        let mut builder = CacheKeyBuilder {
+            hasher: Sha256::new(),
+        };
+        builder
+            .field("format", CACHE_FORMAT_VERSION)
+            .field("version", env!("CARGO_PKG_VERSION"))
+            .field("extractor", extractor_fingerprint())
+            .field("grammar", grammar_revision());
+        builder
+    }
+
+    /// Get the key as a hex string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Accumulates the inputs of a cache key.
+pub struct CacheKeyBuilder {
+    hasher: Sha256,
+}
+
+impl CacheKeyBuilder {
+    /// Add a named input to the key.
+    ///
+    /// Names and values are length-prefixed, so different inputs can never
+    /// produce the same byte stream.
+    pub fn field(&mut self, name: &str, value: impl AsRef<[u8]>) -> &mut Self {
+        let value = value.as_ref();
+        self.hasher.update((name.len() as u64).to_le_bytes());
+        self.hasher.update(name.as_bytes());
+        self.hasher.update((value.len() as u64).to_le_bytes());
+        self.hasher.update(value);
+        self
+    }
+
+    /// Add a named input to the key, given as a value's [`Hash`] (derived
+    /// over its fields in declaration order).
+    ///
+    /// The byte stream of a derived `Hash` can change with the compiler, but
+    /// so does the extractor binary, which every key covers.
+    pub fn hashed(&mut self, name: &str, value: &impl Hash) -> &mut Self {
+        let mut hasher = DigestHasher(Sha256::new());
+        value.hash(&mut hasher);
+        self.field(name, hasher.0.finalize())
+    }
+
+    /// Compute the key.
+    pub fn finish(&mut self) -> CacheKey {
+        CacheKey(hex(&std::mem::take(&mut self.hasher).finalize()))
+    }
+}
+
+/// Feeds the bytes of a [`Hash`] into a SHA-256 digest.
+struct DigestHasher(Sha256);
+
+impl Hasher for DigestHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    /// Not used: the digest is read from the SHA-256 state.
+    fn finish(&self) -> u64 {
+        0
+    }
+}
+
+/// A directory of TRAP files from previous runs.
+pub struct TrapCache {
+    /// Cache directory
+    dir: PathBuf,
+    /// Number of files whose TRAP was reused
+    hits: AtomicUsize,
+    /// Number of files that had to be extracted
+    misses: AtomicUsize,
+}
+
+impl TrapCache {
+    /// Open (creating if needed) a cache directory.
+    pub fn new(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create TRAP cache: {}", dir.display()))?;
+        Ok(TrapCache {
+            dir: dir.to_path_buf(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    /// Check whether there is an entry for `key`, without counting a hit or
+    /// miss.
+    pub fn contains(&self, key: &CacheKey, compression: Compression) -> bool {
+        let (cached_trap, cached_diagnostics) = self.entry_paths(key, compression);
+        cached_trap.is_file() && cached_diagnostics.is_file()
+    }
+
+    /// Copy the cached TRAP file for `key` to `trap_path`.
+    ///
+    /// Returns the diagnostics stored with it, or `None` (counted as a miss) if
+    /// there is no usable entry.
+    pub fn fetch(
+        &self,
+        key: &CacheKey,
+        compression: Compression,
+        trap_path: &Path,
+    ) -> Option<Vec<Diagnostic>> {
+        let (cached_trap, cached_diagnostics) = self.entry_paths(key, compression);
+        let result = fs::read_to_string(&cached_diagnostics)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Vec<Diagnostic>>(&json).ok())
+            .filter(|_| fs::copy(&cached_trap, trap_path).is_ok());
+
+        match result {
+            Some(diagnostics) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(
+                    diagnostics
+                        .into_iter()
+                        .map(Diagnostic::with_current_timestamp)
+                        .collect(),
+                )
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Store a freshly written TRAP file and its diagnostics under `key`.
+    ///
+    /// Failing to store an entry only costs a later cache miss, so errors are
+    /// logged rather than returned.
+    pub fn store(
+        &self,
+        key: &CacheKey,
+        compression: Compression,
+        trap_path: &Path,
+        diagnostics: &[Diagnostic],
+    ) {
+        let (cached_trap, cached_diagnostics) = self.entry_paths(key, compression);
+        let result = (|| -> Result<()> {
+            fs::create_dir_all(cached_trap.parent().expect("cache entry has a parent"))?;
+            // The diagnostics are written first: an entry is only used once its
+            // TRAP file exists, and both are renamed into place atomically
+            write_atomically(&cached_diagnostics, |tmp| {
+                fs::write(tmp, serde_json::to_string(diagnostics)?)?;
+                Ok(())
+            })?;
+            write_atomically(&cached_trap, |tmp| {
+                fs::copy(trap_path, tmp)?;
+                Ok(())
+            })
+        })();
+        if let Err(e) = result {
+            debug!("Failed to store TRAP cache entry {}: {:#}", key.as_str(), e);
+        }
+    }
+
+    /// Get the names of a file stored under `key`, if there are any.
+    pub fn fetch_symbols(&self, key: &CacheKey) -> Option<FileSymbols> {
+        let json = fs::read_to_string(self.symbols_path(key)).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Store the names of a file under `key`; as for TRAP files, errors are
+    /// only logged.
+    pub fn store_symbols(&self, key: &CacheKey, symbols: &FileSymbols) {
+        let path = self.symbols_path(key);
+        let result = (|| -> Result<()> {
+            fs::create_dir_all(path.parent().expect("cache entry has a parent"))?;
+            write_atomically(&path, |tmp| {
+                fs::write(tmp, serde_json::to_string(symbols)?)?;
+                Ok(())
+            })
+        })();
+        if let Err(e) = result {
+            debug!(
+                "Failed to store names cache entry {}: {:#}",
+                key.as_str(),
+                e
+            );
+        }
+    }
+
+    /// Get the number of cache hits so far.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of cache misses so far.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Get the path of a names entry.
+    fn symbols_path(&self, key: &CacheKey) -> PathBuf {
+        let key = key.as_str();
+        self.dir
+            .join(&key[..2])
+            .join(format!("{}.symbols.json", key))
+    }
+
+    /// Get the TRAP and diagnostics paths of an entry.
+    fn entry_paths(&self, key: &CacheKey, compression: Compression) -> (PathBuf, PathBuf) {
+        let key = key.as_str();
+        let dir = self.dir.join(&key[..2]);
+        (
+            dir.join(format!("{}{}", key, compression.extension())),
+            dir.join(format!("{}.json", key)),
+        )
+    }
+}
+
+/// Write a file through a temporary file and rename it into place, so that
+/// concurrent runs never see a partial entry.
+fn write_atomically(path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    let result = write(&tmp).and_then(|_| Ok(fs::rename(&tmp, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Fingerprint of the running extractor binary.
+///
+/// The package version does not change with every build, so the executable
+/// itself is hashed; if it cannot be read, only the version is used.
+fn extractor_fingerprint() -> &'static str {
+    static FINGERPRINT: OnceLock<String> = OnceLock::new();
+    FINGERPRINT.get_or_init(|| {
+        std::env::current_exe()
+            .and_then(fs::read)
+            .map(|binary| hex(&Sha256::digest(binary)))
+            .unwrap_or_default()
+    })
+}
+
+/// Revision of the tree-sitter grammar, as the hash of its node types.
+fn grammar_revision() -> &'static str {
+    static REVISION: OnceLock<String> = OnceLock::new();
+    REVISION.get_or_init(|| hex(&Sha256::digest(tree_sitter_solidity::NODE_TYPES)))
+}
+
+/// Render bytes as lowercase hex.
+pub(super) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::extractor::new_parser;
+    use crate::extraction::names::file_symbols;
+    use crate::test_support::ScratchDir;
+
+    #[test]
+    fn test_key_depends_on_every_field() {
+        let key = |path: &str, source: &str| {
+            CacheKey::builder()
+                .field("path", path)
+                .field("source", source)
+                .finish()
+        };
+        assert_eq!(key("a.sol", "x"), key("a.sol", "x"));
+        assert_ne!(key("a.sol", "x"), key("b.sol", "x"));
+        assert_ne!(key("a.sol", "x"), key("a.sol", "y"));
+        // Length prefixes keep field boundaries unambiguous
+        assert_ne!(key("a.sol", "xy"), key("a.solx", "y"));
+    }
+
+    #[test]
+    fn test_hashed_fields() {
+        let key =
+            |value: &(String, Option<usize>)| CacheKey::builder().hashed("value", value).finish();
+        let value = ("a.sol".to_string(), Some(1));
+        assert_eq!(key(&value), key(&value.clone()));
+        assert_ne!(key(&value), key(&("a.sol".to_string(), None)));
+        assert_ne!(key(&value), key(&("b.sol".to_string(), Some(1))));
+    }
+
+    #[test]
+    fn test_store_and_fetch() {
+        let dir = ScratchDir::new("roundtrip");
+        let cache = TrapCache::new(&dir.join("cache")).unwrap();
+        let key = CacheKey::builder()
+            .field("source", "contract A {}")
+            .finish();
+        let trap = dir.join("out.trap");
+        let restored = dir.join("restored.trap");
+
+        assert!(cache.fetch(&key, Compression::None, &restored).is_none());
+        assert!(!cache.contains(&key, Compression::None));
+
+        fs::write(&trap, "files(#1, \"A.sol\")\n").unwrap();
+        let diagnostic = Diagnostic::skipped_file(Path::new("A.sol"), "test");
+        cache.store(&key, Compression::None, &trap, &[diagnostic]);
+        assert!(cache.contains(&key, Compression::None));
+
+        let diagnostics = cache.fetch(&key, Compression::None, &restored).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].source.id, "solidity/skipped-file");
+        assert_eq!(fs::read(&trap).unwrap(), fs::read(&restored).unwrap());
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // Entries are per compression mode
+        assert!(cache
+            .fetch(&key, Compression::default(), &restored)
+            .is_none());
+    }
+
+    #[test]
+    fn test_store_and_fetch_symbols() {
+        let dir = ScratchDir::new("symbols");
+        let cache = TrapCache::new(&dir.join("cache")).unwrap();
+        let key = CacheKey::builder().field("path", "A.sol").finish();
+        assert!(cache.fetch_symbols(&key).is_none());
+
+        let source = "import \"./B.sol\";\ncontract A { function f(uint x) public {} }\n";
+        let tree = new_parser().unwrap().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree, source, "A.sol", |_| Some("B.sol".to_string()));
+        cache.store_symbols(&key, &symbols);
+
+        let restored = cache.fetch_symbols(&key).unwrap();
+        assert_eq!(format!("{:?}", restored), format!("{:?}", symbols));
+        // Names are not TRAP entries
+        assert_eq!((cache.hits(), cache.misses()), (0, 0));
+    }
+}
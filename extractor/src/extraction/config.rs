@@ -0,0 +1,406 @@
+//! Extractor configuration file.
+//!
+//! A `.codeql-solidity.yml` (or `.yaml`, or `.codeql-solidity.toml`) file at
+//! the source root configures which files are extracted and how:
+//!
+//! ```yaml
+//! include: ["contracts/**"]
+//! exclude: ["**/mocks/**", "*.t.sol"]
+//! gitignore: true
+//! remappings: ["@oz/=lib/openzeppelin-contracts/contracts/"]
+//! max_file_size: 1000000
+//! max_ast_nodes: 5000000
+//! parse_timeout: 60
+//! compression: none
+//! max_failure_ratio: 0.1
+//! ```
+//!
+//! Every setting except `compression` can also be given as an extractor option
+//! (`codeql database create -O solidity.exclude=...`), which takes precedence
+//! over the file; compression is the existing `trap.compression` option.
+//! Files must also pass the code scanning path filters (see `index_filters`).
+
+use anyhow::{bail, Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::info;
+
+use super::extractor::FileLimits;
+use super::imports::Remapping;
+use super::index_filters::IndexFilters;
+use super::source_root::path_to_string;
+use crate::trap::Compression;
+
+/// Configuration file names, in order of preference.
+pub const CONFIG_FILES: &[&str] = &[
+    ".codeql-solidity.yml",
+    ".codeql-solidity.yaml",
+    ".codeql-solidity.toml",
+];
+
+/// Prefix of the environment variables CodeQL sets for extractor options.
+const OPTION_PREFIX: &str = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_";
+
+/// Fraction of files that may fail before the run is aborted, by default.
+const DEFAULT_MAX_FAILURE_RATIO: f64 = 0.5;
+
+/// Settings as written in the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    gitignore: bool,
+    remappings: Vec<String>,
+    max_file_size: Option<u64>,
+    max_ast_nodes: Option<usize>,
+    parse_timeout: Option<u64>,
+    compression: Option<String>,
+    max_failure_ratio: Option<f64>,
+}
+
+impl RawConfig {
+    /// Override settings with the extractor options that are set.
+    fn apply_options(&mut self, option: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(value) = option("include") {
+            self.include = split_list(&value);
+        }
+        if let Some(value) = option("exclude") {
+            self.exclude = split_list(&value);
+        }
+        if let Some(value) = option("gitignore") {
+            self.gitignore = parse_option("gitignore", &value)?;
+        }
+        if let Some(value) = option("remappings") {
+            self.remappings = split_list(&value);
+        }
+        if let Some(value) = option("max_file_size") {
+            self.max_file_size = Some(parse_option("max_file_size", &value)?);
+        }
+        if let Some(value) = option("max_ast_nodes") {
+            self.max_ast_nodes = Some(parse_option("max_ast_nodes", &value)?);
+        }
+        if let Some(value) = option("parse_timeout") {
+            self.parse_timeout = Some(parse_option("parse_timeout", &value)?);
+        }
+        if let Some(value) = option("max_failure_ratio") {
+            self.max_failure_ratio = Some(parse_option("max_failure_ratio", &value)?);
+        }
+        Ok(())
+    }
+}
+
+/// Extractor settings for a source tree.
+#[derive(Debug, Clone)]
+pub struct ExtractorConfig {
+    /// Include and exclude globs
+    pub filter: FileFilter,
+    /// Path filters from the code scanning configuration
+    pub index_filters: IndexFilters,
+    /// Whether autobuild skips files matched by `.gitignore`
+    pub gitignore: bool,
+    /// Extra import remappings for every project
+    pub remappings: Vec<Remapping>,
+    /// Files larger than this many bytes are skipped (None = no limit)
+    pub max_file_size: Option<u64>,
+    /// Limits on the syntax tree size and parse time of each file
+    pub limits: FileLimits,
+    /// TRAP compression (None = the default)
+    pub compression: Option<Compression>,
+    /// Fraction of files that may fail to extract before the run is aborted
+    pub max_failure_ratio: f64,
+}
+
+impl Default for ExtractorConfig {
+    fn default() -> Self {
+        ExtractorConfig {
+            filter: FileFilter::default(),
+            index_filters: IndexFilters::default(),
+            gitignore: false,
+            remappings: Vec::new(),
+            max_file_size: None,
+            limits: FileLimits::default(),
+            compression: None,
+            max_failure_ratio: DEFAULT_MAX_FAILURE_RATIO,
+        }
+    }
+}
+
+impl ExtractorConfig {
+    /// Load the configuration of a source root, including extractor options
+    /// set in the environment.
+    pub fn load(source_root: &Path) -> Result<Self> {
+        let source_root = &source_root
+            .canonicalize()
+            .unwrap_or_else(|_| source_root.to_path_buf());
+        let mut raw = RawConfig::default();
+        for name in CONFIG_FILES {
+            let path = source_root.join(name);
+            if path.is_file() {
+                info!("Using configuration file: {}", path.display());
+                let text = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                raw = parse(&text, name.ends_with(".toml"))
+                    .with_context(|| format!("Failed to parse {}", path.display()))?;
+                break;
+            }
+        }
+        raw.apply_options(|name| {
+            std::env::var(format!("{}{}", OPTION_PREFIX, name.to_uppercase()))
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        })?;
+        let mut config = Self::from_raw(raw, source_root)?;
+        config.index_filters = IndexFilters::from_env();
+        Ok(config)
+    }
+
+    /// Check whether a file (its path relative to the source root) is selected
+    /// by both the configured globs and the code scanning path filters.
+    pub fn selects(&self, relative: &str) -> bool {
+        self.filter.selects(relative) && self.index_filters.selects(relative)
+    }
+
+    /// Validate raw settings.
+    ///
+    /// Remapping targets are relative to the source root, so they are made
+    /// absolute here; their contexts stay relative to each project.
+    fn from_raw(raw: RawConfig, source_root: &Path) -> Result<Self> {
+        let remappings = raw
+            .remappings
+            .iter()
+            .map(|s| {
+                let mut remapping: Remapping = s.parse()?;
+                if !Path::new(&remapping.target).is_absolute() {
+                    remapping.target = format!(
+                        "{}/{}",
+                        path_to_string(source_root),
+                        remapping.target.trim_start_matches("./")
+                    );
+                }
+                Ok(remapping)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let compression = raw
+            .compression
+            .map(|s| s.parse::<Compression>().map_err(anyhow::Error::msg))
+            .transpose()?;
+
+        let max_failure_ratio = raw.max_failure_ratio.unwrap_or(DEFAULT_MAX_FAILURE_RATIO);
+        if !(0.0..=1.0).contains(&max_failure_ratio) {
+            bail!(
+                "max_failure_ratio must be between 0 and 1, got {}",
+                max_failure_ratio
+            );
+        }
+
+        Ok(ExtractorConfig {
+            filter: FileFilter::new(&raw.include, &raw.exclude)?,
+            index_filters: IndexFilters::default(),
+            gitignore: raw.gitignore,
+            remappings,
+            max_file_size: raw.max_file_size,
+            limits: FileLimits {
+                max_ast_nodes: raw.max_ast_nodes,
+                parse_timeout: raw.parse_timeout.map(Duration::from_secs),
+            },
+            compression,
+            max_failure_ratio,
+        })
+    }
+}
+
+/// Selects files by path with include and exclude globs.
+///
+/// Globs are matched against paths relative to the source root. `*` does not
+/// match `/` but `**` does; a glob without a `/` matches at any depth, and a
+/// glob matching a directory also matches everything in it.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    /// Files must match one of these (None = every file is included)
+    include: Option<GlobSet>,
+    /// Files must match none of these
+    exclude: GlobSet,
+}
+
+impl FileFilter {
+    /// Create a filter from include and exclude globs.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(FileFilter {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(glob_set(include)?)
+            },
+            exclude: glob_set(exclude)?,
+        })
+    }
+
+    /// Check whether a file (its path relative to the source root) is selected.
+    pub fn selects(&self, relative: &str) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.is_match(relative))
+            && !self.exclude.is_match(relative)
+    }
+}
+
+/// Compile globs into a set, following the rules described on [`FileFilter`].
+fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let trimmed = pattern
+            .trim()
+            .trim_start_matches("./")
+            .trim_end_matches('/');
+        let anchored = match trimmed.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if trimmed.contains('/') => trimmed.to_string(),
+            None => format!("**/{}", trimmed),
+        };
+        for glob in [anchored.clone(), format!("{}/**", anchored)] {
+            builder.add(
+                GlobBuilder::new(&glob)
+                    .literal_separator(true)
+                    .build()
+                    .with_context(|| format!("Invalid glob '{}'", pattern))?,
+            );
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Parse the value of an extractor option.
+fn parse_option<T: FromStr>(name: &str, value: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid {} option '{}'", name, value))
+}
+
+/// Parse the text of a configuration file.
+fn parse(text: &str, is_toml: bool) -> Result<RawConfig> {
+    if is_toml {
+        Ok(toml::from_str(text)?)
+    } else if text.trim().is_empty() {
+        // An empty YAML document is null rather than an empty mapping
+        Ok(RawConfig::default())
+    } else {
+        Ok(serde_yaml_ng::from_str(text)?)
+    }
+}
+
+/// Split a list given as an option value, separated by commas or newlines.
+///
+/// Commas inside `{...}` belong to a glob alternation and do not separate.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    for c in value.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' | '\n' if depth == 0 => {
+                items.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    items.push(current);
+    items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_yaml_and_toml() {
+        let yaml = parse(
+            "include: [contracts/**]\ngitignore: true\nmax_file_size: 1000\ncompression: none\n",
+            false,
+        )
+        .unwrap();
+        let toml = parse(
+            "include = ['contracts/**']\ngitignore = true\nmax_file_size = 1000\ncompression = 'none'\n",
+            true,
+        )
+        .unwrap();
+        for raw in [yaml, toml] {
+            let config = ExtractorConfig::from_raw(raw, Path::new("/src")).unwrap();
+            assert!(config.gitignore);
+            assert_eq!(config.max_file_size, Some(1000));
+            assert_eq!(config.compression, Some(Compression::None));
+            assert!(config.filter.selects("contracts/Token.sol"));
+            assert!(!config.filter.selects("test/Token.t.sol"));
+        }
+        assert!(parse("gitignor: true", false).is_err());
+        assert!(parse("", false).is_ok());
+    }
+
+    #[test]
+    fn test_options_override_file() {
+        let mut raw = parse(
+            "exclude: [a]\nmax_failure_ratio: 0.2\nparse_timeout: 5\n",
+            false,
+        )
+        .unwrap();
+        raw.apply_options(|name| match name {
+            "exclude" => Some("**/mocks/**, *.{t,s}.sol".to_string()),
+            "max_ast_nodes" => Some("100000".to_string()),
+            "remappings" => Some("@oz/=lib/oz/\nctx:ds/=/abs/ds/".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(raw.exclude, vec!["**/mocks/**", "*.{t,s}.sol"]);
+
+        let config = ExtractorConfig::from_raw(raw, Path::new("/src")).unwrap();
+        assert_eq!(config.max_failure_ratio, 0.2);
+        assert_eq!(config.limits.max_ast_nodes, Some(100000));
+        assert_eq!(config.limits.parse_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.remappings[0].target, "/src/lib/oz/");
+        assert_eq!(config.remappings[1].context, "ctx");
+        assert_eq!(config.remappings[1].target, "/abs/ds/");
+
+        let mut raw = RawConfig::default();
+        assert!(raw
+            .apply_options(|name| (name == "max_file_size").then(|| "big".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_file_filter() {
+        let filter = FileFilter::new(
+            &["contracts".to_string(), "/lib/oz/**/*.sol".to_string()],
+            &["mocks".to_string(), "*.t.sol".to_string()],
+        )
+        .unwrap();
+        assert!(filter.selects("contracts/Token.sol"));
+        assert!(filter.selects("contracts/sub/Token.sol"));
+        assert!(filter.selects("lib/oz/token/ERC20.sol"));
+        assert!(!filter.selects("src/Token.sol"));
+        assert!(!filter.selects("contracts/mocks/Mock.sol"));
+        assert!(!filter.selects("contracts/Token.t.sol"));
+
+        // `*` stays within a directory
+        let filter = FileFilter::new(&["src/*.sol".to_string()], &[]).unwrap();
+        assert!(filter.selects("src/A.sol"));
+        assert!(!filter.selects("src/sub/A.sol"));
+        assert!(FileFilter::default().selects("anything.sol"));
+    }
+}
@@ -0,0 +1,501 @@
+//! Status-page diagnostics for the CodeQL CLI.
+//!
+//! The CodeQL CLI collects JSON diagnostic messages from the directory named by
+//! `CODEQL_EXTRACTOR_SOLIDITY_DIAGNOSTIC_DIR` and shows them on the code scanning
+//! tool status page and in the CLI summary table. Each message is written as one
+//! line of a `.jsonl` file owned by this process.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Environment variable naming the directory diagnostics are written to.
+const DIAGNOSTIC_DIR_ENV: &str = "CODEQL_EXTRACTOR_SOLIDITY_DIAGNOSTIC_DIR";
+
+/// Number of examples named in a message covering several occurrences.
+const MAX_EXAMPLES: usize = 3;
+
+/// Severity of a diagnostic message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Something went wrong and extraction is incomplete
+    Error,
+    /// Something went wrong but extraction could continue
+    Warning,
+    /// Informational message
+    Note,
+}
+
+/// Where a diagnostic message is shown.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Visibility {
+    /// Show on the code scanning tool status page
+    pub status_page: bool,
+    /// Show in the summary table printed by the CLI
+    pub cli_summary_table: bool,
+    /// Include in telemetry
+    pub telemetry: bool,
+}
+
+impl Visibility {
+    /// Visible everywhere.
+    pub fn all() -> Self {
+        Visibility {
+            status_page: true,
+            cli_summary_table: true,
+            telemetry: true,
+        }
+    }
+}
+
+/// The component that produced a diagnostic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticSource {
+    /// Stable identifier, e.g. `solidity/parse-error`
+    pub id: String,
+    /// Human-readable name of the kind of diagnostic
+    pub name: String,
+    /// Name of the extractor
+    pub extractor_name: String,
+}
+
+/// A source range a diagnostic refers to (1-based, inclusive).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticLocation {
+    /// Path of the file
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_column: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<u32>,
+}
+
+impl DiagnosticLocation {
+    /// A location covering a whole file.
+    pub fn file(path: &Path) -> Self {
+        DiagnosticLocation {
+            file: path.to_string_lossy().to_string(),
+            start_line: None,
+            start_column: None,
+            end_line: None,
+            end_column: None,
+        }
+    }
+
+    /// A location covering a range within a file.
+    pub fn range(path: &Path, start: (u32, u32), end: (u32, u32)) -> Self {
+        DiagnosticLocation {
+            file: path.to_string_lossy().to_string(),
+            start_line: Some(start.0),
+            start_column: Some(start.1),
+            end_line: Some(end.0),
+            end_column: Some(end.1),
+        }
+    }
+}
+
+/// A diagnostic message in the format read by the CodeQL CLI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    /// RFC 3339 timestamp
+    pub timestamp: String,
+    /// What produced the message
+    pub source: DiagnosticSource,
+    /// Message body (GitHub-flavoured markdown)
+    pub markdown_message: String,
+    /// Severity
+    pub severity: Severity,
+    /// Where the message is shown
+    pub visibility: Visibility,
+    /// Source location, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<DiagnosticLocation>,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic with the given source id, name, severity and message.
+    pub fn new(id: &str, name: &str, severity: Severity, markdown_message: String) -> Self {
+        Diagnostic {
+            timestamp: format_timestamp(SystemTime::now()),
+            source: DiagnosticSource {
+                id: format!("solidity/{}", id),
+                name: name.to_string(),
+                extractor_name: "solidity".to_string(),
+            },
+            markdown_message,
+            severity,
+            visibility: Visibility::all(),
+            location: None,
+        }
+    }
+
+    /// Set the timestamp to the current time, for a message reported again.
+    pub fn with_current_timestamp(mut self) -> Self {
+        self.timestamp = format_timestamp(SystemTime::now());
+        self
+    }
+
+    /// Attach a location to this diagnostic.
+    pub fn with_location(mut self, location: DiagnosticLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    /// A source file could not be read.
+    pub fn read_failure(file: &Path, error: &str) -> Self {
+        Diagnostic::new(
+            "read-failure",
+            "Could not read a Solidity file",
+            Severity::Warning,
+            format!(
+                "Could not read `{}`: {}. The file was not included in the database.",
+                file.display(),
+                error
+            ),
+        )
+        .with_location(DiagnosticLocation::file(file))
+    }
+
+    /// A source file could not be parsed or extracted at all.
+    pub fn extraction_failure(file: &Path, error: &str) -> Self {
+        Diagnostic::new(
+            "extraction-failure",
+            "Could not extract a Solidity file",
+            Severity::Warning,
+            format!(
+                "Could not extract `{}`: {}. The file was not included in the database.",
+                file.display(),
+                error
+            ),
+        )
+        .with_location(DiagnosticLocation::file(file))
+    }
+
+    /// A source file could not be copied into the source archive.
+    pub fn archive_failure(file: &Path, error: &str) -> Self {
+        Diagnostic::new(
+            "archive-failure",
+            "Could not archive a Solidity file",
+            Severity::Warning,
+            format!(
+                "Could not copy `{}` into the source archive: {}. Results in this file cannot be displayed.",
+                file.display(),
+                error
+            ),
+        )
+        .with_location(DiagnosticLocation::file(file))
+    }
+
+    /// A source file was extracted, but contained syntax errors.
+    ///
+    /// `first` is the location of the first error.
+    pub fn parse_errors(file: &Path, count: usize, first: DiagnosticLocation) -> Self {
+        Diagnostic::new(
+            "parse-error",
+            "Could not parse a Solidity file",
+            Severity::Warning,
+            format!(
+                "Found {} syntax error(s) in `{}`. Code containing syntax errors was skipped, \
+                 so analysis results for this file may be incomplete.",
+                count,
+                file.display()
+            ),
+        )
+        .with_location(first)
+    }
+
+    /// Import directives in a file did not resolve to files.
+    ///
+    /// One message covers all of the file's unresolved imports (`imports`, in
+    /// order), naming the first few, so that a project whose dependencies are
+    /// missing does not flood the status page. `first` is the location of the
+    /// first one.
+    pub fn unresolved_imports(file: &Path, imports: &[&str], first: DiagnosticLocation) -> Self {
+        let mut examples: Vec<String> = imports
+            .iter()
+            .take(MAX_EXAMPLES)
+            .map(|import| format!("`{}`", import))
+            .collect();
+        if imports.len() > MAX_EXAMPLES {
+            examples.push(format!("{} more", imports.len() - MAX_EXAMPLES));
+        }
+        Diagnostic::new(
+            "unresolved-import",
+            "Could not resolve a Solidity import",
+            Severity::Warning,
+            format!(
+                "Could not resolve {} import(s) in `{}` ({}). Check the import remappings \
+                 (`remappings.txt`, `foundry.toml`) and that dependencies are installed; \
+                 uses of the imported declarations may not be resolved.",
+                imports.len(),
+                file.display(),
+                examples.join(", ")
+            ),
+        )
+        .with_location(first)
+    }
+
+    /// Names in a file are bound to declarations in imported files
+    /// (`files`, in order) that failed to extract, so those bindings are not
+    /// in the database.
+    pub fn unresolved_bindings(file: &Path, files: &[&str]) -> Self {
+        let mut examples: Vec<String> = files
+            .iter()
+            .take(MAX_EXAMPLES)
+            .map(|file| format!("`{}`", file))
+            .collect();
+        if files.len() > MAX_EXAMPLES {
+            examples.push(format!("{} more", files.len() - MAX_EXAMPLES));
+        }
+        Diagnostic::new(
+            "unresolved-binding",
+            "Could not resolve names declared in a failed Solidity file",
+            Severity::Warning,
+            format!(
+                "Names in `{}` refer to declarations in {} imported file(s) that failed to \
+                 extract ({}). Uses of those declarations are not resolved.",
+                file.display(),
+                files.len(),
+                examples.join(", ")
+            ),
+        )
+        .with_location(DiagnosticLocation::file(file))
+    }
+
+    /// The base contracts of a contract could not be linearized.
+    pub fn linearization_failure(
+        file: &Path,
+        contract: &str,
+        location: DiagnosticLocation,
+    ) -> Self {
+        Diagnostic::new(
+            "linearization-failure",
+            "Could not linearize the bases of a Solidity contract",
+            Severity::Warning,
+            format!(
+                "Could not linearize the base contracts of `{}` in `{}`: the order they \
+                 are listed in conflicts with that of their own bases, or the contract \
+                 inherits from itself. Calls to inherited functions may not be resolved \
+                 correctly.",
+                contract,
+                file.display()
+            ),
+        )
+        .with_location(location)
+    }
+
+    /// A source file was not valid UTF-8 and was decoded lossily.
+    pub fn invalid_encoding(file: &Path, invalid_sequences: usize) -> Self {
+        Diagnostic::new(
+            "invalid-encoding",
+            "A Solidity file is not valid UTF-8",
+            Severity::Warning,
+            format!(
+                "`{}` contains {} invalid UTF-8 sequence(s), which were replaced with U+FFFD. \
+                 The file was extracted, but string literals and comments containing them \
+                 may not match the original bytes.",
+                file.display(),
+                invalid_sequences
+            ),
+        )
+        .with_location(DiagnosticLocation::file(file))
+    }
+
+    /// A file was not extracted on purpose.
+    pub fn skipped_file(file: &Path, reason: &str) -> Self {
+        Diagnostic::new(
+            "skipped-file",
+            "Skipped a Solidity file",
+            Severity::Note,
+            format!("Skipped `{}`: {}.", file.display(), reason),
+        )
+        .with_location(DiagnosticLocation::file(file))
+    }
+
+    /// Summary written when some (but not all) files failed to extract.
+    pub fn partial_failure(failed: usize, total: usize) -> Self {
+        Diagnostic::new(
+            "partial-failure",
+            "Some Solidity files could not be extracted",
+            Severity::Warning,
+            format!(
+                "{} of {} Solidity files could not be extracted. \
+                 Analysis results are incomplete for the affected files.",
+                failed, total
+            ),
+        )
+    }
+
+    /// Summary written when extraction was aborted because too many files failed.
+    pub fn extraction_aborted(failed: usize, total: usize) -> Self {
+        Diagnostic::new(
+            "extraction-aborted",
+            "Solidity extraction failed",
+            Severity::Error,
+            format!(
+                "{} of {} Solidity files could not be extracted, so extraction was aborted. \
+                 Check the other diagnostics for the individual failures.",
+                failed, total
+            ),
+        )
+    }
+}
+
+/// Writes diagnostics as JSON lines into the CodeQL diagnostic directory.
+///
+/// When no diagnostic directory is configured, messages are dropped; they are
+/// always logged by the caller anyway.
+#[derive(Default)]
+pub struct DiagnosticsWriter {
+    /// Output file, if a diagnostic directory is configured
+    output: Option<Mutex<BufWriter<File>>>,
+}
+
+impl DiagnosticsWriter {
+    /// Create a writer for the directory named by `CODEQL_EXTRACTOR_SOLIDITY_DIAGNOSTIC_DIR`.
+    pub fn from_env() -> Self {
+        match std::env::var_os(DIAGNOSTIC_DIR_ENV) {
+            Some(dir) if !dir.is_empty() => Self::new(Path::new(&dir)),
+            _ => Self::default(),
+        }
+    }
+
+    /// Create a writer for the given directory.
+    pub fn new(dir: &Path) -> Self {
+        let path = diagnostics_file_path(dir);
+        let output = fs::create_dir_all(dir).and_then(|_| File::create(&path));
+        match output {
+            Ok(file) => DiagnosticsWriter {
+                output: Some(Mutex::new(BufWriter::new(file))),
+            },
+            Err(e) => {
+                warn!(
+                    "Failed to create diagnostics file {}: {}",
+                    path.display(),
+                    e
+                );
+                Self::default()
+            }
+        }
+    }
+
+    /// Write a diagnostic message.
+    pub fn write(&self, diagnostic: &Diagnostic) {
+        let Some(output) = &self.output else {
+            return;
+        };
+        let Ok(line) = serde_json::to_string(diagnostic) else {
+            return;
+        };
+        let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(output, "{}", line).and_then(|_| output.flush()) {
+            warn!("Failed to write diagnostic: {}", e);
+        }
+    }
+}
+
+/// Compute a diagnostics file name that is unique to this process.
+fn diagnostics_file_path(dir: &Path) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    dir.join(format!(
+        "solidity-extractor-{}-{}.jsonl",
+        std::process::id(),
+        nanos
+    ))
+}
+
+/// Format a time as an RFC 3339 UTC timestamp with millisecond precision.
+fn format_timestamp(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = duration.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Convert days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60,
+        duration.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(format_timestamp(time), "2024-02-29T12:34:56.789Z");
+    }
+
+    #[test]
+    fn test_diagnostic_json_format() {
+        let diagnostic = Diagnostic::parse_errors(
+            Path::new("contracts/Token.sol"),
+            2,
+            DiagnosticLocation::range(Path::new("contracts/Token.sol"), (3, 5), (3, 9)),
+        );
+        let json: serde_json::Value = serde_json::to_value(&diagnostic).unwrap();
+
+        assert_eq!(json["source"]["id"], "solidity/parse-error");
+        assert_eq!(json["source"]["extractorName"], "solidity");
+        assert_eq!(json["severity"], "warning");
+        assert_eq!(json["visibility"]["statusPage"], true);
+        assert_eq!(json["location"]["file"], "contracts/Token.sol");
+        assert_eq!(json["location"]["startLine"], 3);
+        assert!(json["markdownMessage"]
+            .as_str()
+            .unwrap()
+            .contains("`contracts/Token.sol`"));
+    }
+
+    #[test]
+    fn test_unresolved_imports_are_summarized() {
+        let file = Path::new("src/Token.sol");
+        let imports = ["a.sol", "b.sol", "c.sol", "d.sol", "e.sol"];
+        let diagnostic =
+            Diagnostic::unresolved_imports(file, &imports, DiagnosticLocation::file(file));
+        assert!(diagnostic.markdown_message.contains(
+            "Could not resolve 5 import(s) in `src/Token.sol` (`a.sol`, `b.sol`, `c.sol`, 2 more)"
+        ));
+    }
+
+    #[test]
+    fn test_file_location_omits_range() {
+        let json = serde_json::to_value(DiagnosticLocation::file(Path::new("A.sol"))).unwrap();
+        assert_eq!(json, serde_json::json!({ "file": "A.sol" }));
+    }
+}
@@ -4,9 +4,18 @@
 //! relational tuples for each node.
 
 use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::path::Path;
-use tree_sitter::{Node, Parser, Tree};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tree_sitter::{Node, ParseOptions, Parser, Tree};
 
+use super::names::{
+    declaration_key, file_symbols, needs_label, write_names, SymbolIndex, GLOBAL_DECLARATION_KINDS,
+};
+use super::solc_ast::{write_compiler_nodes, CompiledSource};
+use super::source_text::LineIndex;
 use crate::trap::{Compression, Label, TrapValue, TrapWriter};
 
 /// Extractor for a single Solidity file.
@@ -17,31 +26,220 @@ pub struct Extractor {
     trap: TrapWriter,
     /// File label in the database
     file_label: Option<Label>,
+    /// Syntax errors (ERROR and MISSING nodes) found while extracting
+    parse_errors: Vec<ParseError>,
+    /// Import directives found while extracting
+    imports: Vec<Import>,
+    /// Line starts of the source being extracted
+    line_index: LineIndex,
+    /// Limits on the work done for the file
+    limits: FileLimits,
+    /// Time spent parsing
+    parse_time: Duration,
+    /// Number of syntax tree nodes extracted
+    node_count: usize,
+    /// Labels of named nodes by byte range, the innermost for a shared range
+    /// (None = not recorded, as no compiler AST is matched)
+    node_spans: Option<HashMap<(usize, usize), Label>>,
+    /// Labels of the nodes name resolution refers to, by node id
+    node_labels: HashMap<usize, Label>,
+    /// The syntax tree extracted, until its names are resolved
+    tree: Option<Tree>,
+    /// Contracts whose bases could not be linearized
+    linearization_failures: Vec<LinearizationFailure>,
+}
+
+/// Limits on the work done for a single file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileLimits {
+    /// Files whose syntax tree (tokens included) has more nodes are skipped
+    /// (None = no limit)
+    pub max_ast_nodes: Option<usize>,
+    /// Parsing is abandoned after this long (None = no limit)
+    pub parse_timeout: Option<Duration>,
+}
+
+/// A parsed source file, to be extracted with [`Extractor::extract_parsed`].
+pub struct ParsedSource {
+    /// The syntax tree
+    tree: Tree,
+    /// Time spent parsing
+    parse_time: Duration,
+}
+
+impl ParsedSource {
+    /// Parse source code within the given limits.
+    pub fn parse(source: &str, limits: &FileLimits) -> Result<Self> {
+        let mut parser = new_parser()?;
+        let start = Instant::now();
+        let tree = parse_source(&mut parser, source, limits)?;
+        Ok(ParsedSource {
+            tree,
+            parse_time: start.elapsed(),
+        })
+    }
+
+    /// Get the syntax tree.
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+}
+
+/// A file exceeded one of its [`FileLimits`], so it was not extracted.
+#[derive(Debug, Clone, Error)]
+pub enum LimitExceeded {
+    /// Parsing took longer than the parse timeout
+    #[error("parsing took longer than the parse timeout ({0:?})")]
+    ParseTimeout(Duration),
+    /// The file is larger than allowed
+    #[error("it is larger than the maximum file size ({0} bytes)")]
+    FileSize(u64),
+    /// The syntax tree has more nodes than allowed
+    #[error("its syntax tree has more than the maximum number of nodes ({0})")]
+    TooManyNodes(usize),
+}
+
+/// A node whose children are being extracted.
+struct OpenNode<'tree> {
+    /// The node
+    node: Node<'tree>,
+    /// Label of the node (None = a syntax error, whose children are
+    /// extracted as children of the enclosing node)
+    label: Option<Label>,
+    /// Name of the field the node is in within its parent
+    field_name: Option<&'static str>,
+    /// Index of the child being extracted
+    child_index: usize,
+    /// Index in the parent table of the next child extracted under the node,
+    /// counting those recovered from syntax errors within it
+    next_index: usize,
+    /// Number of children extracted so far in each field
+    field_indices: HashMap<String, usize>,
+}
+
+impl<'tree> OpenNode<'tree> {
+    fn new(node: Node<'tree>, label: Option<Label>, field_name: Option<&'static str>) -> Self {
+        OpenNode {
+            node,
+            label,
+            field_name,
+            child_index: 0,
+            next_index: 0,
+            field_indices: HashMap::new(),
+        }
+    }
+}
+
+/// Get the label of the innermost extracted node of `stack`, and the index
+/// of its next child.
+fn next_child(stack: &mut [OpenNode]) -> Option<(Label, usize)> {
+    let enclosing = stack.iter_mut().rev().find(|open| open.label.is_some())?;
+    let index = enclosing.next_index;
+    enclosing.next_index += 1;
+    Some((enclosing.label.clone()?, index))
+}
+
+/// A syntax error found while extracting a file.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    /// Short description of the error
+    pub message: String,
+    /// Start position (1-based line and column)
+    pub start: (u32, u32),
+    /// End position (1-based line and column)
+    pub end: (u32, u32),
+}
+
+/// An import directive found while extracting a file.
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// Imported path, as written in the source
+    pub path: String,
+    /// Database path of the imported file, once resolved
+    pub resolved: Option<String>,
+    /// Start position (1-based line and column)
+    pub start: (u32, u32),
+    /// End position (1-based line and column)
+    pub end: (u32, u32),
+    /// Label of the `import_directive` node
+    label: Label,
+    /// Label of the directive's location
+    location: Label,
+}
+
+/// A contract whose bases could not be linearized.
+#[derive(Debug, Clone)]
+pub struct LinearizationFailure {
+    /// Name of the contract
+    pub contract: String,
+    /// Start position (1-based line and column)
+    pub start: (u32, u32),
+    /// End position (1-based line and column)
+    pub end: (u32, u32),
 }
 
 impl Extractor {
-    /// Create a new extractor for the given file.
+    /// Create a new extractor for the given file that keeps its TRAP in memory.
     pub fn new(file_path: &str) -> Self {
+        Self::with_trap(file_path, TrapWriter::new(file_path))
+    }
+
+    /// Create a new extractor for the given file that streams its TRAP into
+    /// `trap_path`.
+    pub fn create(file_path: &str, trap_path: &Path, compression: Compression) -> Result<Self> {
+        let trap = TrapWriter::create(file_path, trap_path, compression)
+            .context("Failed to create TRAP file")?;
+        Ok(Self::with_trap(file_path, trap))
+    }
+
+    fn with_trap(file_path: &str, trap: TrapWriter) -> Self {
         Extractor {
             file_path: file_path.to_string(),
-            trap: TrapWriter::new(file_path),
+            trap,
             file_label: None,
+            parse_errors: Vec::new(),
+            imports: Vec::new(),
+            line_index: LineIndex::default(),
+            limits: FileLimits::default(),
+            parse_time: Duration::ZERO,
+            node_count: 0,
+            node_spans: None,
+            node_labels: HashMap::new(),
+            tree: None,
+            linearization_failures: Vec::new(),
         }
     }
 
+    /// Apply limits to the work done for the file.
+    ///
+    /// A file exceeding them fails to extract with a [`LimitExceeded`] error,
+    /// before anything but the TRAP file header is written.
+    pub fn with_limits(mut self, limits: FileLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Record the byte ranges of the file's nodes while extracting, so that
+    /// the compiler's AST of the file can be matched with `emit_compiler_ast`.
+    pub fn with_node_spans(mut self) -> Self {
+        self.node_spans = Some(HashMap::new());
+        self
+    }
+
     /// Extract the given source code.
+    ///
+    /// Lines may end with `\n`, `\r\n` or `\r`; the source should not start with
+    /// a byte order mark (see `SourceText`).
     pub fn extract(&mut self, source: &str) -> Result<()> {
-        // Initialize tree-sitter parser
-        let mut parser = Parser::new();
-        let language: tree_sitter::Language = tree_sitter_solidity::LANGUAGE.into();
-        parser
-            .set_language(&language)
-            .map_err(|e| anyhow::anyhow!("Failed to set tree-sitter language: {:?}", e))?;
-
-        // Parse source code
-        let tree = parser
-            .parse(source, None)
-            .context("Failed to parse source code")?;
+        let parsed = ParsedSource::parse(source, &self.limits)?;
+        self.extract_parsed(source, parsed)
+    }
+
+    /// Extract source code parsed beforehand (within the same limits).
+    pub fn extract_parsed(&mut self, source: &str, parsed: ParsedSource) -> Result<()> {
+        let ParsedSource { tree, parse_time } = parsed;
+        self.line_index = LineIndex::new(source);
+        self.parse_time = parse_time;
 
         // Emit file entry
         self.file_label = Some(self.trap.emit_file(&self.file_path));
@@ -52,16 +250,187 @@ impl Extractor {
         // Extract AST
         self.extract_tree(&tree, source)?;
 
+        // Record how many syntax errors were found, so clean files are distinguishable
+        self.emit_parse_error_count()?;
+
+        self.tree = Some(tree);
         Ok(())
     }
 
-    /// Write TRAP to file.
+    /// Get the number of syntax errors found by the last call to `extract`.
+    pub fn parse_error_count(&self) -> usize {
+        self.parse_errors.len()
+    }
+
+    /// Get the time the last call to `extract` spent parsing.
+    pub fn parse_time(&self) -> Duration {
+        self.parse_time
+    }
+
+    /// Get the number of syntax tree nodes extracted (ERROR and MISSING nodes
+    /// excluded).
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    /// Get the number of TRAP tuples emitted so far.
+    pub fn tuple_count(&self) -> usize {
+        self.trap.entry_count()
+    }
+
+    /// Get the syntax errors found by the last call to `extract`.
+    pub fn parse_errors(&self) -> &[ParseError] {
+        &self.parse_errors
+    }
+
+    /// Record the build project the file belongs to.
+    ///
+    /// `root` is the project directory as recorded in the database; projects are
+    /// identified by it, so every file of a project refers to the same entity.
+    pub fn emit_project(&mut self, root: &str, framework: &str) {
+        let file_label = self.file_label.clone().expect("File label not set");
+        let project = self.trap.string_label(&format!("project;{}", root));
+        self.trap.emit(
+            "solidity_project",
+            vec![
+                TrapValue::Label(project.clone()),
+                TrapValue::String(root.to_string()),
+                TrapValue::String(framework.to_string()),
+            ],
+        );
+        self.trap.emit(
+            "solidity_project_file",
+            vec![TrapValue::Label(file_label), TrapValue::Label(project)],
+        );
+    }
+
+    /// Record the role of the file in its project (`source`, `test`, `script` or `library`).
+    pub fn emit_file_role(&mut self, role: &str) {
+        let file_label = self.file_label.clone().expect("File label not set");
+        self.trap.emit(
+            "solidity_file_role",
+            vec![
+                TrapValue::Label(file_label),
+                TrapValue::String(role.to_string()),
+            ],
+        );
+    }
+
+    /// Get the import directives found by the last call to `extract`.
+    pub fn imports(&self) -> &[Import] {
+        &self.imports
+    }
+
+    /// Link each import directive to the file it loads.
+    ///
+    /// `resolve` maps an imported path to the database path of the imported file.
+    /// Imports it cannot resolve are recorded as diagnostics.
+    pub fn resolve_imports(&mut self, mut resolve: impl FnMut(&str) -> Option<String>) {
+        let mut imports = std::mem::take(&mut self.imports);
+        for import in &mut imports {
+            import.resolved = resolve(&import.path);
+            match &import.resolved {
+                Some(target) => {
+                    let file_label = self.trap.emit_file(target);
+                    self.trap.emit(
+                        "solidity_import_resolution",
+                        vec![
+                            TrapValue::Label(import.label.clone()),
+                            TrapValue::Label(file_label),
+                        ],
+                    );
+                }
+                None => {
+                    let message = format!("Could not resolve import `{}`", import.path);
+                    let full_message = format!("{}. {}", message, UNRESOLVED_IMPORT_HINT);
+                    self.emit_diagnostic(
+                        DIAGNOSTIC_SEVERITY_WARNING,
+                        "unresolved_import",
+                        message,
+                        full_message,
+                        import.location.clone(),
+                    );
+                }
+            }
+        }
+        self.imports = imports;
+    }
+
+    /// Bind the file's names to their declarations, in the file or in the
+    /// files of `index` (see the `names` module), linearize the bases of its
+    /// contracts, and infer the types of its expressions (see the `types`
+    /// module).
+    ///
+    /// Call after `resolve_imports`, as names are imported from the files
+    /// imports resolve to.
+    pub fn resolve_names(&mut self, source: &str, index: &SymbolIndex) {
+        let Some(tree) = self.tree.take() else {
+            return;
+        };
+        let labels = std::mem::take(&mut self.node_labels);
+        // The file's own names are in the index when it was built for a run
+        let computed;
+        let own = match index.get(&self.file_path) {
+            Some(own) => own,
+            None => {
+                let imports = &self.imports;
+                computed = file_symbols(&tree, source, &self.file_path, |path| {
+                    imports
+                        .iter()
+                        .find(|import| import.path == path)
+                        .and_then(|import| import.resolved.clone())
+                });
+                &computed
+            }
+        };
+        let failures = write_names(&mut self.trap, &tree, source, own, index, &labels);
+        for contract in failures {
+            let (start, end) = node_span(&self.line_index, &contract);
+            let name = contract.child_by_field_name("name");
+            self.linearization_failures.push(LinearizationFailure {
+                contract: name.map_or_else(String::new, |name| {
+                    name.utf8_text(source.as_bytes()).unwrap_or("").to_string()
+                }),
+                start,
+                end,
+            });
+        }
+    }
+
+    /// Get the contracts whose bases `resolve_names` could not linearize.
+    pub fn linearization_failures(&self) -> &[LinearizationFailure] {
+        &self.linearization_failures
+    }
+
+    /// Record what the compiler knows about the file's nodes, from a build of
+    /// the same source. `build` is the database path of the build-info file.
+    ///
+    /// Returns the number of compiler nodes matched to syntax tree nodes (none
+    /// unless the extractor was created `with_node_spans`).
+    pub fn emit_compiler_ast(&mut self, build: &str, source: &CompiledSource) -> usize {
+        let spans = self.node_spans.take().unwrap_or_default();
+        write_compiler_nodes(&mut self.trap, build, source, |start, end| {
+            spans.get(&(start, end)).cloned()
+        })
+    }
+
+    /// Write TRAP to file (for an extractor created with `new`).
     pub fn write_trap(&self, path: &Path, compression: Compression) -> Result<()> {
         self.trap
             .write_to_file(path, compression)
             .context("Failed to write TRAP file")
     }
 
+    /// Get the TRAP output (for an extractor created with `new`).
+    pub fn trap_text(&self) -> String {
+        self.trap.format()
+    }
+
+    /// Finish writing the TRAP file (for an extractor created with `create`).
+    pub fn finish(self) -> Result<()> {
+        self.trap.finish().context("Failed to write TRAP file")
+    }
+
     /// Emit folder hierarchy for the file.
     fn emit_folder_hierarchy(&mut self) -> Result<()> {
         let path = Path::new(&self.file_path);
@@ -112,42 +481,130 @@ impl Extractor {
     }
 
     /// Extract the parse tree to TRAP.
+    ///
+    /// Nodes are visited in pre-order with a tree cursor and an explicit stack
+    /// of open nodes rather than by recursion, so deeply nested code (long
+    /// operator chains, nested ternaries) cannot overflow the thread's stack.
+    /// Each field tuple is emitted once the child's subtree is complete.
+    ///
+    /// The named children of a syntax error (declarations tree-sitter
+    /// recovered, say) are extracted as children of the node enclosing it; the
+    /// stray tokens in it are left out. If the root itself is a syntax error,
+    /// a `source_file` node stands in for it.
     fn extract_tree(&mut self, tree: &Tree, source: &str) -> Result<()> {
-        let root = tree.root_node();
-        self.extract_node(root, source, None)?;
-        Ok(())
+        let mut cursor = tree.walk();
+        let root = cursor.node();
+        let mut label = self.extract_node(root, source, None)?;
+        if root.is_error() {
+            label = Some(self.extract_source_file(root)?);
+        }
+        let mut stack = vec![OpenNode::new(root, label, None)];
+        if !cursor.goto_first_child() {
+            return Ok(());
+        }
+
+        loop {
+            // The cursor is at the next child of the innermost open node
+            let parent = stack.last().expect("the root is open");
+            let child = cursor.node();
+            let in_error = parent.node.is_error();
+            let field_name = match in_error {
+                true => None,
+                false => parent.node.field_name_for_child(parent.child_index as u32),
+            };
+
+            if child.is_named() || !in_error {
+                let parent_info = next_child(&mut stack);
+                let label = self.extract_node(child, source, parent_info)?;
+                // Syntax errors are opened too, for the nodes recovered in them
+                if (label.is_some() || child.is_error()) && cursor.goto_first_child() {
+                    stack.push(OpenNode::new(child, label, field_name));
+                    continue;
+                }
+                if let Some(label) = label {
+                    let parent = stack.last_mut().expect("the root is open");
+                    self.emit_field(parent, field_name, label);
+                }
+            }
+
+            // Move on to the next sibling, closing the nodes that are complete
+            loop {
+                if cursor.goto_next_sibling() {
+                    stack.last_mut().expect("the root is open").child_index += 1;
+                    break;
+                }
+                if !cursor.goto_parent() {
+                    return Ok(());
+                }
+                let closed = stack.pop().expect("the cursor's parent is open");
+                let Some(parent) = stack.last_mut() else {
+                    return Ok(());
+                };
+                if let Some(label) = closed.label {
+                    self.emit_field(parent, closed.field_name, label);
+                }
+            }
+        }
     }
 
-    /// Extract a single node and its children recursively.
+    /// Extract a single node (but not its children).
+    ///
+    /// ERROR and MISSING nodes are not part of the schema: they are recorded as
+    /// diagnostics rather than extracted, so `None` is returned.
     fn extract_node(
         &mut self,
         node: Node,
         source: &str,
         parent_info: Option<(Label, usize)>,
-    ) -> Result<Label> {
-        // Generate label for this node
-        let label = self.trap.fresh_label();
+    ) -> Result<Option<Label>> {
+        if node.is_error() || node.is_missing() {
+            self.emit_parse_error(&node, source)?;
+            return Ok(None);
+        }
 
         // Get node kind (type)
         let kind = node.kind();
-        let kind_id = node.kind_id();
 
-        // Emit _def table for this node
-        // Named nodes use their specific type, anonymous tokens use generic token type
-        let table_name = if node.is_named() {
-            format!("solidity_{}_def", normalize_kind(kind))
+        // Generate label for this node. Declarations other files can refer to
+        // are labelled by position, so references from those files match.
+        let label = if GLOBAL_DECLARATION_KINDS.contains(&kind) {
+            self.trap
+                .string_label(&declaration_key(&self.file_path, node.start_byte()))
         } else {
-            // All anonymous tokens (operators, keywords, punctuation) use generic token type
-            "solidity_token_def".to_string()
+            self.trap.fresh_label()
         };
+        self.node_count += 1;
+        let kind_id = node.kind_id();
+
+        // Emit _def table for this node
         self.trap
-            .emit(&table_name, vec![TrapValue::Label(label.clone())]);
+            .emit(def_table(&node), vec![TrapValue::Label(label.clone())]);
 
         // Emit AST node info (for all nodes including anonymous)
         self.emit_ast_node_info(&label, kind_id)?;
 
         // Emit location
-        self.emit_node_location(&label, &node)?;
+        let location = self.emit_node_location(&label, &node)?;
+
+        if kind == "import_directive" {
+            self.record_import(&label, location, node, source);
+        }
+
+        // Record the node's byte range, for matching the compiler's AST. Its
+        // descendants come later, so the innermost node of a range wins. The
+        // compiler leaves the `;` out of some declarations.
+        if let Some(spans) = self.node_spans.as_mut().filter(|_| node.is_named()) {
+            let (start, end) = (node.start_byte(), node.end_byte());
+            if node.child_count() > 0 && source.as_bytes()[..end].ends_with(b";") {
+                spans
+                    .entry((start, end - 1))
+                    .or_insert_with(|| label.clone());
+            }
+            spans.insert((start, end), label.clone());
+        }
+        if node.is_named() && needs_label(kind) {
+            self.node_labels.insert(node.id(), label.clone());
+        }
 
         // Emit parent relationship
         if let Some((parent_label, index)) = parent_info {
@@ -167,49 +624,44 @@ impl Extractor {
             self.emit_token_info(&label, kind_id as u32, text)?;
         }
 
-        // Process all children and emit field relationships
-        self.extract_children_and_fields(&label, node, source)?;
+        Ok(Some(label))
+    }
 
+    /// Extract a `source_file` node covering a root that is a syntax error, so
+    /// that the nodes recovered in it have a parent.
+    fn extract_source_file(&mut self, root: Node) -> Result<Label> {
+        let label = self.trap.fresh_label();
+        self.node_count += 1;
+        self.trap.emit(
+            "solidity_source_file_def",
+            vec![TrapValue::Label(label.clone())],
+        );
+        let kind_id = root.language().id_for_node_kind("source_file", true);
+        self.emit_ast_node_info(&label, kind_id)?;
+        self.emit_node_location(&label, &root)?;
         Ok(label)
     }
 
-    /// Extract all children and emit field relationships.
-    fn extract_children_and_fields(
-        &mut self,
-        parent_label: &Label,
-        node: Node,
-        source: &str,
-    ) -> Result<()> {
-        use std::collections::HashMap;
-        let mut field_indices: HashMap<String, usize> = HashMap::new();
-
-        let mut cursor = node.walk();
-
-        for (child_index, child) in node.children(&mut cursor).enumerate() {
-            // Extract the child
-            let child_label =
-                self.extract_node(child, source, Some((parent_label.clone(), child_index)))?;
-
-            // If this child has a field name, emit the field relationship
-            if let Some(field_name) = node.field_name_for_child(child_index as u32) {
-                let field_idx = *field_indices.get(field_name).unwrap_or(&0);
-                field_indices.insert(field_name.to_string(), field_idx + 1);
-
-                let kind = normalize_kind(node.kind());
-                let table_name = format!("solidity_{}_{}", kind, field_name);
-
-                self.trap.emit(
-                    &table_name,
-                    vec![
-                        TrapValue::Label(parent_label.clone()),
-                        TrapValue::UInt(field_idx as u64),
-                        TrapValue::Label(child_label),
-                    ],
-                );
-            }
-        }
+    /// Emit the field relationship of a child of `parent`, if it is in a field.
+    fn emit_field(&mut self, parent: &mut OpenNode, field_name: Option<&str>, child_label: Label) {
+        let (Some(field_name), Some(parent_label)) = (field_name, &parent.label) else {
+            return;
+        };
+        let field_indices = parent
+            .field_indices
+            .entry(field_name.to_string())
+            .or_insert(0);
+        let field_idx = *field_indices;
+        *field_indices += 1;
 
-        Ok(())
+        self.trap.emit(
+            field_table(&parent.node, field_name),
+            vec![
+                TrapValue::Label(parent_label.clone()),
+                TrapValue::UInt(field_idx as u64),
+                TrapValue::Label(child_label),
+            ],
+        );
     }
 
     /// Emit AST node type info.
@@ -224,30 +676,142 @@ impl Extractor {
         Ok(())
     }
 
-    /// Emit location for a node.
-    fn emit_node_location(&mut self, label: &Label, node: &Node) -> Result<()> {
+    /// Emit a `locations_default` entry covering a node and return its label.
+    fn emit_location_for(&mut self, node: &Node) -> Label {
         let file_label = self.file_label.as_ref().expect("File label not set");
+        let (start, end) = self.node_span(node);
+        self.trap
+            .emit_location(file_label, start.0, start.1, end.0, end.1)
+    }
+
+    /// Emit location for a node and return the location's label.
+    fn emit_node_location(&mut self, label: &Label, node: &Node) -> Result<Label> {
+        let loc_label = self.emit_location_for(node);
+
+        self.trap.emit(
+            "solidity_ast_node_location",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::Label(loc_label.clone()),
+            ],
+        );
+
+        Ok(loc_label)
+    }
+
+    /// Record an import directive and emit the symbols it binds.
+    ///
+    /// `import {A as B, C} from "x"` binds `A` as `B` and `C` as `C`; the whole-unit
+    /// forms `import "x" as M` and `import * as M from "x"` bind `*` as `M`.
+    fn record_import(&mut self, label: &Label, location: Label, node: Node, source: &str) {
+        let (path, symbols) = import_parts(node, source);
+        for (index, (name, alias)) in symbols.into_iter().enumerate() {
+            let alias = alias.unwrap_or_else(|| name.clone());
+            self.trap.emit(
+                "solidity_import_symbol",
+                vec![
+                    TrapValue::Label(label.clone()),
+                    TrapValue::UInt(index as u64),
+                    TrapValue::String(name),
+                    TrapValue::String(alias),
+                ],
+            );
+        }
 
-        let start = node.start_position();
-        let end = node.end_position();
+        // Without a path (a syntax error) there is nothing to resolve
+        if let Some(path) = path {
+            let (start, end) = self.node_span(&node);
+            self.imports.push(Import {
+                path,
+                resolved: None,
+                start,
+                end,
+                label: label.clone(),
+                location,
+            });
+        }
+    }
+
+    /// Emit a `diagnostics` entry for an ERROR or MISSING node.
+    fn emit_parse_error(&mut self, node: &Node, source: &str) -> Result<()> {
+        let (message, full_message) = if node.is_missing() {
+            let message = format!("A parse error occurred (expected `{}` symbol)", node.kind());
+            let full_message = format!("{}. {}", message, PARSE_ERROR_HINT);
+            (message, full_message)
+        } else {
+            let text = node.utf8_text(source.as_bytes()).unwrap_or("");
+            let message = "A parse error occurred".to_string();
+            let full_message = format!(
+                "{} near `{}`. {}",
+                message,
+                error_snippet(text),
+                PARSE_ERROR_HINT
+            );
+            (message, full_message)
+        };
 
-        // tree-sitter uses 0-based lines and columns, CodeQL uses 1-based
-        let loc_label = self.trap.emit_location(
-            file_label,
-            start.row as u32 + 1,
-            start.column as u32 + 1,
-            end.row as u32 + 1,
-            end.column as u32 + 1,
+        let location = self.emit_location_for(node);
+        self.emit_diagnostic(
+            DIAGNOSTIC_SEVERITY_ERROR,
+            "parse_error",
+            message.clone(),
+            full_message,
+            location,
         );
 
+        let (start, end) = self.node_span(node);
+        self.parse_errors.push(ParseError {
+            message,
+            start,
+            end,
+        });
+        Ok(())
+    }
+
+    /// Emit a `diagnostics` entry.
+    fn emit_diagnostic(
+        &mut self,
+        severity: i64,
+        tag: &str,
+        message: String,
+        full_message: String,
+        location: Label,
+    ) {
+        let diagnostic = self.trap.fresh_label();
         self.trap.emit(
-            "solidity_ast_node_location",
-            vec![TrapValue::Label(label.clone()), TrapValue::Label(loc_label)],
+            "diagnostics",
+            vec![
+                TrapValue::Label(diagnostic),
+                TrapValue::Int(severity),
+                TrapValue::String(tag.to_string()),
+                TrapValue::String(message),
+                TrapValue::String(full_message),
+                TrapValue::Label(location),
+            ],
         );
+    }
 
+    /// Emit the per-file syntax error count.
+    fn emit_parse_error_count(&mut self) -> Result<()> {
+        let file_label = self.file_label.clone().expect("File label not set");
+        self.trap.emit(
+            "solidity_parse_error_count",
+            vec![
+                TrapValue::Label(file_label),
+                TrapValue::UInt(self.parse_errors.len() as u64),
+            ],
+        );
         Ok(())
     }
 
+    /// Get the 1-based start and end positions of a node.
+    ///
+    /// Positions are computed from byte offsets rather than tree-sitter's rows,
+    /// which only count `\n`, so that `\r` line endings are numbered too.
+    fn node_span(&self, node: &Node) -> ((u32, u32), (u32, u32)) {
+        node_span(&self.line_index, node)
+    }
+
     /// Emit token info for terminal nodes.
     fn emit_token_info(&mut self, label: &Label, kind: u32, value: &str) -> Result<()> {
         self.trap.emit(
@@ -262,6 +826,174 @@ impl Extractor {
     }
 }
 
+/// Severity code for errors in the `diagnostics` table (see `@diagnostic_error`).
+const DIAGNOSTIC_SEVERITY_ERROR: i64 = 40;
+
+/// Severity code for warnings in the `diagnostics` table (see `@diagnostic_warning`).
+const DIAGNOSTIC_SEVERITY_WARNING: i64 = 30;
+
+/// Advice appended to every parse error message.
+const PARSE_ERROR_HINT: &str =
+    "Check the syntax of the file. If the file is invalid, correct the error or exclude the file from analysis.";
+
+/// Advice appended to every unresolved import message.
+const UNRESOLVED_IMPORT_HINT: &str =
+    "Check the import remappings and that dependencies are installed. Declarations from the imported file will not be linked to their uses.";
+
+/// Maximum number of characters of erroneous source quoted in a diagnostic.
+const MAX_ERROR_SNIPPET_CHARS: usize = 40;
+
+/// Shorten the source text of an ERROR node to a single-line snippet.
+fn error_snippet(text: &str) -> String {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .unwrap_or("");
+    if line.chars().count() > MAX_ERROR_SNIPPET_CHARS {
+        let truncated: String = line.chars().take(MAX_ERROR_SNIPPET_CHARS).collect();
+        format!("{}...", truncated)
+    } else {
+        line.to_string()
+    }
+}
+
+/// Strip the quotes from a string literal.
+fn unquote(literal: &str) -> &str {
+    let quoted = literal.len() >= 2
+        && (literal.starts_with('"') && literal.ends_with('"')
+            || literal.starts_with('\'') && literal.ends_with('\''));
+    if quoted {
+        &literal[1..literal.len() - 1]
+    } else {
+        literal
+    }
+}
+
+/// Get the imported path of an import directive and the symbols it imports,
+/// each with its alias (`*` for the whole unit).
+pub(super) fn import_parts(
+    node: Node,
+    source: &str,
+) -> (Option<String>, Vec<(String, Option<String>)>) {
+    let mut path = None;
+    let mut symbols: Vec<(String, Option<String>)> = Vec::new();
+
+    let mut cursor = node.walk();
+    for (child_index, child) in node.children(&mut cursor).enumerate() {
+        let text = child.utf8_text(source.as_bytes()).unwrap_or("");
+        match node.field_name_for_child(child_index as u32) {
+            Some("source") => path = Some(unquote(text).to_string()),
+            Some("import_name") => symbols.push((text.to_string(), None)),
+            Some("alias") => match symbols.last_mut() {
+                Some((_, alias @ None)) => *alias = Some(text.to_string()),
+                _ => symbols.push(("*".to_string(), Some(text.to_string()))),
+            },
+            _ => {}
+        }
+    }
+    (path, symbols)
+}
+
+/// Parse source code within the given limits.
+pub(super) fn parse_source(parser: &mut Parser, source: &str, limits: &FileLimits) -> Result<Tree> {
+    let tree = match limits.parse_timeout {
+        Some(timeout) => parse_with_timeout(parser, source, timeout)?,
+        None => parser
+            .parse(source, None)
+            .context("Failed to parse source code")?,
+    };
+    if let Some(max) = limits.max_ast_nodes {
+        if exceeds_node_count(&tree, max) {
+            return Err(LimitExceeded::TooManyNodes(max).into());
+        }
+    }
+    Ok(tree)
+}
+
+/// Parse source code, giving up after `timeout`.
+fn parse_with_timeout(parser: &mut Parser, source: &str, timeout: Duration) -> Result<Tree> {
+    let start = Instant::now();
+    let mut progress = |_: &_| {
+        if start.elapsed() > timeout {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    };
+    let bytes = source.as_bytes();
+    let tree = parser.parse_with_options(
+        &mut |offset, _| bytes.get(offset..).unwrap_or_default(),
+        None,
+        Some(ParseOptions::new().progress_callback(&mut progress)),
+    );
+    match tree {
+        Some(tree) => Ok(tree),
+        None if start.elapsed() > timeout => Err(LimitExceeded::ParseTimeout(timeout).into()),
+        None => anyhow::bail!("Failed to parse source code"),
+    }
+}
+
+/// Check whether a syntax tree has more than `max` nodes, without counting
+/// past the limit.
+fn exceeds_node_count(tree: &Tree, max: usize) -> bool {
+    let mut cursor = tree.walk();
+    let mut count = 0;
+    loop {
+        count += 1;
+        if count > max {
+            return true;
+        }
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return false;
+            }
+        }
+    }
+}
+
+/// Create a parser for Solidity.
+pub(super) fn new_parser() -> Result<Parser> {
+    let mut parser = Parser::new();
+    let language: tree_sitter::Language = tree_sitter_solidity::LANGUAGE.into();
+    parser
+        .set_language(&language)
+        .map_err(|e| anyhow::anyhow!("Failed to set tree-sitter language: {:?}", e))?;
+    Ok(parser)
+}
+
+/// Get the `_def` table a node is recorded in.
+///
+/// Named nodes use their specific type; all anonymous tokens (operators,
+/// keywords, punctuation) use the generic token type.
+pub(super) fn def_table(node: &Node) -> String {
+    if node.is_named() {
+        format!("solidity_{}_def", normalize_kind(node.kind()))
+    } else {
+        "solidity_token_def".to_string()
+    }
+}
+
+/// Get the table recording the children of a node in the given field.
+pub(super) fn field_table(node: &Node, field_name: &str) -> String {
+    format!("solidity_{}_{}", normalize_kind(node.kind()), field_name)
+}
+
+/// Get the 1-based start and end positions of a node.
+pub(super) fn node_span(line_index: &LineIndex, node: &Node) -> ((u32, u32), (u32, u32)) {
+    let start = line_index.position(node.start_byte());
+    let end = line_index.position(node.end_byte());
+
+    // Lines and columns are 0-based, CodeQL uses 1-based
+    (
+        (start.0 as u32 + 1, start.1 as u32 + 1),
+        (end.0 as u32 + 1, end.1 as u32 + 1),
+    )
+}
+
 /// Normalize a tree-sitter kind name for use in table names.
 fn normalize_kind(kind: &str) -> String {
     kind.replace('-', "_")
@@ -346,4 +1078,265 @@ mod tests {
         let result = extractor.extract(source);
         assert!(result.is_ok(), "Extraction failed: {:?}", result.err());
     }
+
+    #[test]
+    fn test_parse_errors_become_diagnostics() {
+        let source = r#"
+            contract Broken {
+                function f( public {
+            }
+        "#;
+
+        let mut extractor = Extractor::new("/test/Broken.sol");
+        extractor.extract(source).unwrap();
+        assert!(extractor.parse_error_count() > 0);
+
+        let output = extractor.trap.format();
+        assert!(output.contains("diagnostics("));
+        assert!(output.contains("\"parse_error\""));
+        assert!(!output.contains("solidity_error_def"));
+    }
+
+    #[test]
+    fn test_nodes_recovered_from_syntax_errors() {
+        let source = r#"
+            contract Broken {
+                uint256 public total;
+                ) )
+                function kept() public {}
+            }
+            contract AlsoBroken { function f( public {} }
+            contract Kept {}
+        "#;
+
+        let mut extractor = Extractor::new("/test/Broken.sol");
+        extractor.extract(source).unwrap();
+        assert!(extractor.parse_error_count() > 0);
+
+        // The declarations around and after the errors are extracted
+        let output = extractor.trap.format();
+        for name in ["\"total\"", "\"kept\"", "\"Kept\""] {
+            assert!(output.contains(name), "{} is missing", name);
+        }
+
+        // Every node recovered has a place of its own among its parent's children
+        let parents: Vec<(&str, &str)> = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("solidity_ast_node_parent("))
+            .map(|values| {
+                let mut values = values.split(", ").skip(1);
+                (values.next().unwrap(), values.next().unwrap())
+            })
+            .collect();
+        let unique: std::collections::HashSet<_> = parents.iter().collect();
+        assert_eq!(unique.len(), parents.len());
+    }
+
+    #[test]
+    fn test_nodes_recovered_from_an_unparseable_file() {
+        // Garbage the parser cannot make a source file of, so that the root
+        // itself is a syntax error
+        for source in [
+            "} ) ] contract Kept { uint256 total; }",
+            "function ( contract Kept {} { uint256 total;",
+            "pragma solidity ^0.8.0; ))) }}} contract Kept {}",
+        ] {
+            let mut extractor = Extractor::new("/test/Garbage.sol");
+            extractor.extract(source).unwrap();
+            assert!(extractor.parse_error_count() > 0);
+
+            // Every node but the root has a parent
+            let output = extractor.trap.format();
+            let parents = output
+                .lines()
+                .filter(|line| line.starts_with("solidity_ast_node_parent("))
+                .count();
+            assert_eq!(parents + 1, extractor.node_count(), "{}", source);
+            assert!(output.contains("solidity_source_file_def("));
+            assert!(output.contains("\"Kept\""));
+        }
+    }
+
+    #[test]
+    fn test_node_maps_are_kept_only_while_needed() {
+        let source =
+            "contract A { function f(uint x) public pure returns (uint) { return x + 1; } }";
+        let mut extractor = Extractor::new("/test/A.sol");
+        extractor.extract(source).unwrap();
+        // Statements and type names are not referred to
+        let named = extractor
+            .trap
+            .format()
+            .lines()
+            .filter(|line| line.contains("_def(") && !line.starts_with("solidity_token_def("))
+            .count();
+        assert!(extractor.node_labels.len() < named);
+
+        extractor.resolve_names(source, &SymbolIndex::default());
+        assert!(extractor.tree.is_none());
+        assert!(extractor.node_labels.is_empty());
+        // `x` is bound, and `x + 1` and its operands are typed
+        let output = extractor.trap.format();
+        assert!(output.contains("solidity_binding("));
+        assert!(output.contains(", \"uint256\")"));
+        assert!(output.contains(", \"int_const 1\")"));
+    }
+
+    #[test]
+    fn test_node_spans_are_recorded_on_request() {
+        let source = "contract A {}";
+        let mut extractor = Extractor::new("/test/A.sol");
+        extractor.extract(source).unwrap();
+        assert!(extractor.node_spans.is_none());
+
+        let mut extractor = Extractor::new("/test/A.sol").with_node_spans();
+        extractor.extract(source).unwrap();
+        let spans = extractor.node_spans.as_ref().unwrap();
+        assert!(spans.contains_key(&(0, source.len())));
+    }
+
+    #[test]
+    fn test_import_symbols_and_resolution() {
+        let source = r#"
+            import "./Base.sol";
+            import {Token as T, Math} from "./Token.sol";
+            import * as Lib from "lib/Lib.sol";
+        "#;
+
+        let mut extractor = Extractor::new("/test/Imports.sol");
+        extractor.extract(source).unwrap();
+        let paths: Vec<&str> = extractor
+            .imports()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["./Base.sol", "./Token.sol", "lib/Lib.sol"]);
+
+        extractor.resolve_imports(|path| path.strip_prefix("./").map(|p| format!("/test/{}", p)));
+        assert_eq!(
+            extractor.imports()[1].resolved.as_deref(),
+            Some("/test/Token.sol")
+        );
+        assert_eq!(extractor.imports()[2].resolved, None);
+
+        let output = extractor.trap.format();
+        assert!(output.contains("\"Token\", \"T\")"));
+        assert!(output.contains("\"Math\", \"Math\")"));
+        assert!(output.contains("\"*\", \"Lib\")"));
+        assert_eq!(output.matches("solidity_import_resolution(").count(), 2);
+        assert!(output.contains("\"unresolved_import\""));
+    }
+
+    #[test]
+    fn test_excluded_files_are_not_referred_to() {
+        let a = "import \"./B.sol\";\ncontract A is B { function g() public { f(); } }\n";
+        let b = "contract B { function f() public {} }\n";
+        let parsed = ParsedSource::parse(b, &FileLimits::default()).unwrap();
+        let mut index: SymbolIndex =
+            std::iter::once(file_symbols(parsed.tree(), b, "/test/B.sol", |_| None)).collect();
+
+        let trap = |index: &SymbolIndex| {
+            let mut extractor = Extractor::new("/test/A.sol");
+            extractor.extract(a).unwrap();
+            extractor.resolve_imports(|_| Some("/test/B.sol".to_string()));
+            extractor.resolve_names(a, index);
+            extractor.trap.format()
+        };
+        let bound = trap(&index);
+        assert!(bound.contains("declaration;/test/A.sol;"));
+        assert!(bound.contains("declaration;/test/B.sol;"));
+
+        index.exclude("/test/B.sol");
+        let excluded = trap(&index);
+        assert!(excluded.contains("declaration;/test/A.sol;"));
+        assert!(!excluded.contains("declaration;/test/B.sol;"));
+    }
+
+    #[test]
+    fn test_line_endings_do_not_change_locations() {
+        let locations = |source: &str| {
+            let mut extractor = Extractor::new("/test/Lines.sol");
+            extractor.extract(source).unwrap();
+            let output = extractor.trap.format();
+            output
+                .lines()
+                .filter(|line| line.starts_with("locations_default("))
+                .map(|line| line.split_once(',').unwrap().1.to_string())
+                .collect::<Vec<_>>()
+        };
+        let lf = locations("contract A {\n  uint x;\n}\n");
+        assert!(!lf.is_empty());
+        assert_eq!(locations("contract A {\r\n  uint x;\r\n}\r\n"), lf);
+        assert_eq!(locations("contract A {\r  uint x;\r}\r"), lf);
+    }
+
+    #[test]
+    fn test_deep_nesting_does_not_overflow() {
+        // A generated lookup table: one long chain of binary expressions
+        let chain = vec!["1"; 20_000].join(" + ");
+        let source = format!(
+            "contract T {{ function f() public pure returns (uint) {{ return {}; }} }}",
+            chain
+        );
+
+        // Much less stack than a rayon worker gets
+        let output = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                let mut extractor = Extractor::new("/test/T.sol");
+                extractor.extract(&source).unwrap();
+                extractor.trap.format()
+            })
+            .unwrap()
+            .join()
+            .expect("extraction overflowed the stack");
+        assert!(output.matches("solidity_ast_node_parent(").count() > 40_000);
+    }
+
+    #[test]
+    fn test_file_limits() {
+        let source = "contract A { function f() public { uint x = 1 + 2; } }\n".repeat(2000);
+
+        let mut extractor = Extractor::new("/test/A.sol").with_limits(FileLimits {
+            max_ast_nodes: Some(100),
+            parse_timeout: None,
+        });
+        let error = extractor.extract(&source).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LimitExceeded>(),
+            Some(LimitExceeded::TooManyNodes(100))
+        ));
+        // Nothing was extracted
+        assert!(!extractor.trap.format().contains("files("));
+
+        let mut extractor = Extractor::new("/test/A.sol").with_limits(FileLimits {
+            max_ast_nodes: None,
+            parse_timeout: Some(Duration::ZERO),
+        });
+        let error = extractor.extract(&source).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LimitExceeded>(),
+            Some(LimitExceeded::ParseTimeout(_))
+        ));
+
+        let mut extractor = Extractor::new("/test/A.sol").with_limits(FileLimits {
+            max_ast_nodes: Some(1_000_000),
+            parse_timeout: Some(Duration::from_secs(60)),
+        });
+        assert!(extractor.extract(&source).is_ok());
+    }
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote("\"./A.sol\""), "./A.sol");
+        assert_eq!(unquote("'./A.sol'"), "./A.sol");
+        assert_eq!(unquote("\""), "\"");
+    }
+
+    #[test]
+    fn test_error_snippet() {
+        assert_eq!(error_snippet("\n   foo(bar\n baz"), "foo(bar");
+        let long = "x".repeat(100);
+        assert_eq!(error_snippet(&long).len(), MAX_ERROR_SNIPPET_CHARS + 3);
+    }
 }
@@ -0,0 +1,149 @@
+//! Foundry project configuration.
+//!
+//! Only the parts of `foundry.toml` that affect extraction are read. Values are
+//! taken from the active profile (`FOUNDRY_PROFILE`, or `default`), falling
+//! back to the `default` profile and then to Foundry's own defaults.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use toml::Value;
+
+/// Name of the Foundry configuration file.
+pub const FOUNDRY_TOML: &str = "foundry.toml";
+
+/// Environment variable selecting the active Foundry profile.
+const FOUNDRY_PROFILE_VAR: &str = "FOUNDRY_PROFILE";
+
+/// Settings read from a `foundry.toml` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundryConfig {
+    /// Name of the profile the settings were read from
+    pub profile: String,
+    /// Import remappings, in `[context:]prefix=target` form
+    pub remappings: Vec<String>,
+    /// Contracts directory (Foundry default: `src`)
+    pub src: String,
+    /// Tests directory (Foundry default: `test`)
+    pub test: String,
+    /// Scripts directory (Foundry default: `script`)
+    pub script: String,
+    /// Dependency directories (Foundry default: `lib`)
+    pub libs: Vec<String>,
+    /// Build artifacts directory (Foundry default: `out`)
+    pub out: String,
+    /// Compiler cache directory (Foundry default: `cache`)
+    pub cache_path: String,
+    /// Deployment broadcast directory (Foundry default: `broadcast`)
+    pub broadcast: String,
+}
+
+impl FoundryConfig {
+    /// Load `foundry.toml` from a project directory, if there is one.
+    pub fn load(project_root: &Path) -> Result<Option<Self>> {
+        let path = project_root.join(FOUNDRY_TOML);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let profile = std::env::var(FOUNDRY_PROFILE_VAR).unwrap_or_else(|_| "default".into());
+        Self::parse(&text, &profile)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+            .map(Some)
+    }
+
+    /// Parse the contents of a `foundry.toml` file for the given profile.
+    pub fn parse(text: &str, profile: &str) -> Result<Self> {
+        let document: Value = text.parse()?;
+        let profiles = document.get("profile");
+        let active = profiles.and_then(|p| p.get(profile));
+        let default = profiles.and_then(|p| p.get("default"));
+
+        // A key set in the active profile overrides the default profile
+        let lookup = |key: &str| {
+            active
+                .and_then(|p| p.get(key))
+                .or_else(|| default.and_then(|p| p.get(key)))
+        };
+
+        let string = |key: &str, default: &str| {
+            lookup(key)
+                .and_then(Value::as_str)
+                .map(|s| s.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| default.to_string())
+        };
+
+        Ok(FoundryConfig {
+            profile: profile.to_string(),
+            remappings: string_list(lookup("remappings")),
+            src: string("src", "src"),
+            test: string("test", "test"),
+            script: string("script", "script"),
+            libs: match lookup("libs") {
+                Some(value) => string_list(Some(value)),
+                None => vec!["lib".to_string()],
+            },
+            out: string("out", "out"),
+            cache_path: string("cache_path", "cache"),
+            broadcast: string("broadcast", "broadcast"),
+        })
+    }
+}
+
+/// Read a TOML string or array of strings.
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [profile.default]
+        src = "src"
+        libs = ["lib", "dependencies"]
+        remappings = ["@oz/=lib/openzeppelin-contracts/contracts/"]
+
+        [profile.ci]
+        remappings = ["@oz/=vendor/oz/"]
+        test = "test/ci/"
+    "#;
+
+    #[test]
+    fn test_parse_default_profile() {
+        let config = FoundryConfig::parse(CONFIG, "default").unwrap();
+        assert_eq!(
+            config.remappings,
+            vec!["@oz/=lib/openzeppelin-contracts/contracts/"]
+        );
+        assert_eq!(config.libs, vec!["lib", "dependencies"]);
+        assert_eq!(config.test, "test");
+    }
+
+    #[test]
+    fn test_parse_profile_overrides_default() {
+        let config = FoundryConfig::parse(CONFIG, "ci").unwrap();
+        assert_eq!(config.remappings, vec!["@oz/=vendor/oz/"]);
+        assert_eq!(config.test, "test/ci");
+        // Not set in the `ci` profile, so inherited from `default`
+        assert_eq!(config.libs, vec!["lib", "dependencies"]);
+    }
+
+    #[test]
+    fn test_parse_empty_config() {
+        let config = FoundryConfig::parse("", "default").unwrap();
+        assert!(config.remappings.is_empty());
+        assert_eq!(config.libs, vec!["lib"]);
+        assert_eq!(config.src, "src");
+        assert_eq!(config.out, "out");
+    }
+}
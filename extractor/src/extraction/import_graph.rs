@@ -0,0 +1,196 @@
+//! Import graph of the files in a run.
+//!
+//! Used by diff mode to find which files a change can affect. The graph is
+//! built from the same lexical import scan as autobuild, so it can be computed
+//! for a whole repository without parsing every file.
+
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use super::imports::scan_imports;
+use super::project::Workspace;
+use super::source_text::SourceText;
+
+/// Why a file is extracted in diff mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffReason {
+    /// The file was changed
+    Changed,
+    /// The file imports a changed file, directly or transitively
+    Importer,
+    /// The file is imported by a changed file or an importer
+    Dependency,
+}
+
+impl DiffReason {
+    /// Get the name recorded in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiffReason::Changed => "changed",
+            DiffReason::Importer => "importer",
+            DiffReason::Dependency => "dependency",
+        }
+    }
+}
+
+/// Import edges between files (canonical paths).
+#[derive(Debug, Default)]
+pub struct ImportGraph {
+    /// Files imported by each file
+    imports: HashMap<PathBuf, Vec<PathBuf>>,
+    /// Files importing each file
+    importers: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl ImportGraph {
+    /// Build the import graph of the given files.
+    ///
+    /// Imports of files that cannot be read are left out. An import that does
+    /// not resolve is an edge to the path it names, so that the importers of
+    /// a deleted file are found.
+    pub fn build(files: &[PathBuf], workspace: &Workspace, sources: &HashMap<&Path, &str>) -> Self {
+        let edges: Vec<(PathBuf, Vec<PathBuf>)> = files
+            .par_iter()
+            .map(|file| {
+                let project = workspace.project_for(file);
+                let source = match sources.get(file.as_path()) {
+                    Some(text) => Ok(text.to_string()),
+                    None => SourceText::read(file).map(|source| source.text),
+                };
+                let targets = source
+                    .map(|source| {
+                        scan_imports(&source)
+                            .iter()
+                            .map(|import| {
+                                let imports = project.imports();
+                                imports
+                                    .resolve(file, import)
+                                    .unwrap_or_else(|| imports.imported_path(file, import))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (file.clone(), targets)
+            })
+            .collect();
+
+        let mut graph = ImportGraph::default();
+        for (file, targets) in edges {
+            for target in &targets {
+                graph
+                    .importers
+                    .entry(target.clone())
+                    .or_default()
+                    .push(file.clone());
+            }
+            graph.imports.insert(file, targets);
+        }
+        graph
+    }
+
+    /// Select the files to extract for a change.
+    ///
+    /// These are the changed files, the files importing them (transitively),
+    /// and the files those import (transitively), so that the declarations
+    /// used by affected code are present. The result is sorted by path; it
+    /// includes changed files that were deleted, which have no reason to be
+    /// extracted but whose importers do.
+    pub fn diff_scope(&self, changed: &[PathBuf]) -> Vec<(PathBuf, DiffReason)> {
+        let mut scope: HashMap<PathBuf, DiffReason> = changed
+            .iter()
+            .map(|file| (file.clone(), DiffReason::Changed))
+            .collect();
+
+        let importers = reachable(changed, &self.importers);
+        for file in &importers {
+            scope.entry(file.clone()).or_insert(DiffReason::Importer);
+        }
+
+        let affected: Vec<PathBuf> = scope.keys().cloned().collect();
+        for file in reachable(&affected, &self.imports) {
+            scope.entry(file).or_insert(DiffReason::Dependency);
+        }
+
+        let mut scope: Vec<(PathBuf, DiffReason)> = scope.into_iter().collect();
+        scope.sort_by(|a, b| a.0.cmp(&b.0));
+        scope
+    }
+}
+
+/// Find the files reachable from `roots` along `edges` (excluding the roots
+/// themselves unless they are reachable from another root).
+fn reachable(roots: &[PathBuf], edges: &HashMap<PathBuf, Vec<PathBuf>>) -> HashSet<PathBuf> {
+    let mut seen = HashSet::new();
+    let mut queue: VecDeque<&Path> = roots.iter().map(PathBuf::as_path).collect();
+    while let Some(file) = queue.pop_front() {
+        for next in edges.get(file).into_iter().flatten() {
+            if seen.insert(next.clone()) {
+                queue.push_back(next);
+            }
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &str)]) -> ImportGraph {
+        let mut graph = ImportGraph::default();
+        for (from, to) in edges {
+            graph
+                .imports
+                .entry(PathBuf::from(from))
+                .or_default()
+                .push(PathBuf::from(to));
+            graph
+                .importers
+                .entry(PathBuf::from(to))
+                .or_default()
+                .push(PathBuf::from(from));
+        }
+        graph
+    }
+
+    #[test]
+    fn test_diff_scope() {
+        // App -> Vault -> Token -> Math, Other -> Math, Test -> App
+        let graph = graph(&[
+            ("App.sol", "Vault.sol"),
+            ("Vault.sol", "Token.sol"),
+            ("Token.sol", "Math.sol"),
+            ("Other.sol", "Math.sol"),
+            ("Test.sol", "App.sol"),
+        ]);
+        let scope = graph.diff_scope(&[PathBuf::from("Vault.sol")]);
+        let scope: Vec<(&str, &str)> = scope
+            .iter()
+            .map(|(file, reason)| (file.to_str().unwrap(), reason.as_str()))
+            .collect();
+        assert_eq!(
+            scope,
+            vec![
+                ("App.sol", "importer"),
+                ("Math.sol", "dependency"),
+                ("Test.sol", "importer"),
+                ("Token.sol", "dependency"),
+                ("Vault.sol", "changed"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_scope_with_cycle() {
+        let graph = graph(&[("A.sol", "B.sol"), ("B.sol", "A.sol")]);
+        let scope = graph.diff_scope(&[PathBuf::from("A.sol")]);
+        assert_eq!(
+            scope,
+            vec![
+                (PathBuf::from("A.sol"), DiffReason::Changed),
+                (PathBuf::from("B.sol"), DiffReason::Importer),
+            ]
+        );
+    }
+}
@@ -0,0 +1,568 @@
+//! Import resolution.
+//!
+//! Maps the path in an `import` directive to the file it loads, following the
+//! rules used by solc and the common build tools:
+//!
+//! - relative imports (`./`, `../`) are resolved against the importing file;
+//! - import remappings (`[context:]prefix=target`) from `remappings.txt`,
+//!   `foundry.toml` and Foundry's dependency directories are applied;
+//! - the result is looked up below the project root, then in the
+//!   `node_modules` directories above the importing file (Hardhat/npm).
+
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{debug, warn};
+
+use super::foundry::FoundryConfig;
+use super::source_root::path_to_string;
+
+/// Name of the remappings file read from the project root.
+const REMAPPINGS_TXT: &str = "remappings.txt";
+
+/// Directory searched for npm packages.
+const NODE_MODULES: &str = "node_modules";
+
+/// An import remapping: `[context:]prefix=target`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remapping {
+    /// Only imports from files whose path starts with this are remapped
+    pub context: String,
+    /// Import path prefix to replace
+    pub prefix: String,
+    /// Replacement for the prefix
+    pub target: String,
+}
+
+impl FromStr for Remapping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((lhs, target)) = s.trim().split_once('=') else {
+            bail!("Invalid remapping '{}': expected prefix=target", s);
+        };
+        let (context, prefix) = lhs.split_once(':').unwrap_or(("", lhs));
+        if prefix.is_empty() {
+            bail!("Invalid remapping '{}': empty prefix", s);
+        }
+        Ok(Remapping {
+            context: context.to_string(),
+            prefix: prefix.to_string(),
+            target: target.to_string(),
+        })
+    }
+}
+
+/// Resolves import paths to files on disk.
+#[derive(Debug, Clone)]
+pub struct ImportResolver {
+    /// Directory that import paths and remapping targets are relative to
+    root: PathBuf,
+    /// Remappings, in the order they were declared
+    remappings: Vec<Remapping>,
+    /// Files imports resolve to, if they are given rather than looked up on
+    /// disk (as for a solc standard-JSON input)
+    sources: Option<HashSet<PathBuf>>,
+}
+
+impl ImportResolver {
+    /// Create a resolver for a project root with the given remappings.
+    pub fn new(root: &Path, remappings: Vec<Remapping>) -> Self {
+        ImportResolver {
+            root: root.to_path_buf(),
+            remappings,
+            sources: None,
+        }
+    }
+
+    /// Create a resolver that resolves imports only to the given files below
+    /// `root`, whose paths relative to it are their source unit names.
+    ///
+    /// Absolute source unit names are looked up below the root too.
+    pub fn in_memory(root: &Path, remappings: &[String], sources: HashSet<PathBuf>) -> Self {
+        ImportResolver {
+            root: root.to_path_buf(),
+            remappings: parse_remappings(remappings, ""),
+            sources: Some(sources),
+        }
+    }
+
+    /// Create a resolver for a project root, reading its remappings.
+    ///
+    /// Remappings come from `remappings.txt` and the Foundry configuration, if
+    /// any. In a Foundry project every dependency directory also gets the
+    /// remapping Forge would generate for it, unless one was configured
+    /// explicitly. Invalid remappings are logged and ignored.
+    pub fn discover(root: &Path, foundry: Option<&FoundryConfig>) -> Self {
+        let mut remappings = read_remappings_file(&root.join(REMAPPINGS_TXT), "");
+
+        if let Some(config) = foundry {
+            remappings.extend(parse_remappings(&config.remappings, ""));
+            for lib in &config.libs {
+                remappings.extend(dependency_remappings(root, lib, &remappings));
+            }
+        }
+
+        for remapping in &remappings {
+            debug!(
+                "Remapping: {}:{}={}",
+                remapping.context, remapping.prefix, remapping.target
+            );
+        }
+        ImportResolver::new(root, remappings)
+    }
+
+    /// Add remappings that take precedence over the discovered ones.
+    pub fn add_remappings(&mut self, remappings: &[Remapping]) {
+        self.remappings.extend_from_slice(remappings);
+    }
+
+    /// Resolve an import in `importer` (a canonical path) to a canonical path.
+    ///
+    /// Returns `None` if the imported file does not exist.
+    pub fn resolve(&self, importer: &Path, import: &str) -> Option<PathBuf> {
+        self.lookup(importer, &self.imported_unit(importer, import))
+    }
+
+    /// Get the path an import in `importer` names within the root, whether or
+    /// not a file is there (a deleted file, say).
+    pub fn imported_path(&self, importer: &Path, import: &str) -> PathBuf {
+        let unit = self.imported_unit(importer, import);
+        if self.sources.is_none() && Path::new(&unit).is_absolute() {
+            return PathBuf::from(unit);
+        }
+        self.root.join(unit.trim_start_matches('/'))
+    }
+
+    /// Get the (remapped) source unit name an import in `importer` names.
+    fn imported_unit(&self, importer: &Path, import: &str) -> String {
+        let importer_unit = self.source_unit_name(importer);
+
+        // Relative imports name a file next to the importing one
+        let unit = if import.starts_with("./") || import.starts_with("../") {
+            let dir = importer_unit.rsplit_once('/').map_or("", |(dir, _)| dir);
+            normalize_unit_name(&format!("{}/{}", dir, import))
+        } else {
+            normalize_unit_name(import)
+        };
+
+        self.apply_remappings(&importer_unit, &unit)
+    }
+
+    /// Get the solc source unit name of a file: its path relative to the root.
+    fn source_unit_name(&self, file: &Path) -> String {
+        match file.strip_prefix(&self.root) {
+            Ok(relative) => path_to_string(relative),
+            Err(_) => path_to_string(file),
+        }
+    }
+
+    /// Apply the best matching remapping to a source unit name.
+    ///
+    /// As in solc, the remapping with the longest context wins, then the one with
+    /// the longest prefix, then the one declared last.
+    fn apply_remappings(&self, importer_unit: &str, unit: &str) -> String {
+        let best = self
+            .remappings
+            .iter()
+            .filter(|r| importer_unit.starts_with(&r.context) && unit.starts_with(&r.prefix))
+            .max_by_key(|r| (r.context.len(), r.prefix.len()));
+        match best {
+            Some(r) => format!("{}{}", r.target, &unit[r.prefix.len()..]),
+            None => unit.to_string(),
+        }
+    }
+
+    /// Find the file for a (remapped) source unit name.
+    fn lookup(&self, importer: &Path, unit: &str) -> Option<PathBuf> {
+        if let Some(sources) = &self.sources {
+            let candidate = self.root.join(unit.trim_start_matches('/'));
+            return sources.contains(&candidate).then_some(candidate);
+        }
+
+        let unit_path = Path::new(unit);
+        let mut candidates = Vec::new();
+        if unit_path.is_absolute() {
+            candidates.push(unit_path.to_path_buf());
+        } else {
+            candidates.push(self.root.join(unit_path));
+            // npm packages, from the innermost node_modules outwards
+            candidates.extend(
+                importer
+                    .ancestors()
+                    .skip(1)
+                    .map(|dir| dir.join(NODE_MODULES).join(unit_path)),
+            );
+        }
+
+        candidates
+            .into_iter()
+            .find(|candidate| candidate.is_file())
+            .map(|found| found.canonicalize().unwrap_or(found))
+    }
+}
+
+/// Find the paths imported by a source file without parsing it.
+///
+/// This is a lexical scan used to discover files before extraction: comments
+/// and string literals are skipped, and for each `import` statement the first
+/// string literal before the closing `;` is returned.
+pub fn scan_imports(source: &str) -> Vec<String> {
+    let bytes = source.as_bytes();
+    let mut imports = Vec::new();
+    let mut in_import = false;
+    let mut found_path = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i += 2;
+            }
+            quote @ (b'"' | b'\'') => {
+                let start = i + 1;
+                i = start;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                if in_import && !found_path {
+                    imports.push(source[start..i.min(bytes.len())].to_string());
+                    found_path = true;
+                }
+                i += 1;
+            }
+            b';' => {
+                in_import = false;
+                i += 1;
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' || c == b'$' => {
+                let start = i;
+                while i < bytes.len()
+                    && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'_' | b'$'))
+                {
+                    i += 1;
+                }
+                // `import` is a keyword, but not after `.` (a member access)
+                let after_dot = bytes[..start]
+                    .iter()
+                    .rev()
+                    .find(|b| !b.is_ascii_whitespace())
+                    == Some(&b'.');
+                if &source[start..i] == "import" && !after_dot && !in_import {
+                    in_import = true;
+                    found_path = false;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    imports
+}
+
+/// Read a remappings file (one remapping per line), if it exists.
+///
+/// `base` is prepended to the context and target of each remapping, for files
+/// belonging to a dependency.
+fn read_remappings_file(path: &Path, base: &str) -> Vec<Remapping> {
+    match fs::read_to_string(path) {
+        Ok(text) => {
+            let lines: Vec<String> = text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect();
+            parse_remappings(&lines, base)
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Parse remappings, logging and skipping invalid ones.
+fn parse_remappings(lines: &[String], base: &str) -> Vec<Remapping> {
+    lines
+        .iter()
+        .filter_map(|line| match line.parse::<Remapping>() {
+            Ok(mut remapping) => {
+                if !base.is_empty() {
+                    remapping.context = format!("{}{}", base, remapping.context);
+                    remapping.target = format!("{}{}", base, remapping.target);
+                }
+                Some(remapping)
+            }
+            Err(e) => {
+                warn!("{:#}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Generate the remappings Forge derives from a dependency directory.
+///
+/// Each package `lib/<name>` is mapped as `<name>/` to its `src/` or
+/// `contracts/` directory (or the package itself), and the package's own
+/// `remappings.txt` applies to files inside it.
+fn dependency_remappings(root: &Path, lib: &str, configured: &[Remapping]) -> Vec<Remapping> {
+    let Ok(entries) = fs::read_dir(root.join(lib)) else {
+        return Vec::new();
+    };
+    let mut packages: Vec<(String, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| {
+            (
+                entry.file_name().to_string_lossy().to_string(),
+                entry.path(),
+            )
+        })
+        .collect();
+    packages.sort();
+
+    let mut remappings = Vec::new();
+    for (name, dir) in packages {
+        let base = format!("{}/{}/", lib.trim_end_matches('/'), name);
+        remappings.extend(read_remappings_file(&dir.join(REMAPPINGS_TXT), &base));
+
+        let prefix = format!("{}/", name);
+        if configured
+            .iter()
+            .any(|r| r.context.is_empty() && r.prefix == prefix)
+        {
+            continue;
+        }
+        let target = ["src", "contracts"]
+            .iter()
+            .find(|sub| dir.join(sub).is_dir())
+            .map_or(base.clone(), |sub| format!("{}{}/", base, sub));
+        remappings.push(Remapping {
+            context: String::new(),
+            prefix,
+            target,
+        });
+    }
+    remappings
+}
+
+/// Normalize a source unit name, removing `.` and `..` segments.
+///
+/// `..` segments that would leave the start of the name are kept.
+fn normalize_unit_name(name: &str) -> String {
+    let absolute = name.starts_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in name.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." if segments.last().is_some_and(|last| *last != "..") => {
+                segments.pop();
+            }
+            ".." if absolute => {}
+            other => segments.push(other),
+        }
+    }
+    let joined = segments.join("/");
+    if absolute {
+        format!("/{}", joined)
+    } else {
+        joined
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchDir;
+
+    fn remapping(s: &str) -> Remapping {
+        s.parse().unwrap()
+    }
+
+    /// Create a scratch project containing the given (empty) files.
+    fn project(name: &str, files: &[&str]) -> ScratchDir {
+        let files: Vec<(&str, &str)> = files.iter().map(|file| (*file, "")).collect();
+        ScratchDir::with_files(name, &files)
+    }
+
+    #[test]
+    fn test_parse_remapping() {
+        assert_eq!(
+            remapping("@oz/=lib/oz/contracts/"),
+            Remapping {
+                context: String::new(),
+                prefix: "@oz/".to_string(),
+                target: "lib/oz/contracts/".to_string(),
+            }
+        );
+        assert_eq!(remapping("src/:ds-test/=lib/ds/").context, "src/");
+        assert!("no-equals".parse::<Remapping>().is_err());
+        assert!("=target".parse::<Remapping>().is_err());
+    }
+
+    #[test]
+    fn test_scan_imports() {
+        let source = r#"
+            // import "./Commented.sol";
+            import "./A.sol";
+            /* import "./Block.sol"; */
+            import {X as Y} from '../B.sol';
+            import * as L from "@oz/C.sol";
+            contract T { string s = "import \"./No.sol\";"; }
+        "#;
+        assert_eq!(
+            scan_imports(source),
+            vec!["./A.sol", "../B.sol", "@oz/C.sol"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_unit_name() {
+        assert_eq!(normalize_unit_name("src/./a/../B.sol"), "src/B.sol");
+        assert_eq!(normalize_unit_name("../x/B.sol"), "../x/B.sol");
+        assert_eq!(normalize_unit_name("/a/../../B.sol"), "/B.sol");
+    }
+
+    #[test]
+    fn test_remapping_selection() {
+        let resolver = ImportResolver::new(
+            Path::new("/p"),
+            vec![
+                remapping("@oz/=lib/oz/"),
+                remapping("@oz/token/=lib/oz-token/"),
+                remapping("test/:@oz/=lib/oz-test/"),
+            ],
+        );
+        // Longest prefix wins
+        assert_eq!(
+            resolver.apply_remappings("src/A.sol", "@oz/token/ERC20.sol"),
+            "lib/oz-token/ERC20.sol"
+        );
+        // Longest context wins over longest prefix
+        assert_eq!(
+            resolver.apply_remappings("test/A.t.sol", "@oz/token/ERC20.sol"),
+            "lib/oz-test/token/ERC20.sol"
+        );
+        assert_eq!(
+            resolver.apply_remappings("src/A.sol", "other/X.sol"),
+            "other/X.sol"
+        );
+    }
+
+    #[test]
+    fn test_resolve_relative_and_remapped() {
+        let root = project(
+            "remapped",
+            &[
+                "src/A.sol",
+                "src/lib/B.sol",
+                "lib/oz/contracts/token/ERC20.sol",
+            ],
+        );
+        let resolver = ImportResolver::new(&root, vec![remapping("@oz/=lib/oz/contracts/")]);
+        let importer = root.join("src/lib/B.sol");
+
+        assert_eq!(
+            resolver.resolve(&importer, "../A.sol"),
+            Some(root.join("src/A.sol"))
+        );
+        assert_eq!(
+            resolver.resolve(&importer, "@oz/token/ERC20.sol"),
+            Some(root.join("lib/oz/contracts/token/ERC20.sol"))
+        );
+        assert_eq!(resolver.resolve(&importer, "./Missing.sol"), None);
+        assert_eq!(
+            resolver.imported_path(&importer, "./Missing.sol"),
+            root.join("src/lib/Missing.sol")
+        );
+    }
+
+    #[test]
+    fn test_resolve_in_memory() {
+        // Nothing exists on disk
+        let root = PathBuf::from("/nonexistent/input");
+        let sources = ["contracts/Token.sol", "lib/oz/ERC20.sol"]
+            .iter()
+            .map(|unit| root.join(unit))
+            .collect();
+        let resolver = ImportResolver::in_memory(&root, &["@oz/=lib/oz/".to_string()], sources);
+        let importer = root.join("contracts/Token.sol");
+
+        assert_eq!(
+            resolver.resolve(&importer, "@oz/ERC20.sol"),
+            Some(root.join("lib/oz/ERC20.sol"))
+        );
+        assert_eq!(
+            resolver.resolve(&importer, "/contracts/Token.sol"),
+            Some(root.join("contracts/Token.sol"))
+        );
+        assert_eq!(resolver.resolve(&importer, "./Missing.sol"), None);
+    }
+
+    #[test]
+    fn test_resolve_node_modules() {
+        let root = project(
+            "npm",
+            &[
+                "contracts/Token.sol",
+                "node_modules/@openzeppelin/contracts/token/ERC20/ERC20.sol",
+            ],
+        );
+        let resolver = ImportResolver::new(&root, Vec::new());
+
+        assert_eq!(
+            resolver.resolve(
+                &root.join("contracts/Token.sol"),
+                "@openzeppelin/contracts/token/ERC20/ERC20.sol"
+            ),
+            Some(root.join("node_modules/@openzeppelin/contracts/token/ERC20/ERC20.sol"))
+        );
+    }
+
+    #[test]
+    fn test_discover_foundry_dependencies() {
+        let root = project(
+            "foundry",
+            &[
+                "foundry.toml",
+                "src/Vault.sol",
+                "lib/forge-std/src/Test.sol",
+                "lib/solmate/src/tokens/ERC20.sol",
+            ],
+        );
+        fs::write(root.join("remappings.txt"), "solmate/=lib/solmate/src/\n").unwrap();
+        let config = FoundryConfig::load(&root).unwrap();
+        let resolver = ImportResolver::discover(&root, config.as_ref());
+        let importer = root.join("src/Vault.sol");
+
+        assert_eq!(
+            resolver.resolve(&importer, "forge-std/Test.sol"),
+            Some(root.join("lib/forge-std/src/Test.sol"))
+        );
+        assert_eq!(
+            resolver.resolve(&importer, "solmate/tokens/ERC20.sol"),
+            Some(root.join("lib/solmate/src/tokens/ERC20.sol"))
+        );
+        // The explicit remapping is not duplicated by a generated one
+        assert_eq!(
+            resolver
+                .remappings
+                .iter()
+                .filter(|r| r.prefix == "solmate/")
+                .count(),
+            1
+        );
+    }
+}
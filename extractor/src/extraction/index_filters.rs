@@ -0,0 +1,151 @@
+//! Code scanning path filters.
+//!
+//! When a code scanning configuration sets `paths` or `paths-ignore`, the
+//! CodeQL CLI passes them to the extractor in `LGTM_INDEX_FILTERS`, one
+//! `include:<glob>` or `exclude:<glob>` per line (and, in older versions, as
+//! lists of paths in `LGTM_INDEX_INCLUDE` and `LGTM_INDEX_EXCLUDE`).
+//!
+//! The semantics are those of the other CodeQL extractors:
+//!
+//! - globs are relative to the source root, `*` matches within a path segment,
+//!   `**` matches any number of segments, and a glob matching a directory
+//!   matches every file below it;
+//! - a file is selected if the last filter matching it is an `include`;
+//! - a file no filter matches is selected unless there are `include` filters.
+
+use globset::{GlobBuilder, GlobMatcher};
+use tracing::{debug, warn};
+
+/// Environment variable holding the filters.
+const FILTERS_VAR: &str = "LGTM_INDEX_FILTERS";
+
+/// Legacy environment variable listing included paths.
+const INCLUDE_VAR: &str = "LGTM_INDEX_INCLUDE";
+
+/// Legacy environment variable listing excluded paths.
+const EXCLUDE_VAR: &str = "LGTM_INDEX_EXCLUDE";
+
+/// One `include:` or `exclude:` filter.
+#[derive(Debug, Clone)]
+struct Filter {
+    /// Whether matching files are included (rather than excluded)
+    include: bool,
+    /// Matcher for the glob and the files below it
+    glob: GlobMatcher,
+    /// Matcher for the files below a matching directory
+    contents: GlobMatcher,
+}
+
+/// Path filters from the code scanning configuration.
+#[derive(Debug, Clone, Default)]
+pub struct IndexFilters {
+    /// Filters, in the order given
+    filters: Vec<Filter>,
+}
+
+impl IndexFilters {
+    /// Read the filters the CodeQL CLI passes in the environment.
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+
+        // The legacy variables list paths; turn them into filter lines
+        let mut text = String::new();
+        for (prefix, name) in [("include", INCLUDE_VAR), ("exclude", EXCLUDE_VAR)] {
+            for path in var(name).lines().filter(|line| !line.trim().is_empty()) {
+                text.push_str(&format!("{}:{}\n", prefix, path));
+            }
+        }
+        text.push_str(&var(FILTERS_VAR));
+        Self::parse(&text)
+    }
+
+    /// Parse filter lines (`include:<glob>` or `exclude:<glob>`).
+    ///
+    /// Malformed lines are logged and ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut filters = Vec::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (include, pattern) = match line.split_once(':') {
+                Some(("include", pattern)) => (true, pattern),
+                Some(("exclude", pattern)) => (false, pattern),
+                _ => {
+                    warn!("Ignoring malformed path filter: {}", line);
+                    continue;
+                }
+            };
+            let pattern = pattern
+                .trim()
+                .trim_start_matches("./")
+                .trim_start_matches('/')
+                .trim_end_matches('/');
+            let pattern = if pattern.is_empty() { "**" } else { pattern };
+            match (matcher(pattern), matcher(&format!("{}/**", pattern))) {
+                (Some(glob), Some(contents)) => {
+                    debug!("Path filter: {}", line);
+                    filters.push(Filter {
+                        include,
+                        glob,
+                        contents,
+                    });
+                }
+                _ => warn!("Ignoring path filter with an invalid glob: {}", line),
+            }
+        }
+        IndexFilters { filters }
+    }
+
+    /// Check whether a file (its path relative to the source root) is selected.
+    pub fn selects(&self, relative: &str) -> bool {
+        let default = !self.filters.iter().any(|filter| filter.include);
+        self.filters
+            .iter()
+            .rev()
+            .find(|filter| filter.glob.is_match(relative) || filter.contents.is_match(relative))
+            .map_or(default, |filter| filter.include)
+    }
+}
+
+/// Compile a glob in which `*` does not match `/`.
+fn matcher(glob: &str) -> Option<GlobMatcher> {
+    GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()
+        .ok()
+        .map(|glob| glob.compile_matcher())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_ignore() {
+        let filters = IndexFilters::parse("exclude:test\nexclude:**/mocks/**\nexclude:*.t.sol\n");
+        assert!(filters.selects("src/Token.sol"));
+        assert!(!filters.selects("test/Token.sol"));
+        assert!(!filters.selects("src/mocks/Mock.sol"));
+        assert!(!filters.selects("Token.t.sol"));
+        // Globs are anchored at the source root
+        assert!(filters.selects("src/test/Token.sol"));
+        assert!(filters.selects("src/Token.t.sol"));
+    }
+
+    #[test]
+    fn test_last_matching_filter_wins() {
+        let filters = IndexFilters::parse(
+            "include:contracts\nexclude:contracts/mocks\ninclude:contracts/mocks/Keep.sol\n",
+        );
+        assert!(filters.selects("contracts/Token.sol"));
+        assert!(!filters.selects("contracts/mocks/Mock.sol"));
+        assert!(filters.selects("contracts/mocks/Keep.sol"));
+        // With `include` filters, unmatched files are not selected
+        assert!(!filters.selects("lib/Dep.sol"));
+    }
+
+    #[test]
+    fn test_no_filters() {
+        let filters = IndexFilters::parse("\nbogus line\n");
+        assert!(filters.selects("anything/A.sol"));
+        assert!(IndexFilters::default().selects("A.sol"));
+    }
+}
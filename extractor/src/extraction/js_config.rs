@@ -0,0 +1,361 @@
+//! Hardhat and Truffle project configuration.
+//!
+//! These tools are configured in JavaScript (or TypeScript), which the
+//! extractor does not execute. Instead the configuration file is scanned for
+//! the few path settings that affect extraction, written as string literals
+//! (`sources: "./src"`) or as `path.join(__dirname, "src")`. Settings that
+//! cannot be read statically fall back to the tool's defaults.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Hardhat configuration file names, in order of preference.
+pub const HARDHAT_CONFIGS: &[&str] = &[
+    "hardhat.config.js",
+    "hardhat.config.ts",
+    "hardhat.config.cjs",
+    "hardhat.config.mjs",
+    "hardhat.config.cts",
+];
+
+/// Truffle configuration file names, in order of preference.
+pub const TRUFFLE_CONFIGS: &[&str] = &["truffle-config.js", "truffle.js"];
+
+/// Settings read from a Hardhat configuration file (`paths`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct HardhatConfig {
+    /// Contracts directory (`paths.sources`, default: `contracts`)
+    pub sources: String,
+    /// Tests directory (`paths.tests`, default: `test`)
+    pub tests: String,
+    /// Compiler cache directory (`paths.cache`, default: `cache`)
+    pub cache: String,
+    /// Build artifacts directory (`paths.artifacts`, default: `artifacts`)
+    pub artifacts: String,
+}
+
+impl HardhatConfig {
+    /// Load the Hardhat configuration of a project directory, if there is one.
+    pub fn load(project_root: &Path) -> Result<Option<Self>> {
+        Ok(read_config(project_root, HARDHAT_CONFIGS)?.map(|text| Self::parse(&text)))
+    }
+
+    /// Read the path settings from the text of a Hardhat configuration file.
+    ///
+    /// Only the properties of the `paths` object are read, as other objects
+    /// (plugin options, say) may have properties of the same names.
+    pub fn parse(text: &str) -> Self {
+        let text = strip_comments(text);
+        let paths = object_property(&text, "paths").unwrap_or_default();
+        let setting = |key: &str, default: &str| {
+            string_property(paths, key).unwrap_or_else(|| default.to_string())
+        };
+        HardhatConfig {
+            sources: setting("sources", "contracts"),
+            tests: setting("tests", "test"),
+            cache: setting("cache", "cache"),
+            artifacts: setting("artifacts", "artifacts"),
+        }
+    }
+}
+
+/// Settings read from a Truffle configuration file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TruffleConfig {
+    /// Contracts directory (`contracts_directory`, default: `contracts`)
+    pub contracts: String,
+    /// Tests directory (`test_directory`, default: `test`)
+    pub test: String,
+    /// Migrations directory (`migrations_directory`, default: `migrations`)
+    pub migrations: String,
+    /// Build artifacts directory (`contracts_build_directory`, default: `build`)
+    pub build: String,
+}
+
+impl TruffleConfig {
+    /// Load the Truffle configuration of a project directory, if there is one.
+    pub fn load(project_root: &Path) -> Result<Option<Self>> {
+        Ok(read_config(project_root, TRUFFLE_CONFIGS)?.map(|text| Self::parse(&text)))
+    }
+
+    /// Read the path settings from the text of a Truffle configuration file.
+    pub fn parse(text: &str) -> Self {
+        let text = strip_comments(text);
+        let setting = |key: &str, default: &str| {
+            string_property(&text, key).unwrap_or_else(|| default.to_string())
+        };
+        TruffleConfig {
+            contracts: setting("contracts_directory", "contracts"),
+            test: setting("test_directory", "test"),
+            migrations: setting("migrations_directory", "migrations"),
+            build: setting("contracts_build_directory", "build"),
+        }
+    }
+}
+
+/// Read the first of the given configuration files that exists.
+fn read_config(project_root: &Path, names: &[&str]) -> Result<Option<String>> {
+    for name in names {
+        let path = project_root.join(name);
+        if path.is_file() {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            return Ok(Some(text));
+        }
+    }
+    Ok(None)
+}
+
+/// Replace comments with spaces, leaving string literals intact.
+fn strip_comments(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        result.push('\n');
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                result.push(' ');
+            }
+            '"' | '\'' | '`' => {
+                result.push(c);
+                while let Some(s) = chars.next() {
+                    result.push(s);
+                    if s == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            result.push(escaped);
+                        }
+                    } else if s == c {
+                        break;
+                    }
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Find the values of the `key: <value>` properties and `x.key = <value>`
+/// assignments, each with the text following it.
+fn property_values<'s>(source: &'s str, key: &'s str) -> impl Iterator<Item = &'s str> + 's {
+    let mut search_from = 0;
+    std::iter::from_fn(move || {
+        while let Some(found) = source[search_from..].find(key) {
+            let start = search_from + found;
+            let end = start + key.len();
+            search_from = end;
+
+            // The key must be a whole identifier, optionally quoted
+            let before = source[..start].chars().next_back();
+            let after = source[end..].chars().next();
+            let is_ident =
+                |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$');
+            if is_ident(before) || is_ident(after) {
+                continue;
+            }
+            let rest = source[end..].trim_start_matches(['"', '\'']).trim_start();
+            if let Some(value) = rest.strip_prefix(':') {
+                return Some(value.trim_start());
+            }
+            let assigned = rest
+                .strip_prefix('=')
+                .filter(|v| !v.starts_with(['=', '>']));
+            if let (Some('.'), Some(value)) = (before, assigned) {
+                return Some(value.trim_start());
+            }
+        }
+        None
+    })
+}
+
+/// Find the value of the first `key: <path>` property with a static value.
+///
+/// The value may be a string literal (without interpolation) or a call to
+/// `path.join`/`path.resolve` whose arguments are `__dirname` and string
+/// literals.
+fn string_property(source: &str, key: &str) -> Option<String> {
+    property_values(source, key).find_map(static_path)
+}
+
+/// Find the body of the first `key: { ... }` property whose value is an
+/// object literal.
+fn object_property<'s>(source: &'s str, key: &'s str) -> Option<&'s str> {
+    property_values(source, key).find_map(object_literal)
+}
+
+/// Read an object literal at the start of `text`, returning its body.
+fn object_literal(text: &str) -> Option<&str> {
+    let body = text.strip_prefix('{')?;
+    let mut depth = 0;
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(&body[..i]),
+            '}' => depth -= 1,
+            '"' | '\'' | '`' => {
+                while let Some((_, s)) = chars.next() {
+                    if s == '\\' {
+                        chars.next();
+                    } else if s == c {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Read a static path expression at the start of `text`.
+fn static_path(text: &str) -> Option<String> {
+    if let Some((literal, _)) = string_literal(text) {
+        return Some(literal);
+    }
+
+    let arguments = ["path.join(", "path.resolve("]
+        .iter()
+        .find_map(|call| text.strip_prefix(call))?;
+    let mut segments = Vec::new();
+    let mut rest = arguments.trim_start();
+    loop {
+        if let Some(after) = rest.strip_prefix("__dirname") {
+            rest = after;
+        } else if let Some((literal, after)) = string_literal(rest) {
+            segments.push(literal);
+            rest = after;
+        } else {
+            return None;
+        }
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+        } else if rest.starts_with(')') {
+            break;
+        } else {
+            return None;
+        }
+    }
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments.join("/"))
+    }
+}
+
+/// Read a string literal at the start of `text`, returning it and the rest.
+///
+/// Template literals with substitutions are not static, so they are rejected.
+fn string_literal(text: &str) -> Option<(String, &str)> {
+    let quote = text
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '"' | '\'' | '`'))?;
+    let body = &text[1..];
+    let end = body.find(quote)?;
+    let literal = &body[..end];
+    if literal.contains('\\') || (quote == '`' && literal.contains("${")) {
+        return None;
+    }
+    Some((literal.to_string(), &body[end + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hardhat_config() {
+        let config = HardhatConfig::parse(
+            r#"
+            // sources: "commented-out",
+            require("@nomicfoundation/hardhat-toolbox");
+            module.exports = {
+              solidity: "0.8.24",
+              networks: { mainnet: { url: "https://eth.example/v2/key" } },
+              paths: {
+                sources: "./src",
+                tests: path.join(__dirname, "test", "hardhat"),
+                artifacts: `./build/${network}`,
+              },
+            };
+            "#,
+        );
+        assert_eq!(config.sources, "./src");
+        assert_eq!(config.tests, "test/hardhat");
+        assert_eq!(config.cache, "cache");
+        // Not static, so the default is used
+        assert_eq!(config.artifacts, "artifacts");
+    }
+
+    #[test]
+    fn test_hardhat_paths_only_come_from_paths() {
+        let config = HardhatConfig::parse(
+            r#"
+            module.exports = {
+              docgen: { sources: "./docs", templates: "{x}" },
+              paths: { tests: "./spec" },
+              cache: "./not-a-path-setting",
+            };
+            "#,
+        );
+        assert_eq!(config.sources, "contracts");
+        assert_eq!(config.tests, "./spec");
+        assert_eq!(config.cache, "cache");
+
+        let config = HardhatConfig::parse(
+            r#"
+            const config = { solidity: "0.8.24" };
+            config.paths = { sources: "./src" };
+            module.exports = config;
+            "#,
+        );
+        assert_eq!(config.sources, "./src");
+
+        // Without a `paths` object, every setting is the default
+        let config = HardhatConfig::parse(r#"module.exports = { sources: "./src" };"#);
+        assert_eq!(config.sources, "contracts");
+    }
+
+    #[test]
+    fn test_parse_truffle_config() {
+        let config = TruffleConfig::parse(
+            r#"
+            module.exports = {
+              contracts_directory: './solidity',
+              "contracts_build_directory": "./out/contracts",
+              compilers: { solc: { version: "0.8.19" } },
+            };
+            "#,
+        );
+        assert_eq!(config.contracts, "./solidity");
+        assert_eq!(config.build, "./out/contracts");
+        assert_eq!(config.test, "test");
+        assert_eq!(config.migrations, "migrations");
+    }
+
+    #[test]
+    fn test_strip_comments_keeps_strings() {
+        assert_eq!(
+            strip_comments("a = \"http://x\"; // c\nb /* d */ = 1"),
+            "a = \"http://x\"; \nb   = 1"
+        );
+    }
+}
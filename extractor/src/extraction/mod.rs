@@ -6,32 +6,145 @@
 //! - AST traversal and TRAP generation
 //! - Source archive management
 
+mod ast_dump;
+mod bundle;
+mod cache;
+mod config;
+mod diagnostics;
 mod extractor;
+mod foundry;
+mod import_graph;
+mod imports;
+mod index_filters;
+mod js_config;
+mod names;
+mod project;
+mod report;
+mod session;
+mod solc_ast;
+mod source_root;
+mod source_text;
+mod standard_json;
+mod types;
 
 use anyhow::{Context, Result};
-use rayon::prelude::*;
+use std::any::Any;
+use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use tracing::{error, info, warn};
-use walkdir::WalkDir;
+use std::sync::Arc;
+use std::time::Instant;
+use thiserror::Error;
+use tracing::{debug, info, warn};
 
-use crate::trap::Compression;
+use crate::trap::{remove_trap_file, Compression, TrapFilter, TrapValue, TrapWriter};
 
-pub use extractor::Extractor;
+use bundle::{write_deployed_contract, DeployedContract};
+use cache::{CacheKey, TrapCache};
+use extractor::{new_parser, parse_source};
+use import_graph::DiffReason;
+use imports::{scan_imports, ImportResolver};
+use names::{file_symbols, FileSymbols, SymbolIndex};
+use report::REPORT_FILE;
+use source_text::SourceText;
+use standard_json::{write_compiler_settings, CompilerSettings};
+
+pub use ast_dump::{dump_ast, parse_ast, AstFormat, AstNode};
+pub use bundle::VerifiedBundle;
+pub use config::ExtractorConfig;
+pub use diagnostics::{Diagnostic, DiagnosticLocation, DiagnosticsWriter};
+pub use extractor::{Extractor, FileLimits, LimitExceeded, ParsedSource};
+pub use project::{Project, Workspace};
+pub use report::{ExtractionReport, FileReport, FileStats, FileStatus};
+pub use session::{ExtractionResults, ExtractionSession, FileResult, SourceInput};
+pub use source_root::SourceRoot;
+pub use standard_json::StandardJsonInput;
+
+/// Reasons a single file can fail to extract.
+#[derive(Debug, Error)]
+pub enum FileError {
+    /// The source file could not be read
+    #[error("Failed to read file: {0}")]
+    Read(#[source] io::Error),
+    /// The source file could not be parsed or converted to TRAP
+    #[error("Failed to extract file: {0:#}")]
+    Extract(anyhow::Error),
+    /// The TRAP file could not be written
+    #[error("Failed to write TRAP file: {0:#}")]
+    WriteTrap(anyhow::Error),
+    /// A directory was given instead of a source file
+    #[error("Skipped: it is a directory")]
+    Directory,
+    /// The source file could not be copied into the source archive
+    #[error("Failed to copy to archive: {0}")]
+    Archive(#[source] io::Error),
+    /// The source file exceeded a limit, so it was skipped
+    #[error("Skipped: {0}")]
+    LimitExceeded(#[source] LimitExceeded),
+    /// The extractor panicked while extracting the file
+    #[error("Extractor panicked: {0}")]
+    Panic(String),
+    /// A solc standard-JSON input could not be read
+    #[error("Failed to read standard JSON input: {0:#}")]
+    StandardJson(anyhow::Error),
+    /// A verified-source bundle could not be read
+    #[error("Failed to read verified-source bundle: {0:#}")]
+    Bundle(anyhow::Error),
+}
+
+impl FileError {
+    /// Build the status-page diagnostic describing this failure.
+    pub fn to_diagnostic(&self, file: &Path) -> Diagnostic {
+        match self {
+            FileError::Read(e) => Diagnostic::read_failure(file, &e.to_string()),
+            FileError::StandardJson(e) | FileError::Bundle(e) => {
+                Diagnostic::read_failure(file, &format!("{:#}", e))
+            }
+            FileError::Extract(e) | FileError::WriteTrap(e) => {
+                Diagnostic::extraction_failure(file, &format!("{:#}", e))
+            }
+            FileError::Archive(e) => Diagnostic::archive_failure(file, &e.to_string()),
+            FileError::Directory => Diagnostic::skipped_file(file, "it is a directory"),
+            FileError::LimitExceeded(e) => Diagnostic::skipped_file(file, &e.to_string()),
+            FileError::Panic(message) => Diagnostic::extraction_failure(
+                file,
+                &format!("the extractor crashed ({})", message),
+            ),
+        }
+    }
+
+    /// Check whether the file was skipped on purpose rather than failing.
+    pub fn is_skip(&self) -> bool {
+        matches!(self, FileError::Directory | FileError::LimitExceeded(_))
+    }
+}
 
 /// Options for the extract command.
 pub struct ExtractOptions {
-    /// File containing list of source files to extract
-    pub file_list: PathBuf,
+    /// File containing list of source files to extract (None = only bundles)
+    pub file_list: Option<PathBuf>,
+    /// Verified-source bundles to extract (see `VerifiedBundle`)
+    pub bundles: Vec<PathBuf>,
     /// Output directory for TRAP files
     pub trap_dir: PathBuf,
     /// Output directory for source archive
     pub source_archive_dir: PathBuf,
-    /// Compression mode
-    pub compression: Compression,
+    /// Compression mode (None = the configuration file's, or gzip)
+    pub compression: Option<Compression>,
     /// Number of threads (None = use all available)
     pub threads: Option<usize>,
+    /// Source root, recorded in `sourceLocationPrefix` (None = current directory)
+    pub source_root: Option<PathBuf>,
+    /// Directory for reusing TRAP files between runs (None = no cache)
+    pub trap_cache: Option<PathBuf>,
+    /// File listing the files changed by a pull request (None = extract
+    /// everything); the database covers only the files they affect
+    pub changed_files: Option<PathBuf>,
+    /// Path of the JSON extraction report (None = `extraction-report.json` in
+    /// the TRAP directory)
+    pub report: Option<PathBuf>,
 }
 
 /// Options for the autobuild command.
@@ -42,77 +155,141 @@ pub struct AutobuildOptions {
     pub trap_dir: PathBuf,
     /// Output directory for source archive
     pub source_archive_dir: PathBuf,
+    /// Source root, recorded in `sourceLocationPrefix` (None = `root`)
+    pub source_root: Option<PathBuf>,
+    /// Compression mode (None = the configuration file's, or gzip)
+    pub compression: Option<Compression>,
+    /// Directory for reusing TRAP files between runs (None = no cache)
+    pub trap_cache: Option<PathBuf>,
+    /// File listing the files changed by a pull request (None = extract
+    /// everything); the database covers only the files they affect
+    pub changed_files: Option<PathBuf>,
+    /// Path of the JSON extraction report (None = `extraction-report.json` in
+    /// the TRAP directory)
+    pub report: Option<PathBuf>,
 }
 
-/// Run extraction on a list of files.
-pub fn run(options: ExtractOptions) -> Result<()> {
-    // Configure thread pool if specified
-    if let Some(threads) = options.threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .context("Failed to configure thread pool")?;
-    }
-
-    // Ensure output directories exist
-    fs::create_dir_all(&options.trap_dir).context("Failed to create TRAP directory")?;
-    fs::create_dir_all(&options.source_archive_dir)
-        .context("Failed to create source archive directory")?;
+/// Options for the extract-file command.
+pub struct ExtractFileOptions {
+    /// Source file to extract (None = read standard input)
+    pub file: Option<PathBuf>,
+    /// Path of the source read from standard input, relative to the
+    /// source root
+    pub stdin_path: PathBuf,
+    /// Source root, recorded in `sourceLocationPrefix` (None = current directory)
+    pub source_root: Option<PathBuf>,
+    /// Part of the TRAP to print
+    pub filter: TrapFilter,
+}
 
-    // Read file list
-    let file_list = File::open(&options.file_list)
-        .with_context(|| format!("Failed to open file list: {}", options.file_list.display()))?;
-    let reader = BufReader::new(file_list);
-    let files: Vec<PathBuf> = reader
-        .lines()
-        .map_while(Result::ok)
-        .filter(|line| !line.trim().is_empty())
-        .map(PathBuf::from)
-        .collect();
+/// Settings shared by every file processed in a run.
+struct RunContext<'a> {
+    /// Output directory for TRAP files
+    trap_dir: &'a Path,
+    /// Output directory for source archive
+    source_archive_dir: &'a Path,
+    /// Compression mode
+    compression: Compression,
+    /// Source root, recorded in `sourceLocationPrefix`
+    source_root: &'a SourceRoot,
+    /// Projects the files belong to
+    workspace: &'a Workspace,
+    /// TRAP files from previous runs, if caching is enabled
+    cache: Option<&'a TrapCache>,
+    /// Limits on the work done for each file
+    limits: FileLimits,
+    /// Import resolvers of files from standard-JSON inputs and bundles, which
+    /// resolve imports among the input's sources
+    source_imports: &'a HashMap<PathBuf, Arc<ImportResolver>>,
+    /// Names declared by the files being extracted
+    symbols: SymbolIndex,
+}
 
-    info!("Processing {} files", files.len());
+/// Run extraction on a list of files.
+///
+/// Settings not given in `options` are read from the `.codeql-solidity.yml`
+/// configuration file at the source root, if there is one.
+pub fn run(options: ExtractOptions) -> Result<()> {
+    let config = match &options.source_root {
+        Some(root) => ExtractorConfig::load(root)?,
+        None => ExtractorConfig::load(&std::env::current_dir()?)?,
+    };
+    run_with_config(options, config)
+}
 
-    // Process files in parallel
-    let results: Vec<Result<(), String>> = files
-        .par_iter()
+/// Run extraction on a list of files with the given configuration.
+///
+/// Unlike an [`ExtractionSession`], this fails when too many files fail to
+/// extract.
+fn run_with_config(options: ExtractOptions, config: ExtractorConfig) -> Result<()> {
+    // Listed paths are relative to the current directory; `.json` files that
+    // are solc standard-JSON inputs are read as such
+    let current_dir = std::env::current_dir()?;
+    let file_list = match &options.file_list {
+        Some(file_list) => read_file_list(file_list)?,
+        None => Vec::new(),
+    };
+    let mut listed: Vec<SourceInput> = file_list
+        .into_iter()
         .map(|file| {
-            process_file(
-                file.as_path(),
-                options.trap_dir.as_path(),
-                options.source_archive_dir.as_path(),
-                options.compression,
-            )
-            .map_err(|e| format!("{}: {}", file.display(), e))
+            let file = current_dir.join(file);
+            if file.extension().is_some_and(|ext| ext == "json")
+                && StandardJsonInput::is_standard_json(&file)
+            {
+                SourceInput::StandardJson(file)
+            } else {
+                SourceInput::Path(file)
+            }
         })
         .collect();
+    listed.extend(
+        options
+            .bundles
+            .iter()
+            .map(|bundle| SourceInput::Bundle(current_dir.join(bundle))),
+    );
 
-    // Report errors
-    let mut success_count = 0;
-    let mut error_count = 0;
-    for result in results {
-        match result {
-            Ok(()) => success_count += 1,
-            Err(e) => {
-                error!("{}", e);
-                error_count += 1;
-            }
-        }
+    let report = options
+        .report
+        .unwrap_or_else(|| options.trap_dir.join(REPORT_FILE));
+    let mut session = ExtractionSession::new(options.trap_dir, options.source_archive_dir)
+        .with_source_root(options.source_root.unwrap_or(current_dir))
+        .with_config(config)
+        .with_report(report);
+    if let Some(compression) = options.compression {
+        session = session.with_compression(compression);
+    }
+    if let Some(threads) = options.threads {
+        session = session.with_threads(threads);
+    }
+    if let Some(trap_cache) = options.trap_cache {
+        session = session.with_trap_cache(trap_cache);
+    }
+    if let Some(changed_files) = &options.changed_files {
+        session = session.with_changed_files(read_file_list(changed_files)?);
     }
 
-    info!(
-        "Extraction complete: {} succeeded, {} failed",
-        success_count, error_count
-    );
+    let results = session.extract(listed)?;
 
-    if error_count > 0 && error_count == files.len() {
+    // Files skipped on purpose are neither successes nor failures
+    let error_count = results.failed();
+    let success_count = results.succeeded();
+    let attempted = error_count + success_count;
+    let diagnostics = session.diagnostics();
+    if error_count > 0 && error_count == attempted {
+        diagnostics.write(&Diagnostic::extraction_aborted(error_count, attempted));
         anyhow::bail!("All {} files failed to extract", error_count);
-    } else if error_count > 0 && error_count > files.len() / 2 {
+    } else if error_count > 0
+        && error_count as f64 > session.config().max_failure_ratio * attempted as f64
+    {
+        diagnostics.write(&Diagnostic::extraction_aborted(error_count, attempted));
         anyhow::bail!(
             "Too many extraction failures: {}/{} files failed",
             error_count,
-            files.len()
+            attempted
         );
     } else if error_count > 0 {
+        diagnostics.write(&Diagnostic::partial_failure(error_count, attempted));
         warn!(
             "{} files failed to extract (continuing with {} successful)",
             error_count, success_count
@@ -122,28 +299,135 @@ pub fn run(options: ExtractOptions) -> Result<()> {
     Ok(())
 }
 
-/// Run autobuild: find all .sol files and extract them.
-pub fn autobuild(options: AutobuildOptions) -> Result<()> {
-    // Find all .sol files
-    let files: Vec<PathBuf> = WalkDir::new(&options.root)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "sol")
-        })
-        .filter(|entry| {
-            // Skip node_modules and other common excluded directories
-            let path = entry.path();
-            !path.components().any(|c| {
-                matches!(
-                    c.as_os_str().to_str(),
-                    Some("node_modules" | ".git" | "cache")
-                )
-            })
-        })
-        .map(|entry| entry.into_path())
+/// Extract a single file and write its uncompressed TRAP to `out`.
+///
+/// The TRAP is that of a full run, with the file's project and imports
+/// resolved against the source root, but nothing is written to disk. Names
+/// are bound to the declarations of imported files, whose rows are not part
+/// of the output, so no rows referring to them are written.
+pub fn extract_single(options: ExtractFileOptions, out: &mut impl Write) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let source_root = SourceRoot::new(options.source_root.as_ref().unwrap_or(&current_dir))?;
+    let config = ExtractorConfig::load(source_root.path())?;
+
+    let file = match &options.file {
+        Some(file) => source_root.canonicalize(&current_dir.join(file)),
+        None => source_root.canonicalize(&source_root.path().join(&options.stdin_path)),
+    };
+    let source = read_source(options.file.as_ref().map(|_| file.as_path()))?;
+    let recorded = source_root.recorded_path(&file);
+    let workspace = Workspace::detect(source_root.path()).with_remappings(&config.remappings);
+    let project = workspace.project_for(&file);
+
+    let compiled = project.build_info().find(&file, &source.text);
+    let mut extractor = Extractor::new(&recorded).with_limits(config.limits);
+    if compiled.is_some() {
+        extractor = extractor.with_node_spans();
+    }
+    extractor.extract(&source.text)?;
+    extractor.emit_project(
+        &source_root.recorded_path(project.root()),
+        project.framework().as_str(),
+    );
+    extractor.emit_file_role(project.role_of(&file).as_str());
+    extractor.resolve_imports(|import| {
+        project
+            .imports()
+            .resolve(&file, import)
+            .map(|target| source_root.recorded_path(&target))
+    });
+    let imported = extractor
+        .imports()
+        .iter()
+        .filter_map(|import| import.resolved.clone())
         .collect();
+    let symbols = index_imports(imported, &source_root, &workspace, &config.limits);
+    extractor.resolve_names(&source.text, &symbols);
+    if let Some(compiled) = compiled {
+        extractor.emit_compiler_ast(&source_root.recorded_path(&compiled.build.path), compiled);
+    }
+
+    out.write_all(options.filter.apply(&extractor.trap_text()).as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Collect the names of the given files (database paths) and of the files
+/// they import, directly or not, resolving imports in the projects the files
+/// belong to. The files are excluded from the database: only their names are
+/// used.
+fn index_imports(
+    files: Vec<String>,
+    source_root: &SourceRoot,
+    workspace: &Workspace,
+    limits: &FileLimits,
+) -> SymbolIndex {
+    let mut index = SymbolIndex::default();
+    let mut pending = files;
+    while let Some(path) = pending.pop() {
+        if index.get(&path).is_some() {
+            continue;
+        }
+        let file = PathBuf::from(&path);
+        let project = workspace.project_for(&file);
+        let symbols = SourceText::read(&file)
+            .ok()
+            .and_then(|source| {
+                let tree = parse_source(&mut new_parser().ok()?, &source.text, limits).ok()?;
+                Some(file_symbols(&tree, &source.text, &path, |import| {
+                    project
+                        .imports()
+                        .resolve(&file, import)
+                        .map(|target| source_root.recorded_path(&target))
+                }))
+            })
+            .unwrap_or_else(|| FileSymbols {
+                path: path.clone(),
+                ..FileSymbols::default()
+            });
+        pending.extend(symbols.imports.iter().filter_map(|i| i.file.clone()));
+        index.insert(symbols);
+        index.exclude(&path);
+    }
+    index
+}
+
+/// Print the syntax tree of a single file (None = standard input) to `out`.
+pub fn dump_file(file: Option<&Path>, format: AstFormat, out: &mut impl Write) -> Result<()> {
+    let source = read_source(file)?;
+    dump_ast(&source.text, format, out)
+}
+
+/// Read and decode a source file, or standard input if `file` is None.
+fn read_source(file: Option<&Path>) -> Result<SourceText> {
+    match file {
+        Some(file) => SourceText::read(file)
+            .with_context(|| format!("Failed to read file: {}", file.display())),
+        None => {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .context("Failed to read standard input")?;
+            Ok(SourceText::decode(bytes))
+        }
+    }
+}
+
+/// Run autobuild: find the project's .sol files and extract them.
+pub fn autobuild(options: AutobuildOptions) -> Result<()> {
+    let source_root = options.source_root.unwrap_or(options.root.clone());
+    let config = ExtractorConfig::load(&source_root)?;
+
+    // Find the files each project's build tool would compile
+    let workspace = Workspace::detect(&options.root).with_remappings(&config.remappings);
+    for project in workspace.projects() {
+        info!(
+            "Project: {} (framework: {})",
+            project.root().display(),
+            project.framework()
+        );
+    }
+    let files = workspace.discover_files(config.gitignore);
 
     if files.is_empty() {
         warn!("No Solidity files found in {}", options.root.display());
@@ -153,6 +437,7 @@ pub fn autobuild(options: AutobuildOptions) -> Result<()> {
     info!("Found {} Solidity files", files.len());
 
     // Create temporary file list
+    fs::create_dir_all(&options.trap_dir).context("Failed to create TRAP directory")?;
     let file_list = options.trap_dir.join("file_list.txt");
     fs::write(
         &file_list,
@@ -164,54 +449,542 @@ pub fn autobuild(options: AutobuildOptions) -> Result<()> {
     )?;
 
     // Run extraction
-    run(ExtractOptions {
-        file_list,
-        trap_dir: options.trap_dir,
-        source_archive_dir: options.source_archive_dir,
-        compression: Compression::from_env(),
-        threads: None,
-    })
+    run_with_config(
+        ExtractOptions {
+            file_list: Some(file_list),
+            bundles: Vec::new(),
+            trap_dir: options.trap_dir,
+            source_archive_dir: options.source_archive_dir,
+            compression: options.compression,
+            threads: None,
+            source_root: Some(source_root),
+            trap_cache: options.trap_cache,
+            changed_files: options.changed_files,
+            report: options.report,
+        },
+        config,
+    )
 }
 
-/// Process a single file.
-fn process_file(
-    file: &Path,
+/// Read a list of files, one per line.
+fn read_file_list(path: &Path) -> Result<Vec<PathBuf>> {
+    let file_list = File::open(path)
+        .with_context(|| format!("Failed to open file list: {}", path.display()))?;
+    let reader = BufReader::new(file_list);
+    Ok(reader
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Write the `solidity_diff_scope` TRAP file recording why each file was extracted.
+fn write_diff_scope(
     trap_dir: &Path,
-    source_archive_dir: &Path,
+    source_root: &SourceRoot,
+    scope: &[(PathBuf, DiffReason)],
     compression: Compression,
 ) -> Result<()> {
-    // Read source file
-    let source = fs::read_to_string(file)
-        .with_context(|| format!("Failed to read file: {}", file.display()))?;
+    let mut trap = TrapWriter::new("diffScope");
+    for (file, reason) in scope {
+        let file_label = trap.emit_file(&source_root.recorded_path(file));
+        trap.emit(
+            "solidity_diff_scope",
+            vec![
+                TrapValue::Label(file_label),
+                TrapValue::String(reason.as_str().to_string()),
+            ],
+        );
+    }
+    trap.write_to_file(&trap_dir.join("diffScope"), compression)
+        .context("Failed to write diff scope TRAP file")
+}
 
-    // Get canonical path
-    let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
-    let file_str = canonical.to_string_lossy();
+/// Write the TRAP file recording the compiler settings of a standard-JSON input
+/// or bundle (a database path), the files extracted from it and, for a bundle,
+/// the deployed contract.
+fn write_compiler_settings_file(
+    trap_dir: &Path,
+    input: &str,
+    settings: &CompilerSettings,
+    contract: Option<&DeployedContract>,
+    files: &[String],
+    compression: Compression,
+) -> Result<()> {
+    let trap_path = compute_trap_path(
+        &trap_dir.join("compilerSettings"),
+        Path::new(input),
+        compression,
+    );
+    if let Some(parent) = trap_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut trap = TrapWriter::new("compilerSettings");
+    let settings = write_compiler_settings(&mut trap, input, settings, files);
+    if let Some(contract) = contract {
+        write_deployed_contract(&mut trap, input, &settings, contract);
+    }
+    trap.write_to_file(&trap_path, compression)
+        .context("Failed to write compiler settings TRAP file")
+}
 
-    // Create extractor and process
-    let mut extractor = Extractor::new(&file_str);
-    extractor.extract(&source)?;
+/// Write the `sourceLocationPrefix` TRAP file for the database.
+fn write_source_location_prefix(
+    trap_dir: &Path,
+    source_root: &SourceRoot,
+    compression: Compression,
+) -> Result<()> {
+    let mut trap = TrapWriter::new("sourceLocationPrefix");
+    trap.emit(
+        "sourceLocationPrefix",
+        vec![TrapValue::String(source_root.prefix())],
+    );
+    trap.write_to_file(&trap_dir.join("sourceLocationPrefix"), compression)
+        .context("Failed to write sourceLocationPrefix TRAP file")
+}
+
+/// Process a single file, given its canonical path.
+///
+/// `text` is the contents of the file, if they were given rather than being on
+/// disk. The status-page diagnostics to report for the file are added to
+/// `diagnostics`.
+fn process_file(
+    file: &Path,
+    text: Option<&str>,
+    parsed: Option<ParsedFile>,
+    context: &RunContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<FileStats, FileError> {
+    // Read source file, unless it was read while collecting its names
+    let (source, parsed) = match parsed {
+        Some(ParsedFile { source, parsed }) => (source, Some(parsed)),
+        None => (file_text(file, text)?, None),
+    };
+    let SourceText {
+        text: source,
+        had_bom,
+        invalid_sequences,
+    } = source;
+
+    // Get the path recorded in the database, and the one shown to users
+    let recorded = context.source_root.recorded_path(file);
+    let recorded_path = Path::new(&recorded);
+    let relative = context.source_root.relative_path(file);
+    let project = context.workspace.project_for(file);
+
+    if had_bom {
+        debug!("{}: removed byte order mark", relative);
+    }
+    if invalid_sequences > 0 {
+        warn!(
+            "{}: {} invalid UTF-8 sequence(s), decoded lossily",
+            relative, invalid_sequences
+        );
+        diagnostics.push(Diagnostic::invalid_encoding(
+            Path::new(&relative),
+            invalid_sequences,
+        ));
+    }
 
     // Compute output paths
-    let trap_path = compute_trap_path(trap_dir, canonical.as_path(), compression);
-    let archive_path = compute_archive_path(source_archive_dir, canonical.as_path());
+    let compression = context.compression;
+    let trap_path = compute_trap_path(context.trap_dir, recorded_path, compression);
+    let archive_path = compute_archive_path(context.source_archive_dir, recorded_path);
 
     // Ensure parent directories exist
     if let Some(parent) = trap_path.parent() {
-        fs::create_dir_all(parent)?;
+        fs::create_dir_all(parent).map_err(|e| FileError::WriteTrap(anyhow::Error::new(e)))?;
     }
     if let Some(parent) = archive_path.parent() {
-        fs::create_dir_all(parent)?;
+        fs::create_dir_all(parent).map_err(FileError::Archive)?;
     }
 
-    // Write TRAP file
-    extractor.write_trap(&trap_path, compression)?;
+    // Reuse the TRAP file from a previous run if none of its inputs changed
+    let cached = context.cache.map(|cache| {
+        let key = cache_key(file, &source, &recorded, project, context);
+        let diagnostics = cache.fetch(&key, compression, &trap_path);
+        (cache, key, diagnostics)
+    });
+    let (file_diagnostics, mut stats) = match cached {
+        Some((_, _, Some(cached_diagnostics))) => (
+            cached_diagnostics,
+            FileStats {
+                cached: true,
+                ..FileStats::default()
+            },
+        ),
+        Some((cache, key, None)) => {
+            let extracted = extract_file(
+                file, &source, parsed, &recorded, project, context, &trap_path,
+            )?;
+            cache.store(&key, compression, &trap_path, &extracted.0);
+            extracted
+        }
+        None => extract_file(
+            file, &source, parsed, &recorded, project, context, &trap_path,
+        )?,
+    };
+    diagnostics.extend(file_diagnostics);
+
+    // Archive the decoded text, which the recorded locations refer to; a file
+    // that is not archived is left out of the database
+    if let Err(e) = fs::write(&archive_path, &source) {
+        remove_trap_file(&trap_path, compression);
+        return Err(FileError::Archive(e));
+    }
 
-    // Copy to source archive
-    fs::copy(file, &archive_path)
-        .with_context(|| format!("Failed to copy to archive: {}", archive_path.display()))?;
+    stats.bytes_out = fs::metadata(&trap_path).map_or(0, |m| m.len());
+    Ok(stats)
+}
 
-    Ok(())
+/// Check whether the TRAP cache has an entry for a file as it would be
+/// extracted in this run.
+fn is_cached(file: &Path, text: Option<&str>, context: &RunContext) -> bool {
+    let Some(cache) = context.cache else {
+        return false;
+    };
+    let Ok(source) = file_text(file, text) else {
+        return false;
+    };
+    let recorded = context.source_root.recorded_path(file);
+    let project = context.workspace.project_for(file);
+    let key = cache_key(file, &source.text, &recorded, project, context);
+    cache.contains(&key, context.compression)
+}
+
+/// Reuse the cached TRAP of a file outside the scope of a diff-mode run and
+/// archive the file, without extracting it.
+///
+/// Returns None if the file is not cached.
+fn reuse_file(
+    file: &Path,
+    text: Option<&str>,
+    context: &RunContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Option<FileStats>, FileError> {
+    let Some(cache) = context.cache else {
+        return Ok(None);
+    };
+    let source = file_text(file, text)?.text;
+    let recorded = context.source_root.recorded_path(file);
+    let recorded_path = Path::new(&recorded);
+    let project = context.workspace.project_for(file);
+
+    let compression = context.compression;
+    let trap_path = compute_trap_path(context.trap_dir, recorded_path, compression);
+    let archive_path = compute_archive_path(context.source_archive_dir, recorded_path);
+    if let Some(parent) = trap_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| FileError::WriteTrap(anyhow::Error::new(e)))?;
+    }
+    if let Some(parent) = archive_path.parent() {
+        fs::create_dir_all(parent).map_err(FileError::Archive)?;
+    }
+
+    let key = cache_key(file, &source, &recorded, project, context);
+    let Some(cached_diagnostics) = cache.fetch(&key, compression, &trap_path) else {
+        return Ok(None);
+    };
+    diagnostics.extend(cached_diagnostics);
+    if let Err(e) = fs::write(&archive_path, &source) {
+        remove_trap_file(&trap_path, compression);
+        return Err(FileError::Archive(e));
+    }
+    Ok(Some(FileStats {
+        cached: true,
+        bytes_out: fs::metadata(&trap_path).map_or(0, |m| m.len()),
+        ..FileStats::default()
+    }))
+}
+
+/// Read a file, or decode its contents if they were given.
+fn file_text(file: &Path, text: Option<&str>) -> Result<SourceText, FileError> {
+    match text {
+        Some(text) => Ok(SourceText::decode(text.as_bytes().to_vec())),
+        None => SourceText::read(file).map_err(FileError::Read),
+    }
+}
+
+/// A file read and parsed while collecting its names, kept for its
+/// extraction so that it is parsed only once.
+struct ParsedFile {
+    /// Decoded source text
+    source: SourceText,
+    /// Its syntax tree
+    parsed: ParsedSource,
+}
+
+/// Collect the names a file declares and imports, so that they can be bound
+/// in the files importing it.
+///
+/// The names are reused from the cache when the file and how its imports
+/// resolve did not change, so a file whose TRAP is cached is not parsed.
+/// Otherwise the parsed file is returned too, for extracting it. A file that
+/// cannot be read or parsed is not extracted.
+fn summarize_file(
+    file: &Path,
+    text: Option<&str>,
+    context: &RunContext,
+) -> Result<(FileSymbols, Option<ParsedFile>), FileError> {
+    let source = file_text(file, text)?;
+    let recorded = context.source_root.recorded_path(file);
+    let project = context.workspace.project_for(file);
+
+    let cached = context.cache.map(|cache| {
+        (
+            cache,
+            symbols_key(file, &source.text, &recorded, project, context),
+        )
+    });
+    if let Some(symbols) = cached
+        .as_ref()
+        .and_then(|(cache, key)| cache.fetch_symbols(key))
+    {
+        return Ok((symbols, None));
+    }
+
+    // As in extraction, a panic fails only this file
+    let (symbols, parsed) = panic::catch_unwind(AssertUnwindSafe(|| {
+        let parsed = ParsedSource::parse(&source.text, &context.limits).map_err(extract_error)?;
+        let symbols = file_symbols(parsed.tree(), &source.text, &recorded, |import| {
+            resolve_import(file, import, project, context)
+        });
+        Ok((symbols, parsed))
+    }))
+    .unwrap_or_else(|payload| Err(FileError::Panic(panic_message(payload.as_ref()))))?;
+    if let Some((cache, key)) = &cached {
+        cache.store_symbols(key, &symbols);
+    }
+    Ok((symbols, Some(ParsedFile { source, parsed })))
+}
+
+/// Extract a file and write its TRAP file.
+///
+/// Returns the status-page diagnostics to report for the file, and
+/// measurements of the extraction.
+fn extract_file(
+    file: &Path,
+    source: &str,
+    parsed: Option<ParsedSource>,
+    recorded: &str,
+    project: &Project,
+    context: &RunContext,
+    trap_path: &Path,
+) -> Result<(Vec<Diagnostic>, FileStats), FileError> {
+    // A panic (say, in tree-sitter) fails only this file
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        stream_file(file, source, parsed, recorded, project, context, trap_path)
+    }))
+    .unwrap_or_else(|payload| Err(FileError::Panic(panic_message(payload.as_ref()))));
+    if result.is_err() {
+        // Do not leave a truncated TRAP file behind for the CLI to import
+        remove_trap_file(trap_path, context.compression);
+    }
+    result
+}
+
+/// Extract a file, streaming its TRAP into `trap_path`.
+///
+/// The file is parsed unless `parsed` is given.
+fn stream_file(
+    file: &Path,
+    source: &str,
+    parsed: Option<ParsedSource>,
+    recorded: &str,
+    project: &Project,
+    context: &RunContext,
+    trap_path: &Path,
+) -> Result<(Vec<Diagnostic>, FileStats), FileError> {
+    let start = Instant::now();
+    let relative = context.source_root.relative_path(file);
+    let relative_path = Path::new(&relative);
+    let mut diagnostics = Vec::new();
+
+    // Create extractor and process
+    let compiled = project.build_info().find(file, source);
+    let mut extractor = Extractor::create(recorded, trap_path, context.compression)
+        .map_err(FileError::WriteTrap)?
+        .with_limits(context.limits);
+    if compiled.is_some() {
+        extractor = extractor.with_node_spans();
+    }
+    match parsed {
+        Some(parsed) => extractor.extract_parsed(source, parsed),
+        None => extractor.extract(source),
+    }
+    .map_err(extract_error)?;
+    extractor.emit_project(
+        &context.source_root.recorded_path(project.root()),
+        project.framework().as_str(),
+    );
+    extractor.emit_file_role(project.role_of(file).as_str());
+    extractor.resolve_imports(|import| resolve_import(file, import, project, context));
+    extractor.resolve_names(source, &context.symbols);
+    if let Some(compiled) = compiled {
+        let build = context.source_root.recorded_path(&compiled.build.path);
+        let matched = extractor.emit_compiler_ast(&build, compiled);
+        debug!(
+            "{}: matched {} of {} compiler AST nodes from {}",
+            relative,
+            matched,
+            compiled.nodes.len(),
+            build
+        );
+    }
+
+    if let Some(first) = extractor.parse_errors().first() {
+        let count = extractor.parse_error_count();
+        warn!(
+            "{}: {} syntax error(s), extracted partially (first at {}:{}: {})",
+            relative, count, first.start.0, first.start.1, first.message
+        );
+        diagnostics.push(Diagnostic::parse_errors(
+            relative_path,
+            count,
+            DiagnosticLocation::range(relative_path, first.start, first.end),
+        ));
+    }
+
+    let unresolved: Vec<_> = extractor
+        .imports()
+        .iter()
+        .filter(|i| i.resolved.is_none())
+        .collect();
+    for import in &unresolved {
+        warn!("{}: could not resolve import '{}'", relative, import.path);
+    }
+    if let Some(first) = unresolved.first() {
+        let paths: Vec<&str> = unresolved.iter().map(|i| i.path.as_str()).collect();
+        diagnostics.push(Diagnostic::unresolved_imports(
+            relative_path,
+            &paths,
+            DiagnosticLocation::range(relative_path, first.start, first.end),
+        ));
+    }
+
+    for failure in extractor.linearization_failures() {
+        warn!(
+            "{}: could not linearize the bases of contract '{}'",
+            relative, failure.contract
+        );
+        diagnostics.push(Diagnostic::linearization_failure(
+            relative_path,
+            &failure.contract,
+            DiagnosticLocation::range(relative_path, failure.start, failure.end),
+        ));
+    }
+
+    let mut stats = FileStats {
+        parse_time: extractor.parse_time(),
+        nodes: extractor.node_count(),
+        tuples: extractor.tuple_count(),
+        error_nodes: extractor.parse_error_count(),
+        ..FileStats::default()
+    };
+
+    // Flush the TRAP file
+    extractor.finish().map_err(FileError::WriteTrap)?;
+    stats.trap_time = start.elapsed().saturating_sub(stats.parse_time);
+
+    Ok((diagnostics, stats))
+}
+
+/// Get the failure of a file that could not be parsed or extracted.
+fn extract_error(e: anyhow::Error) -> FileError {
+    match e.downcast::<LimitExceeded>() {
+        Ok(limit) => FileError::LimitExceeded(limit),
+        Err(e) => FileError::Extract(e),
+    }
+}
+
+/// Get the message of a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Resolve an import in `file` to the database path of the imported file.
+fn resolve_import(
+    file: &Path,
+    import: &str,
+    project: &Project,
+    context: &RunContext,
+) -> Option<String> {
+    let resolver = context
+        .source_imports
+        .get(file)
+        .map_or(project.imports(), Arc::as_ref);
+    resolver
+        .resolve(file, import)
+        .map(|target| context.source_root.recorded_path(&target))
+}
+
+/// Compute the TRAP cache key for a file.
+///
+/// Besides the source text, the TRAP file depends on the recorded path, the
+/// project the file belongs to, where its imports resolve to, the names
+/// declared in the files it imports (directly or not), the contents of the
+/// build-info file the compiler's AST comes from and the TRAP compression.
+/// Imports are found with the same lexical scan autobuild uses.
+fn cache_key(
+    file: &Path,
+    source: &str,
+    recorded: &str,
+    project: &Project,
+    context: &RunContext,
+) -> CacheKey {
+    let mut key = CacheKey::builder();
+    key.field("path", recorded)
+        .field("project", context.source_root.recorded_path(project.root()))
+        .field("framework", project.framework().as_str())
+        .field("role", project.role_of(file).as_str())
+        .field("compression", context.compression.to_string())
+        .field(
+            "max_ast_nodes",
+            context
+                .limits
+                .max_ast_nodes
+                .map_or(String::new(), |max| max.to_string()),
+        );
+    for import in scan_imports(source) {
+        let target = resolve_import(file, &import, project, context);
+        key.field("import", &import)
+            .field("target", target.unwrap_or_default());
+    }
+    context.symbols.fingerprint(recorded, &mut key);
+    if let Some(compiled) = project.build_info().find(file, source) {
+        key.field(
+            "build_info",
+            context.source_root.recorded_path(&compiled.build.path),
+        )
+        .field("build_info_hash", compiled.build.hash);
+    }
+    key.field("source", source).finish()
+}
+
+/// Compute the cache key of the names a file declares and imports, which
+/// depend on the source text, the recorded path and where its imports resolve
+/// to.
+fn symbols_key(
+    file: &Path,
+    source: &str,
+    recorded: &str,
+    project: &Project,
+    context: &RunContext,
+) -> CacheKey {
+    let mut key = CacheKey::builder();
+    key.field("entry", "symbols").field("path", recorded);
+    for import in scan_imports(source) {
+        let target = resolve_import(file, &import, project, context);
+        key.field("import", &import)
+            .field("target", target.unwrap_or_default());
+    }
+    key.field("source", source).finish()
 }
 
 /// Compute the TRAP file output path.
@@ -256,7 +1029,7 @@ mod tests {
     fn test_compute_trap_path() {
         let trap_dir = PathBuf::from("/tmp/trap");
         let source = PathBuf::from("/home/user/contracts/Token.sol");
-        let path = compute_trap_path(&trap_dir, &source, Compression::Gzip);
+        let path = compute_trap_path(&trap_dir, &source, Compression::default());
 
         assert!(path.to_string_lossy().ends_with(".trap.gz"));
         assert!(path.starts_with("/tmp/trap"));
@@ -0,0 +1,1857 @@
+//! Syntactic name resolution.
+//!
+//! Names are bound to the declarations they refer to from the syntax trees
+//! alone, without a compiler. Lexical scopes are built for each file (its
+//! top-level declarations, free functions among them, and the names it
+//! imports), contract, function, block and Yul block, and recorded in the
+//! `solidity_scope*` tables. Identifiers, members of `this`, `super`,
+//! contracts, enums and imported units, and the builtins (`msg.sender`,
+//! `abi.encode`, `require`, ...) are bound in `solidity_binding`; a
+//! declaration hiding one of the same name in an enclosing scope (or an
+//! inherited one) is recorded in `solidity_shadowing`.
+//!
+//! The C3 linearization of each contract's bases, which member lookups
+//! follow, is recorded in `solidity_contract_linearization`, and the functions
+//! and modifiers an `override` overrides in `solidity_function_override`.
+//!
+//! Names declared in other files are looked up in a [`SymbolIndex`] of every
+//! file's top-level declarations, contract members and imports, built before
+//! the files are extracted. Declarations other files can refer to are
+//! labelled by file and offset rather than freshly, so a binding resolves
+//! once the declaring file is extracted too. Files that are not in the
+//! database (they failed to extract, say) are excluded from the index: names
+//! still resolve to their declarations, for inheritance and types, but no row
+//! refers to them.
+//!
+//! Members of other expressions (`token.transfer`, `s.amount`) are bound once
+//! their types are inferred (see the `types` module). A call to an overloaded
+//! function is bound to the candidates taking as many arguments as it passes.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use tree_sitter::{Node, Tree};
+
+use super::cache::CacheKeyBuilder;
+use super::extractor::import_parts;
+use super::types::{infer_types, parameter_types, type_name, using, TypeName, Using, TYPED_KINDS};
+use crate::trap::{Label, TrapValue, TrapWriter};
+
+/// Names bound when no declaration of the name is in scope.
+const BUILTINS: &[&str] = &[
+    "abi",
+    "addmod",
+    "assert",
+    "blobhash",
+    "block",
+    "blockhash",
+    "ecrecover",
+    "gasleft",
+    "keccak256",
+    "msg",
+    "mulmod",
+    "now",
+    "require",
+    "revert",
+    "ripemd160",
+    "selfdestruct",
+    "sha256",
+    "tx",
+    "type",
+];
+
+/// Members of the builtins that have them.
+const BUILTIN_MEMBERS: &[(&str, &[&str])] = &[
+    (
+        "abi",
+        &[
+            "decode",
+            "encode",
+            "encodeCall",
+            "encodePacked",
+            "encodeWithSelector",
+            "encodeWithSignature",
+        ],
+    ),
+    (
+        "block",
+        &[
+            "basefee",
+            "blobbasefee",
+            "chainid",
+            "coinbase",
+            "difficulty",
+            "gaslimit",
+            "number",
+            "prevrandao",
+            "timestamp",
+        ],
+    ),
+    ("msg", &["data", "sender", "sig", "value"]),
+    ("tx", &["gasprice", "origin"]),
+];
+
+/// Kinds of contract declarations.
+pub(super) const CONTRACT_KINDS: &[&str] = &[
+    "contract_declaration",
+    "interface_declaration",
+    "library_declaration",
+];
+
+/// Kinds of declarations other files can refer to: top-level declarations,
+/// contract members and the members of structs and enums.
+pub(super) const GLOBAL_DECLARATION_KINDS: &[&str] = &[
+    "constant_variable_declaration",
+    "contract_declaration",
+    "enum_declaration",
+    "enum_value",
+    "error_declaration",
+    "event_definition",
+    "function_definition",
+    "interface_declaration",
+    "library_declaration",
+    "modifier_definition",
+    "state_variable_declaration",
+    "struct_declaration",
+    "struct_member",
+    "user_defined_type_definition",
+];
+
+/// Kinds of functions, whose parameters are in a scope of their own.
+const FUNCTION_KINDS: &[&str] = &[
+    "constructor_definition",
+    "fallback_receive_definition",
+    "function_definition",
+    "modifier_definition",
+];
+
+/// Kinds of nodes that open a scope (a contract's body opens the
+/// contract's).
+const SCOPE_KINDS: &[&str] = &[
+    "assembly_statement",
+    "block_statement",
+    "catch_clause",
+    "constructor_definition",
+    "contract_declaration",
+    "fallback_receive_definition",
+    "for_statement",
+    "function_body",
+    "function_definition",
+    "interface_declaration",
+    "library_declaration",
+    "modifier_definition",
+    "source_file",
+    "try_statement",
+    "yul_block",
+    "yul_for_statement",
+    "yul_function_definition",
+];
+
+/// Kinds of local declarations, and of the names bound to declarations.
+const LOCAL_KINDS: &[&str] = &[
+    "identifier",
+    "parameter",
+    "variable_declaration",
+    "yul_identifier",
+];
+
+/// Kinds of parameters counted in a declaration's arity.
+const PARAMETER_KINDS: &[&str] = &["error_parameter", "event_parameter", "parameter"];
+
+/// Kinds of dotted names (`A.B`, `x.slot`), whose identifiers are bound in turn.
+const PATH_KINDS: &[&str] = &[
+    "modifier_invocation",
+    "type_alias",
+    "user_defined_type",
+    "using_alias",
+    "yul_path",
+];
+
+/// Check whether [`write_names`] needs the label of a node of the given kind:
+/// a scope, a local declaration, a name or an expression.
+pub(super) fn needs_label(kind: &str) -> bool {
+    SCOPE_KINDS.contains(&kind) || LOCAL_KINDS.contains(&kind) || TYPED_KINDS.contains(&kind)
+}
+
+/// Get the key of the label of a declaration other files can refer to.
+pub(super) fn declaration_key(file: &str, start: usize) -> String {
+    format!("declaration;{};{}", file, start)
+}
+
+/// A declaration other files can refer to.
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
+pub struct Symbol {
+    /// Declared name
+    pub name: String,
+    /// Kind of the declaration node
+    #[serde(deserialize_with = "declaration_kind")]
+    pub kind: &'static str,
+    /// Byte offset of the declaration node, which identifies it within its file
+    pub start: usize,
+    /// Number of parameters of a function, modifier, event or error
+    pub arity: Option<usize>,
+    /// Members of a contract, struct or enum
+    pub members: Vec<Symbol>,
+    /// Base contracts of a contract as written (`A`, `M.A`), in order
+    pub bases: Vec<Vec<String>>,
+    /// Declared type of a variable or struct member, values returned by a
+    /// function, or underlying type of a user-defined value type
+    pub type_name: Option<TypeName>,
+    /// Types of the parameters of a function or modifier
+    pub parameters: Vec<TypeName>,
+    /// `using for` directives of a contract
+    pub usings: Vec<Using>,
+    /// Whether a function or modifier is declared `virtual`
+    pub is_virtual: bool,
+    /// Bases listed by the `override` of a function or modifier, as written
+    /// (None = no `override`, empty = `override` without a list)
+    pub overrides: Option<Vec<Vec<String>>>,
+}
+
+/// The names a file declares and imports.
+#[derive(Debug, Clone, Default, Hash, Serialize, Deserialize)]
+pub struct FileSymbols {
+    /// Database path of the file
+    pub path: String,
+    /// Top-level declarations
+    pub declarations: Vec<Symbol>,
+    /// Import directives
+    pub imports: Vec<ImportedNames>,
+    /// Top-level `using for` directives
+    pub usings: Vec<Using>,
+}
+
+/// The names an import directive binds.
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
+pub struct ImportedNames {
+    /// Byte offset of the directive
+    pub start: usize,
+    /// Database path of the imported file (None = unresolved)
+    pub file: Option<String>,
+    /// Imported names and the names they are bound to, `*` standing for the
+    /// whole unit (empty = every name the file declares or imports)
+    pub symbols: Vec<(String, String)>,
+}
+
+/// Read the kind of a cached declaration as one of the kinds it can have.
+fn declaration_kind<'de, D: Deserializer<'de>>(deserializer: D) -> Result<&'static str, D::Error> {
+    let kind = String::deserialize(deserializer)?;
+    GLOBAL_DECLARATION_KINDS
+        .iter()
+        .find(|&&known| known == kind)
+        .copied()
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown declaration kind: {}", kind)))
+}
+
+/// The names declared by the files of a run, by database path.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    files: HashMap<String, FileSymbols>,
+    /// Files whose declarations are not in the database
+    excluded: HashSet<String>,
+}
+
+impl SymbolIndex {
+    /// Add a file's names.
+    pub fn insert(&mut self, symbols: FileSymbols) {
+        self.files.insert(symbols.path.clone(), symbols);
+    }
+
+    /// Get a file's names.
+    pub fn get(&self, path: &str) -> Option<&FileSymbols> {
+        self.files.get(path)
+    }
+
+    /// Keep resolving names to the declarations of a file, but leave them out
+    /// of the rows written: the file is not in the database.
+    pub fn exclude(&mut self, path: &str) {
+        self.excluded.insert(path.to_string());
+    }
+
+    /// Check whether a file's names are known and its declarations are in the
+    /// database.
+    pub fn includes(&self, path: &str) -> bool {
+        self.files.contains_key(path) && !self.excluded.contains(path)
+    }
+
+    /// Add the names of the files a file imports, directly or not, which the
+    /// bindings in the file depend on, to a cache key.
+    pub fn fingerprint(&self, path: &str, key: &mut CacheKeyBuilder) {
+        for imported in self.imported_files(path) {
+            key.field("imported_file", imported)
+                .hashed("imported_names", &self.files.get(imported))
+                .hashed("imported_excluded", &self.excluded.contains(imported));
+        }
+    }
+
+    /// Get the database paths of the files a file imports, directly or not,
+    /// in order.
+    pub fn imported_files(&self, path: &str) -> Vec<&str> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut pending = vec![path];
+        while let Some(path) = pending.pop() {
+            let Some(file) = self.files.get(path) else {
+                continue;
+            };
+            for import in &file.imports {
+                if let Some(target) = import.file.as_deref() {
+                    if seen.insert(target) {
+                        pending.push(target);
+                    }
+                }
+            }
+        }
+        let mut imported: Vec<&str> = seen.into_iter().filter(|&p| p != path).collect();
+        imported.sort_unstable();
+        imported
+    }
+}
+
+impl FromIterator<FileSymbols> for SymbolIndex {
+    fn from_iter<I: IntoIterator<Item = FileSymbols>>(iter: I) -> Self {
+        let mut index = SymbolIndex::default();
+        for symbols in iter {
+            index.insert(symbols);
+        }
+        index
+    }
+}
+
+/// Collect the names a parsed file declares and imports.
+///
+/// `resolve` maps an imported path to the database path of the imported file.
+pub fn file_symbols(
+    tree: &Tree,
+    source: &str,
+    path: &str,
+    mut resolve: impl FnMut(&str) -> Option<String>,
+) -> FileSymbols {
+    let mut symbols = FileSymbols {
+        path: path.to_string(),
+        ..FileSymbols::default()
+    };
+    for node in recovered_children(tree.root_node()) {
+        if node.kind() == "using_directive" {
+            symbols.usings.push(using(node, source));
+            continue;
+        }
+        if node.kind() != "import_directive" {
+            symbols.declarations.extend(symbol(node, source));
+            continue;
+        }
+        let (import_path, names) = import_parts(node, source);
+        let Some(import_path) = import_path else {
+            continue;
+        };
+        symbols.imports.push(ImportedNames {
+            start: node.start_byte(),
+            file: resolve(&import_path),
+            symbols: names
+                .into_iter()
+                .map(|(name, alias)| {
+                    let alias = alias.unwrap_or_else(|| name.clone());
+                    (name, alias)
+                })
+                .collect(),
+        });
+    }
+    symbols
+}
+
+/// Summarize a declaration other files can refer to, with its members.
+fn symbol(node: Node, source: &str) -> Option<Symbol> {
+    let kind = node.kind();
+    if !GLOBAL_DECLARATION_KINDS.contains(&kind) {
+        return None;
+    }
+    let name = match kind {
+        "enum_value" => node,
+        _ => node.child_by_field_name("name")?,
+    };
+    let mut symbol = Symbol {
+        name: text(name, source).to_string(),
+        kind,
+        start: node.start_byte(),
+        arity: None,
+        members: Vec::new(),
+        bases: Vec::new(),
+        type_name: None,
+        parameters: Vec::new(),
+        usings: Vec::new(),
+        is_virtual: false,
+        overrides: None,
+    };
+    let body = node.child_by_field_name("body");
+    match kind {
+        "contract_declaration" | "interface_declaration" | "library_declaration" => {
+            symbol.bases = named_children(node)
+                .filter(|child| child.kind() == "inheritance_specifier")
+                .filter_map(|child| child.child_by_field_name("ancestor"))
+                .map(|ancestor| {
+                    named_children(ancestor)
+                        .map(|segment| text(segment, source).to_string())
+                        .collect()
+                })
+                .collect();
+            symbol.members = body.map_or_else(Vec::new, |body| members(body, source));
+            symbol.usings = body.map_or_else(Vec::new, |body| {
+                recovered_children(body)
+                    .into_iter()
+                    .filter(|member| member.kind() == "using_directive")
+                    .map(|member| using(member, source))
+                    .collect()
+            });
+        }
+        "struct_declaration" | "enum_declaration" => {
+            symbol.members = body.map_or_else(Vec::new, |body| members(body, source));
+        }
+        "function_definition"
+        | "modifier_definition"
+        | "event_definition"
+        | "error_declaration" => {
+            let parameters = named_children(node).filter(|c| PARAMETER_KINDS.contains(&c.kind()));
+            symbol.arity = Some(parameters.count());
+            symbol.is_virtual = named_children(node).any(|c| c.kind() == "virtual");
+            symbol.overrides = named_children(node)
+                .find(|c| c.kind() == "override_specifier")
+                .map(|overrides| {
+                    named_children(overrides)
+                        .map(|base| named_children(base).map(|s| text(s, source).to_string()))
+                        .map(|path| path.collect())
+                        .collect()
+                });
+            let declared_type = |parameter: Node| {
+                let declared = parameter.child_by_field_name("type");
+                declared.and_then(|t| type_name(t, source))
+            };
+            symbol.parameters = named_children(node)
+                .filter(|c| c.kind() == "parameter")
+                .filter_map(declared_type)
+                .collect();
+            if kind == "function_definition" {
+                let returns = named_children(node)
+                    .filter(|c| c.kind() == "return_type_definition")
+                    .flat_map(named_children)
+                    .filter_map(declared_type)
+                    .collect();
+                symbol.type_name = Some(TypeName::Tuple(returns));
+            }
+        }
+        "state_variable_declaration" | "constant_variable_declaration" | "struct_member" => {
+            symbol.type_name = node
+                .child_by_field_name("type")
+                .and_then(|t| type_name(t, source));
+        }
+        "user_defined_type_definition" => {
+            symbol.type_name = named_children(node)
+                .find(|c| c.kind() == "primitive_type")
+                .and_then(|t| type_name(t, source));
+        }
+        _ => {}
+    }
+    Some(symbol)
+}
+
+/// Summarize the declarations in the body of a contract, struct or enum.
+fn members(body: Node, source: &str) -> Vec<Symbol> {
+    recovered_children(body)
+        .into_iter()
+        .filter_map(|member| symbol(member, source))
+        .collect()
+}
+
+/// Get the named children of a node, those of a syntax error among them
+/// taking its place (they are extracted as children of the node).
+fn recovered_children(node: Node) -> Vec<Node> {
+    let mut children = Vec::new();
+    let mut pending: Vec<Node> = named_children(node).collect();
+    pending.reverse();
+    while let Some(child) = pending.pop() {
+        if child.is_error() {
+            pending.extend(named_children(child).collect::<Vec<_>>().into_iter().rev());
+        } else {
+            children.push(child);
+        }
+    }
+    children
+}
+
+/// Get the named children of a node.
+pub(super) fn named_children(node: Node) -> impl Iterator<Item = Node> {
+    (0..node.named_child_count()).filter_map(move |i| node.named_child(i as u32))
+}
+
+/// Get the source text of a node.
+pub(super) fn text<'s>(node: Node, source: &'s str) -> &'s str {
+    node.utf8_text(source.as_bytes()).unwrap_or("")
+}
+
+/// A contract declaration in some file.
+pub(super) type Contract<'a> = (&'a str, &'a Symbol);
+
+/// Linearization of a contract, most derived first.
+type Linearization<'a> = Rc<Vec<Contract<'a>>>;
+
+/// Names visible at the top level of a file.
+type FileScope<'a> = Rc<HashMap<&'a str, Vec<Target<'a>>>>;
+
+/// What a name can be bound to.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Target<'a> {
+    /// A declaration only visible within the file (a parameter, say)
+    Local(Node<'a>),
+    /// A declaration other files can refer to, and the file declaring it
+    Global(&'a str, &'a Symbol),
+    /// A whole imported file (`import "x" as M`)
+    Unit(&'a str),
+    /// A builtin (`msg`) or a member of one (`msg.sender`)
+    Builtin(&'static str, Option<&'static str>),
+}
+
+impl PartialEq for Target<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Target::Local(a), Target::Local(b)) => a == b,
+            (Target::Global(a, x), Target::Global(b, y)) => a == b && x.start == y.start,
+            (Target::Unit(a), Target::Unit(b)) => a == b,
+            (Target::Builtin(a, x), Target::Builtin(b, y)) => a == b && x == y,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Target<'a> {
+    /// Get the contract this is, if it is one.
+    pub(super) fn contract(self) -> Option<Contract<'a>> {
+        match self {
+            Target::Global(file, symbol) if CONTRACT_KINDS.contains(&symbol.kind) => {
+                Some((file, symbol))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Remove repeated targets, keeping the first of each.
+pub(super) fn dedup(targets: &mut Vec<Target>) {
+    let mut unique: Vec<Target> = Vec::with_capacity(targets.len());
+    for target in targets.drain(..) {
+        if !unique.contains(&target) {
+            unique.push(target);
+        }
+    }
+    *targets = unique;
+}
+
+/// Keep the overloads of a called function that take `arity` arguments, if any do.
+pub(super) fn filter_arity<'a>(targets: Vec<Target<'a>>, arity: Option<usize>) -> Vec<Target<'a>> {
+    let Some(arity) = arity else {
+        return targets;
+    };
+    let takes = |target: &Target| match target {
+        Target::Global(_, symbol) => symbol.arity.is_none_or(|a| a == arity),
+        _ => true,
+    };
+    if targets.iter().all(takes) || !targets.iter().any(takes) {
+        return targets;
+    }
+    targets.into_iter().filter(takes).collect()
+}
+
+/// Looks names up among the declarations of the file being resolved and the
+/// files in the index.
+pub(super) struct Resolver<'a> {
+    /// Names of other files
+    index: &'a SymbolIndex,
+    /// Names of the file being resolved
+    own: &'a FileSymbols,
+    /// Names visible at the top level of each file
+    file_scopes: RefCell<HashMap<&'a str, FileScope<'a>>>,
+    /// Linearizations of contracts, by file and offset
+    linearizations: RefCell<HashMap<(&'a str, usize), Linearization<'a>>>,
+    /// Contracts being linearized
+    linearizing: RefCell<HashSet<(&'a str, usize)>>,
+    /// Contracts whose bases could not be linearized
+    failures: RefCell<HashSet<(&'a str, usize)>>,
+}
+
+impl<'a> Resolver<'a> {
+    pub(super) fn new(index: &'a SymbolIndex, own: &'a FileSymbols) -> Self {
+        Resolver {
+            index,
+            own,
+            file_scopes: RefCell::new(HashMap::new()),
+            linearizations: RefCell::new(HashMap::new()),
+            linearizing: RefCell::new(HashSet::new()),
+            failures: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Check whether the declarations of a file are in the database, so rows
+    /// can refer to them.
+    pub(super) fn in_database(&self, path: &str) -> bool {
+        path == self.own.path || !self.index.excluded.contains(path)
+    }
+
+    pub(super) fn file(&self, path: &str) -> Option<&'a FileSymbols> {
+        if path == self.own.path {
+            Some(self.own)
+        } else {
+            self.index.files.get(path)
+        }
+    }
+
+    /// Get the names visible at the top level of a file: its declarations and
+    /// the names it imports.
+    ///
+    /// Within an import cycle, a file does not see the names that reach it
+    /// through the cycle.
+    pub(super) fn file_scope(&self, path: &str) -> FileScope<'a> {
+        let Some(file) = self.file(path) else {
+            return Rc::default();
+        };
+        let path = file.path.as_str();
+        if let Some(scope) = self.file_scopes.borrow().get(path) {
+            return scope.clone();
+        }
+        self.file_scopes.borrow_mut().insert(path, Rc::default());
+
+        let mut names: HashMap<&'a str, Vec<Target<'a>>> = HashMap::new();
+        for declaration in &file.declarations {
+            names
+                .entry(declaration.name.as_str())
+                .or_default()
+                .push(Target::Global(path, declaration));
+        }
+        for import in &file.imports {
+            let Some(imported) = import.file.as_deref().and_then(|f| self.file(f)) else {
+                continue;
+            };
+            let imported_path = imported.path.as_str();
+            let imported_scope = self.file_scope(imported_path);
+            if import.symbols.is_empty() {
+                for (&name, targets) in imported_scope.iter() {
+                    names.entry(name).or_default().extend(targets);
+                }
+            }
+            for (symbol, alias) in &import.symbols {
+                let targets = names.entry(alias.as_str()).or_default();
+                if symbol == "*" {
+                    targets.push(Target::Unit(imported_path));
+                } else if let Some(imported) = imported_scope.get(symbol.as_str()) {
+                    targets.extend(imported);
+                }
+            }
+        }
+        names.values_mut().for_each(dedup);
+
+        let names = Rc::new(names);
+        self.file_scopes.borrow_mut().insert(path, names.clone());
+        names
+    }
+
+    /// Get the contracts a contract inherits from directly, in the order written.
+    fn bases(&self, (file, contract): Contract<'a>) -> Vec<Contract<'a>> {
+        contract
+            .bases
+            .iter()
+            .filter_map(|path| {
+                self.resolve_path(file, None, path)
+                    .into_iter()
+                    .find_map(Target::contract)
+            })
+            .collect()
+    }
+
+    /// Resolve a dotted name (`A`, `M.A.S`) written in a file, within a
+    /// contract if given.
+    pub(super) fn resolve_path(
+        &self,
+        file: &str,
+        contract: Option<Contract<'a>>,
+        path: &[String],
+    ) -> Vec<Target<'a>> {
+        let Some((first, rest)) = path.split_first() else {
+            return Vec::new();
+        };
+        let mut targets = contract
+            .map(|contract| self.lookup_in(&self.linearization(contract), first))
+            .unwrap_or_default();
+        if targets.is_empty() {
+            targets = self
+                .file_scope(file)
+                .get(first.as_str())
+                .cloned()
+                .unwrap_or_default();
+        }
+        for segment in rest {
+            targets = targets
+                .into_iter()
+                .flat_map(|target| self.members(target, segment))
+                .collect();
+            dedup(&mut targets);
+        }
+        targets
+    }
+
+    /// Get the declaration directly containing a declaration of a file (the
+    /// contract of a member, the struct of a struct member), if any.
+    pub(super) fn container(&self, file: &str, symbol: &Symbol) -> Option<&'a Symbol> {
+        let mut pending: Vec<&'a Symbol> = self.file(file)?.declarations.iter().collect();
+        while let Some(declaration) = pending.pop() {
+            if declaration.members.iter().any(|m| m.start == symbol.start) {
+                return Some(declaration);
+            }
+            pending.extend(&declaration.members);
+        }
+        None
+    }
+
+    /// Get the contract a declaration is in, if any, within which the names
+    /// it is written with are resolved.
+    pub(super) fn context_contract(&self, file: &'a str, symbol: &Symbol) -> Option<Contract<'a>> {
+        let mut container = self.container(file, symbol)?;
+        while !CONTRACT_KINDS.contains(&container.kind) {
+            container = self.container(file, container)?;
+        }
+        Some((file, container))
+    }
+
+    /// Find the summary of a contract declaration of the file being resolved.
+    pub(super) fn own_contract(&self, node: Node) -> Option<Contract<'a>> {
+        let own = self.own;
+        own.declarations
+            .iter()
+            .find(|d| d.start == node.start_byte() && CONTRACT_KINDS.contains(&d.kind))
+            .map(|contract| (own.path.as_str(), contract))
+    }
+
+    /// Get the names of the file being resolved.
+    pub(super) fn own(&self) -> &'a FileSymbols {
+        self.own
+    }
+
+    /// Get the C3 linearization of a contract, most derived (itself) first.
+    ///
+    /// Bases that cannot be resolved are left out; when the bases cannot be
+    /// linearized, they are listed depth-first from the last written (see
+    /// [`Resolver::linearization_failed`]).
+    pub(super) fn linearization(&self, contract: Contract<'a>) -> Linearization<'a> {
+        let key = (contract.0, contract.1.start);
+        if let Some(linearization) = self.linearizations.borrow().get(&key) {
+            if self.linearizing.borrow().contains(&key) {
+                self.failures.borrow_mut().insert(key);
+            }
+            return linearization.clone();
+        }
+        // Inheritance cycles end at the contract itself
+        self.linearizations
+            .borrow_mut()
+            .insert(key, Rc::new(vec![contract]));
+        self.linearizing.borrow_mut().insert(key);
+
+        let bases = self.bases(contract);
+        let mut sequences: Vec<Vec<Contract<'a>>> = bases
+            .iter()
+            .rev()
+            .map(|&base| self.linearization(base).to_vec())
+            .collect();
+        sequences.push(bases.iter().rev().copied().collect());
+        let merged = c3_merge(sequences.clone()).unwrap_or_else(|| {
+            self.failures.borrow_mut().insert(key);
+            let mut all: Vec<Contract<'a>> = Vec::new();
+            for base in sequences.into_iter().flatten() {
+                if !all.iter().any(|c| same_contract(*c, base)) {
+                    all.push(base);
+                }
+            }
+            all
+        });
+
+        let mut linearization = vec![contract];
+        linearization.extend(merged.into_iter().filter(|&c| !same_contract(c, contract)));
+        let linearization = Rc::new(linearization);
+        self.linearizations
+            .borrow_mut()
+            .insert(key, linearization.clone());
+        self.linearizing.borrow_mut().remove(&key);
+        linearization
+    }
+
+    /// Check whether the bases of a contract linearized so far could not be
+    /// linearized: the order they are written in contradicts that of their
+    /// own bases, or the contract inherits from itself.
+    pub(super) fn linearization_failed(&self, (file, contract): Contract<'a>) -> bool {
+        self.failures.borrow().contains(&(file, contract.start))
+    }
+
+    /// Get the functions or modifiers a member of a contract overrides: for
+    /// `override(A, B)`, the most derived implementation in each of `A` and
+    /// `B`, otherwise that in the contract's bases. Only `virtual` ones (and
+    /// those of interfaces) can be overridden.
+    pub(super) fn overridden(
+        &self,
+        contract: Contract<'a>,
+        member: &'a Symbol,
+    ) -> Vec<(&'a str, &'a Symbol)> {
+        let Some(bases) = &member.overrides else {
+            return Vec::new();
+        };
+        let signature = parameter_types(self, contract.0, member);
+        let implementation = |contracts: &[Contract<'a>]| {
+            contracts.iter().find_map(|&(file, base)| {
+                let found = base.members.iter().find(|m| {
+                    m.kind == member.kind
+                        && m.name == member.name
+                        && m.arity == member.arity
+                        && parameter_types(self, file, m) == signature
+                })?;
+                let overridable = found.is_virtual || base.kind == "interface_declaration";
+                overridable.then_some((file, found))
+            })
+        };
+        if bases.is_empty() {
+            let linearization = self.linearization(contract);
+            return implementation(&linearization[1..]).into_iter().collect();
+        }
+        let mut overridden: Vec<(&'a str, &'a Symbol)> = Vec::new();
+        for path in bases {
+            let base = self
+                .resolve_path(contract.0, Some(contract), path)
+                .into_iter()
+                .find_map(Target::contract);
+            let found = base.and_then(|base| implementation(&self.linearization(base)));
+            if let Some(found) = found.filter(|&f| !overridden.iter().any(|&o| same_contract(o, f)))
+            {
+                overridden.push(found);
+            }
+        }
+        overridden
+    }
+
+    /// Look a member up in a list of contracts, most derived first.
+    ///
+    /// A function hides the functions of less derived contracts with the same
+    /// number of parameters (which it overrides), but not their other overloads.
+    pub(super) fn lookup_in(&self, contracts: &[Contract<'a>], name: &str) -> Vec<Target<'a>> {
+        let mut found = Vec::new();
+        let mut arities = HashSet::new();
+        for &(file, contract) in contracts {
+            for member in contract.members.iter().filter(|m| m.name == name) {
+                match member.arity {
+                    Some(arity) if arities.insert(arity) => {
+                        found.push(Target::Global(file, member))
+                    }
+                    Some(_) => {}
+                    None if found.is_empty() => found.push(Target::Global(file, member)),
+                    None => {}
+                }
+            }
+            if !found.is_empty() && arities.is_empty() {
+                break;
+            }
+        }
+        found
+    }
+
+    /// Get the members of a contract, enum, imported unit or builtin with the given name.
+    pub(super) fn members(&self, target: Target<'a>, name: &str) -> Vec<Target<'a>> {
+        match target {
+            Target::Unit(file) => self.file_scope(file).get(name).cloned().unwrap_or_default(),
+            Target::Global(file, symbol) if CONTRACT_KINDS.contains(&symbol.kind) => {
+                self.lookup_in(&self.linearization((file, symbol)), name)
+            }
+            Target::Global(file, symbol) if symbol.kind == "enum_declaration" => symbol
+                .members
+                .iter()
+                .filter(|m| m.name == name)
+                .map(|m| Target::Global(file, m))
+                .collect(),
+            Target::Builtin(builtin, None) => BUILTIN_MEMBERS
+                .iter()
+                .filter(|(b, _)| *b == builtin)
+                .flat_map(|(_, members)| members.iter())
+                .find(|&&m| m == name)
+                .map(|&m| Target::Builtin(builtin, Some(m)))
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Check whether two contracts are the same declaration.
+fn same_contract(a: Contract, b: Contract) -> bool {
+    a.0 == b.0 && a.1.start == b.1.start
+}
+
+/// Merge linearizations as C3 does: repeatedly take the first head that is in
+/// no other sequence's tail. None if there is no such head.
+fn c3_merge<'a>(mut sequences: Vec<Vec<Contract<'a>>>) -> Option<Vec<Contract<'a>>> {
+    let mut merged = Vec::new();
+    loop {
+        sequences.retain(|s| !s.is_empty());
+        if sequences.is_empty() {
+            return Some(merged);
+        }
+        let head = sequences.iter().map(|s| s[0]).find(|&head| {
+            sequences
+                .iter()
+                .all(|s| !s[1..].iter().any(|&c| same_contract(c, head)))
+        })?;
+        for sequence in &mut sequences {
+            if same_contract(sequence[0], head) {
+                sequence.remove(0);
+            }
+        }
+        merged.push(head);
+    }
+}
+
+/// Kind of an open scope, for looking names up.
+#[derive(Clone, Copy)]
+enum ScopeKind<'a> {
+    /// The file's top level, whose names the resolver knows
+    File,
+    /// A contract, whose members (inherited ones too) the resolver knows
+    Contract(Contract<'a>),
+    /// A function or block, whose names are collected when it is opened
+    Local,
+}
+
+/// A scope open during the walk.
+struct OpenScope<'a> {
+    label: Label,
+    kind: ScopeKind<'a>,
+    /// Names declared in the scope, with the offset they are visible from
+    names: Vec<(&'a str, Target<'a>, usize)>,
+}
+
+/// A step of the walk over the syntax tree.
+enum Step<'a> {
+    /// Visit a node, given its field and parent, and the number of
+    /// arguments it is called with if it is the function of a call
+    Visit {
+        node: Node<'a>,
+        field: Option<&'static str>,
+        parent: Option<Node<'a>>,
+        arity: Option<usize>,
+    },
+    /// Close the innermost scope
+    Close,
+}
+
+/// Writes the scopes and bindings of a file.
+struct NameWriter<'a, 'w> {
+    trap: &'w mut TrapWriter,
+    /// Labels of the file's nodes, by node id (see [`needs_label`])
+    labels: &'w HashMap<usize, Label>,
+    source: &'a str,
+    resolver: Resolver<'a>,
+    scopes: Vec<OpenScope<'a>>,
+    /// Builtins and imported units already recorded
+    written: HashMap<String, Label>,
+    /// What the names bound so far refer to, by node id
+    bindings: HashMap<usize, Vec<Target<'a>>>,
+    /// Contracts whose bases could not be linearized
+    failures: Vec<Node<'a>>,
+}
+
+/// Record the scopes and bindings of a file, the linearizations of its
+/// contracts, and the types of its expressions.
+///
+/// `own` are the file's names (see [`file_symbols`]), `index` those of the
+/// other files; `labels` are the labels of the tree's nodes, by node id, for
+/// the kinds of nodes it [`needs_label`] for.
+/// Returns the contract declarations whose bases could not be linearized.
+pub fn write_names<'t>(
+    trap: &mut TrapWriter,
+    tree: &'t Tree,
+    source: &'t str,
+    own: &'t FileSymbols,
+    index: &'t SymbolIndex,
+    labels: &HashMap<usize, Label>,
+) -> Vec<Node<'t>> {
+    let mut writer = NameWriter {
+        trap,
+        labels,
+        source,
+        resolver: Resolver::new(index, own),
+        scopes: Vec::new(),
+        written: HashMap::new(),
+        bindings: HashMap::new(),
+        failures: Vec::new(),
+    };
+    writer.walk(tree.root_node());
+
+    let inferred = infer_types(&writer.resolver, tree, source, &writer.bindings);
+    for (reference, targets) in inferred.bindings {
+        writer.bind(reference, targets);
+    }
+    for (expression, expression_type) in inferred.types {
+        let Some(label) = writer.labels.get(&expression.id()).cloned() else {
+            continue;
+        };
+        writer.trap.emit(
+            "solidity_expr_type",
+            vec![TrapValue::Label(label), TrapValue::String(expression_type)],
+        );
+    }
+    writer.failures
+}
+
+impl<'a> NameWriter<'a, '_> {
+    /// Walk the tree in pre-order, opening and closing scopes and binding
+    /// names. An explicit stack is used rather than recursion, as trees can
+    /// be deep.
+    fn walk(&mut self, root: Node<'a>) {
+        let mut steps = vec![Step::Visit {
+            node: root,
+            field: None,
+            parent: None,
+            arity: None,
+        }];
+        while let Some(step) = steps.pop() {
+            let Step::Visit {
+                node,
+                field,
+                parent,
+                arity,
+            } = step
+            else {
+                self.scopes.pop();
+                continue;
+            };
+            if node.is_missing() {
+                continue;
+            }
+            // Syntax errors are not extracted, but the nodes recovered in them are
+            if !node.is_error() {
+                if self.open_scope(node, parent) {
+                    steps.push(Step::Close);
+                }
+                self.bind_node(node, field, parent, arity);
+            }
+
+            let call_arity = (node.kind() == "call_expression").then(|| call_arity(node));
+            let mut children = Vec::new();
+            let mut cursor = node.walk();
+            if cursor.goto_first_child() {
+                loop {
+                    let child = cursor.node();
+                    if child.is_named() {
+                        let field = cursor.field_name();
+                        let arity = match (node.kind(), field) {
+                            ("call_expression", Some("function")) => call_arity,
+                            ("expression", _) => arity,
+                            _ => None,
+                        };
+                        children.push(Step::Visit {
+                            node: child,
+                            field,
+                            parent: Some(node),
+                            arity,
+                        });
+                    }
+                    if !cursor.goto_next_sibling() {
+                        break;
+                    }
+                }
+            }
+            steps.extend(children.into_iter().rev());
+        }
+    }
+
+    /// Open the scope a node introduces, if any. Returns whether one was opened.
+    fn open_scope(&mut self, node: Node<'a>, parent: Option<Node<'a>>) -> bool {
+        let (kind, scope_node, scope_kind, names) = match node.kind() {
+            "source_file" => ("file", node, ScopeKind::File, Vec::new()),
+            "contract_body" => {
+                let Some(contract) = parent.and_then(|p| self.resolver.own_contract(p)) else {
+                    return false;
+                };
+                (
+                    "contract",
+                    parent.expect("a contract body has a parent"),
+                    ScopeKind::Contract(contract),
+                    Vec::new(),
+                )
+            }
+            kind if FUNCTION_KINDS.contains(&kind) => {
+                let mut names = Vec::new();
+                for child in named_children(node) {
+                    match child.kind() {
+                        "parameter" => names.extend(self.declared(child, 0)),
+                        "return_type_definition" => {
+                            for parameter in named_children(child) {
+                                names.extend(self.declared(parameter, 0));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                ("function", node, ScopeKind::Local, names)
+            }
+            "yul_function_definition" => {
+                let names = named_children(node)
+                    .filter(|c| c.kind() == "yul_identifier")
+                    .skip(1)
+                    .map(|parameter| (text(parameter, self.source), Target::Local(parameter), 0))
+                    .collect();
+                ("function", node, ScopeKind::Local, names)
+            }
+            "block_statement" | "function_body" => {
+                let names = named_children(node)
+                    .flat_map(|statement| self.declared(unwrap(statement, "statement"), 0))
+                    .collect();
+                ("block", node, ScopeKind::Local, names)
+            }
+            "for_statement" => {
+                let names = node
+                    .child_by_field_name("initial")
+                    .map(|initial| self.declared(unwrap(initial, "statement"), 0))
+                    .unwrap_or_default();
+                ("block", node, ScopeKind::Local, names)
+            }
+            "catch_clause" | "try_statement" => {
+                let names = named_children(node)
+                    .filter(|c| c.kind() == "parameter")
+                    .flat_map(|parameter| self.declared(parameter, 0))
+                    .collect();
+                ("block", node, ScopeKind::Local, names)
+            }
+            "assembly_statement" | "yul_block" | "yul_for_statement" => {
+                // The initializer block of a Yul for loop is in the loop's scope
+                let body = match node.kind() {
+                    "yul_for_statement" => node.named_child(0).unwrap_or(node),
+                    _ => node,
+                };
+                let is_initializer = parent.is_some_and(|p| {
+                    p.kind() == "yul_for_statement" && p.named_child(0) == Some(node)
+                });
+                if is_initializer {
+                    return false;
+                }
+                let names = named_children(body)
+                    .flat_map(|statement| self.declared(statement, 0))
+                    .collect();
+                ("yul_block", node, ScopeKind::Local, names)
+            }
+            _ => return false,
+        };
+        let Some(node_label) = self.labels.get(&scope_node.id()).cloned() else {
+            return false;
+        };
+
+        let label = self.trap.fresh_label();
+        self.trap.emit(
+            "solidity_scope",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::String(kind.to_string()),
+                TrapValue::Label(node_label),
+            ],
+        );
+        if let Some(parent) = self.scopes.last() {
+            self.trap.emit(
+                "solidity_scope_parent",
+                vec![
+                    TrapValue::Label(label.clone()),
+                    TrapValue::Label(parent.label.clone()),
+                ],
+            );
+        }
+
+        // Record the scope's declarations, and the declarations they hide
+        // (looked up before the scope is open)
+        let declarations: Vec<(&'a str, Target<'a>)> = match scope_kind {
+            ScopeKind::File => {
+                let scope = self.resolver.file_scope(&self.resolver.own.path);
+                let mut names: Vec<_> = scope
+                    .iter()
+                    .flat_map(|(&name, targets)| targets.iter().map(move |&t| (name, t)))
+                    .collect();
+                names.sort_by_key(|&(name, _)| name);
+                names
+            }
+            ScopeKind::Contract((file, contract)) => contract
+                .members
+                .iter()
+                .map(|m| (m.name.as_str(), Target::Global(file, m)))
+                .collect(),
+            ScopeKind::Local => names.iter().map(|&(name, t, _)| (name, t)).collect(),
+        };
+        for &(name, target) in &declarations {
+            let Some(target_label) = self.target_label(target) else {
+                continue;
+            };
+            self.trap.emit(
+                "solidity_scope_declaration",
+                vec![
+                    TrapValue::Label(label.clone()),
+                    TrapValue::String(name.to_string()),
+                    TrapValue::Label(target_label.clone()),
+                ],
+            );
+            // Only the file's own declarations can hide anything here
+            let own = match target {
+                Target::Global(file, _) => file == self.resolver.own.path,
+                Target::Local(_) => true,
+                _ => false,
+            };
+            if !own {
+                continue;
+            }
+            let start = match target {
+                Target::Global(_, symbol) => symbol.start,
+                Target::Local(node) => node.start_byte(),
+                _ => 0,
+            };
+            let mut hidden = self.lookup(name, start);
+            if let ScopeKind::Contract(contract) = scope_kind {
+                // Inherited members that are not overridden
+                let linearization = self.resolver.linearization(contract);
+                hidden.extend(
+                    self.resolver
+                        .lookup_in(&linearization[1..], name)
+                        .into_iter()
+                        .filter(|t| !matches!(t, Target::Global(_, s) if s.arity.is_some())),
+                );
+            }
+            for hidden in hidden {
+                if hidden == target {
+                    continue;
+                }
+                let Some(hidden_label) = self.target_label(hidden) else {
+                    continue;
+                };
+                self.trap.emit(
+                    "solidity_shadowing",
+                    vec![
+                        TrapValue::Label(target_label.clone()),
+                        TrapValue::Label(hidden_label),
+                    ],
+                );
+            }
+        }
+
+        if let ScopeKind::Contract(contract) = scope_kind {
+            self.write_inheritance(scope_node, contract);
+        }
+
+        self.scopes.push(OpenScope {
+            label,
+            kind: scope_kind,
+            names,
+        });
+        true
+    }
+
+    /// Record the linearization of a contract of the file and what its
+    /// members override.
+    fn write_inheritance(&mut self, node: Node<'a>, contract: Contract<'a>) {
+        let Some(contract_label) = self.labels.get(&node.id()).cloned() else {
+            return;
+        };
+        let linearization = self.resolver.linearization(contract);
+        for (index, &(file, base)) in linearization.iter().enumerate() {
+            if !self.resolver.in_database(file) {
+                continue;
+            }
+            let base = self.trap.string_label(&declaration_key(file, base.start));
+            self.trap.emit(
+                "solidity_contract_linearization",
+                vec![
+                    TrapValue::Label(contract_label.clone()),
+                    TrapValue::UInt(index as u64),
+                    TrapValue::Label(base),
+                ],
+            );
+        }
+        if self.resolver.linearization_failed(contract) {
+            self.failures.push(node);
+        }
+
+        let (file, symbol) = contract;
+        for member in &symbol.members {
+            for (overridden_file, overridden) in self.resolver.overridden(contract, member) {
+                if !self.resolver.in_database(overridden_file) {
+                    continue;
+                }
+                let member = self.trap.string_label(&declaration_key(file, member.start));
+                let overridden = self
+                    .trap
+                    .string_label(&declaration_key(overridden_file, overridden.start));
+                self.trap.emit(
+                    "solidity_function_override",
+                    vec![TrapValue::Label(member), TrapValue::Label(overridden)],
+                );
+            }
+        }
+    }
+
+    /// Get the names a statement or parameter declares, visible from the end
+    /// of the statement (or from `from` for a parameter).
+    fn declared(&self, node: Node<'a>, from: usize) -> Vec<(&'a str, Target<'a>, usize)> {
+        let source = self.source;
+        let name = |node: Node<'a>| {
+            let name = node.child_by_field_name("name")?;
+            Some((text(name, source), Target::Local(node), from))
+        };
+        match node.kind() {
+            "parameter" => name(node).into_iter().collect(),
+            "variable_declaration_statement" => {
+                let end = node.end_byte();
+                named_children(node)
+                    .flat_map(|child| match child.kind() {
+                        "variable_declaration_tuple" => named_children(child).collect(),
+                        _ => vec![child],
+                    })
+                    .filter(|child| child.kind() == "variable_declaration")
+                    .filter_map(name)
+                    .map(|(name, target, _)| (name, target, end))
+                    .collect()
+            }
+            "yul_variable_declaration" => {
+                let end = node.end_byte();
+                let mut cursor = node.walk();
+                node.children_by_field_name("left", &mut cursor)
+                    .flat_map(|left| match left.kind() {
+                        "yul_identifier" => vec![left],
+                        _ => named_children(left)
+                            .filter(|c| c.kind() == "yul_identifier")
+                            .collect(),
+                    })
+                    .map(|identifier| (text(identifier, source), Target::Local(identifier), end))
+                    .collect()
+            }
+            "yul_function_definition" => named_children(node)
+                .find(|c| c.kind() == "yul_identifier")
+                .map(|identifier| (text(identifier, source), Target::Local(identifier), 0))
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the innermost contract being walked.
+    fn current_contract(&self) -> Option<Contract<'a>> {
+        self.scopes.iter().rev().find_map(|scope| match scope.kind {
+            ScopeKind::Contract(contract) => Some(contract),
+            _ => None,
+        })
+    }
+
+    /// Look a name used at `position` up in the open scopes, innermost first,
+    /// then among the builtins.
+    fn lookup(&self, name: &str, position: usize) -> Vec<Target<'a>> {
+        for scope in self.scopes.iter().rev() {
+            let found = match scope.kind {
+                ScopeKind::File => self
+                    .resolver
+                    .file_scope(&self.resolver.own.path)
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_default(),
+                ScopeKind::Contract(contract) => self
+                    .resolver
+                    .lookup_in(&self.resolver.linearization(contract), name),
+                ScopeKind::Local => scope
+                    .names
+                    .iter()
+                    .filter(|&&(n, _, from)| n == name && from <= position)
+                    .map(|&(_, target, _)| target)
+                    .collect(),
+            };
+            if !found.is_empty() {
+                return found;
+            }
+        }
+        BUILTINS
+            .iter()
+            .find(|&&builtin| builtin == name)
+            .map(|&builtin| Target::Builtin(builtin, None))
+            .into_iter()
+            .collect()
+    }
+
+    /// Get what an expression used as the object of a member access refers to.
+    fn object_targets(&self, object: Node<'a>) -> Vec<Target<'a>> {
+        let object = unwrap(object, "expression");
+        match object.kind() {
+            "identifier" => match text(object, self.source) {
+                "this" | "super" => Vec::new(),
+                name => self.lookup(name, object.start_byte()),
+            },
+            "member_expression" => self.member_targets(object),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get what the property of a member access refers to.
+    fn member_targets(&self, member: Node<'a>) -> Vec<Target<'a>> {
+        let (Some(object), Some(property)) = (
+            member.child_by_field_name("object"),
+            member.child_by_field_name("property"),
+        ) else {
+            return Vec::new();
+        };
+        let name = text(property, self.source);
+        let object = unwrap(object, "expression");
+        if object.kind() == "identifier" {
+            let skip = match text(object, self.source) {
+                "this" => Some(0),
+                "super" => Some(1),
+                _ => None,
+            };
+            if let Some(skip) = skip {
+                let Some(contract) = self.current_contract() else {
+                    return Vec::new();
+                };
+                let linearization = self.resolver.linearization(contract);
+                return self
+                    .resolver
+                    .lookup_in(linearization.get(skip..).unwrap_or_default(), name);
+            }
+        }
+        let mut targets: Vec<Target<'a>> = self
+            .object_targets(object)
+            .into_iter()
+            .flat_map(|target| self.resolver.members(target, name))
+            .collect();
+        dedup(&mut targets);
+        targets
+    }
+
+    /// Bind the names a node contains directly.
+    fn bind_node(
+        &mut self,
+        node: Node<'a>,
+        field: Option<&str>,
+        parent: Option<Node<'a>>,
+        arity: Option<usize>,
+    ) {
+        match node.kind() {
+            "identifier" | "yul_identifier" if is_reference(field, parent) => {
+                let targets = match text(node, self.source) {
+                    "this" if node.kind() == "identifier" => self
+                        .current_contract()
+                        .map(|(file, contract)| Target::Global(file, contract))
+                        .into_iter()
+                        .collect(),
+                    "super" if node.kind() == "identifier" => Vec::new(),
+                    name => self.lookup(name, node.start_byte()),
+                };
+                self.bind(node, filter_arity(targets, arity));
+            }
+            "member_expression" => {
+                if let Some(property) = node.child_by_field_name("property") {
+                    let targets = filter_arity(self.member_targets(node), arity);
+                    self.bind(property, targets);
+                }
+            }
+            kind if PATH_KINDS.contains(&kind) => {
+                let segments: Vec<Node<'a>> = named_children(node)
+                    .filter(|c| matches!(c.kind(), "identifier" | "yul_identifier"))
+                    .collect();
+                let Some((&first, rest)) = segments.split_first() else {
+                    return;
+                };
+                let mut targets = self.lookup(text(first, self.source), first.start_byte());
+                self.bind(first, targets.clone());
+                // Only the variable of a Yul path (`x.slot`) is a declared name
+                if kind == "yul_path" {
+                    return;
+                }
+                for &segment in rest {
+                    let name = text(segment, self.source);
+                    targets = targets
+                        .into_iter()
+                        .flat_map(|target| self.resolver.members(target, name))
+                        .collect();
+                    dedup(&mut targets);
+                    self.bind(segment, targets.clone());
+                }
+            }
+            "import_directive" => {
+                let file = self
+                    .resolver
+                    .own
+                    .imports
+                    .iter()
+                    .find(|import| import.start == node.start_byte())
+                    .and_then(|import| import.file.as_deref());
+                let Some(file) = file else {
+                    return;
+                };
+                let scope = self.resolver.file_scope(file);
+                let mut cursor = node.walk();
+                let names: Vec<Node<'a>> = node
+                    .children_by_field_name("import_name", &mut cursor)
+                    .collect();
+                for name in names {
+                    let targets = scope
+                        .get(text(name, self.source))
+                        .cloned()
+                        .unwrap_or_default();
+                    self.bind(name, targets);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record what a name refers to.
+    fn bind(&mut self, reference: Node<'a>, targets: Vec<Target<'a>>) {
+        self.bindings
+            .entry(reference.id())
+            .or_default()
+            .extend(&targets);
+        let Some(reference) = self.labels.get(&reference.id()).cloned() else {
+            return;
+        };
+        for target in targets {
+            if let Some(declaration) = self.target_label(target) {
+                self.trap.emit(
+                    "solidity_binding",
+                    vec![
+                        TrapValue::Label(reference.clone()),
+                        TrapValue::Label(declaration),
+                    ],
+                );
+            }
+        }
+    }
+
+    /// Get the label of what a name is bound to, recording builtins and
+    /// imported files the first time.
+    fn target_label(&mut self, target: Target<'a>) -> Option<Label> {
+        match target {
+            Target::Local(node) => self.labels.get(&node.id()).cloned(),
+            Target::Global(file, symbol) if self.resolver.in_database(file) => {
+                Some(self.trap.string_label(&declaration_key(file, symbol.start)))
+            }
+            Target::Global(..) => None,
+            Target::Unit(file) => {
+                if let Some(label) = self.written.get(file) {
+                    return Some(label.clone());
+                }
+                let label = self.trap.emit_file(file);
+                self.written.insert(file.to_string(), label.clone());
+                Some(label)
+            }
+            Target::Builtin(builtin, member) => {
+                let name = match member {
+                    Some(member) => format!("{}.{}", builtin, member),
+                    None => builtin.to_string(),
+                };
+                let key = format!("builtin;{}", name);
+                if let Some(label) = self.written.get(&key) {
+                    return Some(label.clone());
+                }
+                let label = self.trap.string_label(&key);
+                self.trap.emit(
+                    "solidity_builtin",
+                    vec![TrapValue::Label(label.clone()), TrapValue::String(name)],
+                );
+                self.written.insert(key, label.clone());
+                Some(label)
+            }
+        }
+    }
+}
+
+/// Check whether an identifier in the given field of `parent` is a use of a
+/// name (rather than a declared name, or part of a name bound by its parent).
+pub(super) fn is_reference(field: Option<&str>, parent: Option<Node>) -> bool {
+    let parent = parent.map_or("", |p| p.kind());
+    if PATH_KINDS.contains(&parent)
+        || matches!(parent, "import_directive" | "yul_function_definition")
+    {
+        return false;
+    }
+    match field {
+        Some("name") => matches!(parent, "emit_statement" | "new_expression"),
+        Some("property" | "alias" | "key_identifier" | "value_identifier") => false,
+        Some("left") => parent != "yul_variable_declaration",
+        _ => true,
+    }
+}
+
+/// Get the single named child of a wrapper node of the given kind, or the node.
+pub(super) fn unwrap<'t>(mut node: Node<'t>, wrapper: &str) -> Node<'t> {
+    while node.kind() == wrapper && node.named_child_count() == 1 {
+        match node.named_child(0) {
+            Some(child) => node = child,
+            None => break,
+        }
+    }
+    node
+}
+
+/// Count the arguments of a call; `f({a: 1, b: 2})` passes two.
+pub(super) fn call_arity(call: Node) -> usize {
+    let arguments: Vec<Node> = named_children(call)
+        .filter(|c| c.kind() == "call_argument")
+        .collect();
+    match arguments.as_slice() {
+        [single] if named_children(*single).any(|c| c.kind() == "call_struct_argument") => {
+            named_children(*single).count()
+        }
+        _ => arguments.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::cache::CacheKey;
+
+    fn declaration(name: &str, kind: &'static str, start: usize) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            kind,
+            start,
+            arity: None,
+            members: Vec::new(),
+            bases: Vec::new(),
+            type_name: None,
+            parameters: Vec::new(),
+            usings: Vec::new(),
+            is_virtual: false,
+            overrides: None,
+        }
+    }
+
+    fn function(name: &str, start: usize, arity: usize) -> Symbol {
+        Symbol {
+            arity: Some(arity),
+            ..declaration(name, "function_definition", start)
+        }
+    }
+
+    fn contract(name: &str, start: usize, bases: &[&str], members: Vec<Symbol>) -> Symbol {
+        Symbol {
+            members,
+            bases: bases.iter().map(|b| vec![b.to_string()]).collect(),
+            ..declaration(name, "contract_declaration", start)
+        }
+    }
+
+    fn names(targets: &[Target]) -> Vec<String> {
+        targets
+            .iter()
+            .map(|target| match target {
+                Target::Global(file, symbol) => format!("{}:{}", file, symbol.start),
+                Target::Unit(file) => format!("unit {}", file),
+                Target::Builtin(builtin, _) => builtin.to_string(),
+                Target::Local(_) => "local".to_string(),
+            })
+            .collect()
+    }
+
+    /// `Base.sol` declares `A` and `B is A`; `Lib.sol` imports it as a unit.
+    fn index() -> SymbolIndex {
+        let base = FileSymbols {
+            path: "Base.sol".to_string(),
+            usings: Vec::new(),
+            declarations: vec![
+                contract(
+                    "A",
+                    0,
+                    &[],
+                    vec![
+                        function("f", 10, 0),
+                        function("f", 20, 1),
+                        declaration("x", "state_variable_declaration", 30),
+                    ],
+                ),
+                contract("B", 100, &["A"], vec![function("f", 110, 0)]),
+            ],
+            imports: Vec::new(),
+        };
+        let lib = FileSymbols {
+            path: "Lib.sol".to_string(),
+            usings: Vec::new(),
+            declarations: vec![declaration("helper", "function_definition", 0)],
+            imports: vec![ImportedNames {
+                start: 50,
+                file: Some("Base.sol".to_string()),
+                symbols: vec![("*".to_string(), "M".to_string())],
+            }],
+        };
+        [base, lib].into_iter().collect()
+    }
+
+    #[test]
+    fn test_file_scope_across_imports() {
+        let index = index();
+        let own = FileSymbols {
+            path: "Main.sol".to_string(),
+            usings: Vec::new(),
+            declarations: vec![contract("C", 0, &["B"], Vec::new())],
+            imports: vec![
+                ImportedNames {
+                    start: 0,
+                    file: Some("Lib.sol".to_string()),
+                    symbols: Vec::new(),
+                },
+                ImportedNames {
+                    start: 20,
+                    file: Some("Base.sol".to_string()),
+                    symbols: vec![("B".to_string(), "B".to_string())],
+                },
+                ImportedNames {
+                    start: 40,
+                    file: None,
+                    symbols: vec![("Missing".to_string(), "Missing".to_string())],
+                },
+            ],
+        };
+        let resolver = Resolver::new(&index, &own);
+        let scope = resolver.file_scope("Main.sol");
+        assert_eq!(names(&scope["C"]), vec!["Main.sol:0"]);
+        assert_eq!(names(&scope["helper"]), vec!["Lib.sol:0"]);
+        assert_eq!(names(&scope["M"]), vec!["unit Base.sol"]);
+        assert_eq!(names(&scope["B"]), vec!["Base.sol:100"]);
+        assert!(!scope.contains_key("A"));
+        assert!(!scope.contains_key("Missing"));
+
+        // Members of a unit alias and of a contract
+        let a = resolver.members(scope["M"][0], "A");
+        assert_eq!(names(&a), vec!["Base.sol:0"]);
+        assert_eq!(names(&resolver.members(a[0], "x")), vec!["Base.sol:30"]);
+    }
+
+    #[test]
+    fn test_linearization_and_inherited_members() {
+        let index = index();
+        let own = FileSymbols {
+            path: "Main.sol".to_string(),
+            usings: Vec::new(),
+            declarations: vec![
+                contract("X", 0, &["B"], Vec::new()),
+                contract("Y", 10, &["B"], Vec::new()),
+                contract("Z", 20, &["X", "Y"], Vec::new()),
+            ],
+            imports: vec![ImportedNames {
+                start: 0,
+                file: Some("Base.sol".to_string()),
+                symbols: Vec::new(),
+            }],
+        };
+        let resolver = Resolver::new(&index, &own);
+        let z = ("Main.sol", &own.declarations[2]);
+        let linearization: Vec<&str> = resolver
+            .linearization(z)
+            .iter()
+            .map(|(_, c)| c.name.as_str())
+            .collect();
+        assert_eq!(linearization, vec!["Z", "Y", "X", "B", "A"]);
+
+        // B.f() overrides A.f(), but not its overload A.f(uint)
+        let f = resolver.lookup_in(&resolver.linearization(z), "f");
+        assert_eq!(names(&f), vec!["Base.sol:110", "Base.sol:20"]);
+        assert_eq!(
+            names(&filter_arity(f.clone(), Some(1))),
+            vec!["Base.sol:20"]
+        );
+        assert_eq!(filter_arity(f, Some(3)).len(), 2);
+    }
+
+    #[test]
+    fn test_linearization_failures_and_overrides() {
+        let index = index();
+        let overriding = |start: usize, bases: &[&str]| Symbol {
+            overrides: Some(bases.iter().map(|b| vec![b.to_string()]).collect()),
+            ..function("f", start, 0)
+        };
+        let virtual_f = |function: Symbol| Symbol {
+            is_virtual: true,
+            ..function
+        };
+        let own = FileSymbols {
+            path: "Main.sol".to_string(),
+            declarations: vec![
+                // `B` is `A`, so `A` must be listed first
+                contract("X", 0, &["B", "A"], Vec::new()),
+                contract("C1", 10, &["C2"], Vec::new()),
+                contract("C2", 20, &["C1"], Vec::new()),
+                contract("P", 30, &[], vec![virtual_f(function("f", 31, 0))]),
+                contract("Q", 40, &[], vec![virtual_f(function("f", 41, 0))]),
+                contract(
+                    "R",
+                    50,
+                    &["P", "Q"],
+                    vec![virtual_f(overriding(51, &["P", "Q"]))],
+                ),
+                contract("S", 60, &["R"], vec![overriding(61, &[])]),
+                contract("T", 70, &["B"], vec![overriding(71, &[])]),
+            ],
+            imports: vec![ImportedNames {
+                start: 0,
+                file: Some("Base.sol".to_string()),
+                symbols: Vec::new(),
+            }],
+            usings: Vec::new(),
+        };
+        let resolver = Resolver::new(&index, &own);
+        let contract = |i: usize| ("Main.sol", &own.declarations[i]);
+        for (i, fails) in [(0, true), (1, true), (5, false)] {
+            resolver.linearization(contract(i));
+            assert_eq!(resolver.linearization_failed(contract(i)), fails);
+        }
+
+        let overridden = |i: usize| -> Vec<usize> {
+            let member = &own.declarations[i].members[0];
+            let overridden = resolver.overridden(contract(i), member);
+            overridden.iter().map(|(_, f)| f.start).collect()
+        };
+        assert_eq!(overridden(5), vec![31, 41]);
+        assert_eq!(overridden(6), vec![51]);
+        // `B.f` is not virtual
+        assert!(overridden(7).is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint_covers_imported_files() {
+        let mut index = index();
+        index.insert(FileSymbols {
+            path: "Main.sol".to_string(),
+            usings: Vec::new(),
+            declarations: Vec::new(),
+            imports: vec![ImportedNames {
+                start: 0,
+                file: Some("Lib.sol".to_string()),
+                symbols: Vec::new(),
+            }],
+        });
+        let fingerprint = |index: &SymbolIndex, path: &str| {
+            let mut key = CacheKey::builder();
+            index.fingerprint(path, &mut key);
+            key.finish()
+        };
+        assert_eq!(
+            index.imported_files("Main.sol"),
+            vec!["Base.sol", "Lib.sol"]
+        );
+        let before = fingerprint(&index, "Main.sol");
+        assert_eq!(before, fingerprint(&index, "Main.sol"));
+        assert_ne!(before, fingerprint(&index, "Lib.sol"));
+
+        // A change to a file imported indirectly changes the fingerprint
+        index.insert(FileSymbols {
+            path: "Base.sol".to_string(),
+            ..FileSymbols::default()
+        });
+        let changed = fingerprint(&index, "Main.sol");
+        assert_ne!(before, changed);
+
+        // So does leaving it out of the database
+        assert!(index.includes("Base.sol"));
+        index.exclude("Base.sol");
+        assert!(!index.includes("Base.sol"));
+        assert_ne!(changed, fingerprint(&index, "Main.sol"));
+    }
+}
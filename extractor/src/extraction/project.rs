@@ -0,0 +1,656 @@
+//! Project detection.
+//!
+//! Autobuild extracts a project the way its build tool sees it: the configured
+//! source, test and script directories are extracted, build output is ignored,
+//! and dependencies are only extracted when something imports them. Every
+//! extracted file is given a role (see `solidity_file_role`).
+//!
+//! A source tree may contain several projects (a monorepo); each file belongs
+//! to the innermost project containing it.
+
+use ignore::WalkBuilder;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tracing::{debug, info, warn};
+
+use super::foundry::FoundryConfig;
+use super::imports::{scan_imports, ImportResolver, Remapping};
+use super::js_config::{HardhatConfig, TruffleConfig};
+use super::solc_ast::{BuildInfoIndex, BUILD_INFO_DIR};
+use super::source_root::path_to_string;
+use super::source_text::SourceText;
+
+/// Directories that never contain project sources.
+const ALWAYS_EXCLUDED: &[&str] = &[".git", "node_modules"];
+
+/// Directories skipped wherever they appear in a project without build configuration.
+const PLAIN_EXCLUDED: &[&str] = &["cache"];
+
+/// The build tool a project is configured for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framework {
+    /// Foundry (`foundry.toml`)
+    Foundry,
+    /// Hardhat (`hardhat.config.js` or `.ts`)
+    Hardhat,
+    /// Truffle (`truffle-config.js`)
+    Truffle,
+    /// No recognized build configuration
+    None,
+}
+
+impl Framework {
+    /// Get the name recorded in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Framework::Foundry => "foundry",
+            Framework::Hardhat => "hardhat",
+            Framework::Truffle => "truffle",
+            Framework::None => "none",
+        }
+    }
+}
+
+impl fmt::Display for Framework {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The role of a source file in its project.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileRole {
+    /// Project contracts
+    Source,
+    /// Tests
+    Test,
+    /// Deployment and maintenance scripts
+    Script,
+    /// Dependencies
+    Library,
+}
+
+impl FileRole {
+    /// Get the name recorded in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileRole::Source => "source",
+            FileRole::Test => "test",
+            FileRole::Script => "script",
+            FileRole::Library => "library",
+        }
+    }
+}
+
+/// Directories of a project, relative to its root.
+#[derive(Debug, Clone, Default)]
+struct Layout {
+    /// Directories holding project contracts (empty = the whole project)
+    sources: Vec<String>,
+    /// Directories holding tests
+    tests: Vec<String>,
+    /// Directories holding scripts
+    scripts: Vec<String>,
+    /// Directories holding dependencies
+    libraries: Vec<String>,
+    /// Build output and other directories that are never extracted
+    excluded: Vec<String>,
+    /// Directories holding build-info files of the compiler's output
+    build_info: Vec<String>,
+}
+
+/// A project found in the source tree.
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// Canonical project directory
+    root: PathBuf,
+    /// Build tool the project is configured for
+    framework: Framework,
+    /// Where sources, tests and dependencies live
+    layout: Layout,
+    /// Import resolver using the project's remappings
+    imports: ImportResolver,
+    /// Roots of the projects nested inside this one
+    nested: Vec<PathBuf>,
+    /// The compiler's output of the project's builds, loaded on first use
+    build_info: Arc<OnceLock<BuildInfoIndex>>,
+}
+
+impl Project {
+    /// Detect the project rooted at a directory.
+    ///
+    /// A directory without build configuration is treated as a plain project.
+    pub fn detect(root: &Path) -> Self {
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        Self::configured(&root).unwrap_or_else(|| Project {
+            imports: ImportResolver::discover(&root, None),
+            root,
+            framework: Framework::None,
+            layout: Layout::default(),
+            nested: Vec::new(),
+            build_info: Arc::default(),
+        })
+    }
+
+    /// Detect a project with build configuration rooted at a directory.
+    ///
+    /// When several configurations are present, Foundry's is preferred, then
+    /// Hardhat's, then Truffle's.
+    fn configured(root: &Path) -> Option<Self> {
+        let foundry = FoundryConfig::load(root).unwrap_or_else(|e| {
+            warn!("Ignoring Foundry configuration: {:#}", e);
+            None
+        });
+        let (framework, layout) = if let Some(config) = &foundry {
+            debug!("Foundry profile '{}' in {}", config.profile, root.display());
+            (Framework::Foundry, foundry_layout(config))
+        } else if let Some(config) = load_js_config(root, HardhatConfig::load) {
+            (Framework::Hardhat, hardhat_layout(&config))
+        } else if let Some(config) = load_js_config(root, TruffleConfig::load) {
+            (Framework::Truffle, truffle_layout(&config))
+        } else {
+            return None;
+        };
+
+        info!("Detected {} project in {}", framework, root.display());
+        Some(Project {
+            root: root.to_path_buf(),
+            framework,
+            layout,
+            imports: ImportResolver::discover(root, foundry.as_ref()),
+            nested: Vec::new(),
+            build_info: Arc::default(),
+        })
+    }
+
+    /// Get the canonical project directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the build tool the project is configured for.
+    pub fn framework(&self) -> Framework {
+        self.framework
+    }
+
+    /// Get the import resolver for files in this project.
+    pub fn imports(&self) -> &ImportResolver {
+        &self.imports
+    }
+
+    /// Get the compiler's output of the project's builds (Hardhat and Foundry
+    /// build-info files), loading it on first use.
+    pub fn build_info(&self) -> &BuildInfoIndex {
+        self.build_info.get_or_init(|| {
+            let dirs: Vec<PathBuf> = self
+                .layout
+                .build_info
+                .iter()
+                .map(|dir| self.root.join(dir))
+                .collect();
+            BuildInfoIndex::load(&self.root, &dirs)
+        })
+    }
+
+    /// Get the role of a file (a canonical path) in this project.
+    pub fn role_of(&self, file: &Path) -> FileRole {
+        let Ok(relative) = file.strip_prefix(&self.root) else {
+            // Only dependencies are imported from outside the project
+            return FileRole::Library;
+        };
+        let relative = path_to_string(relative);
+        let file_name = relative.rsplit('/').next().unwrap_or("");
+        let components: Vec<&str> = relative.split('/').collect();
+        let layout = &self.layout;
+
+        if in_any(&relative, &layout.libraries) || components.contains(&"node_modules") {
+            FileRole::Library
+        } else if in_any(&relative, &layout.tests) || file_name.ends_with(".t.sol") {
+            FileRole::Test
+        } else if in_any(&relative, &layout.scripts) || file_name.ends_with(".s.sol") {
+            FileRole::Script
+        } else if self.framework == Framework::None
+            && components[..components.len() - 1]
+                .iter()
+                .any(|c| matches!(*c, "test" | "tests"))
+        {
+            FileRole::Test
+        } else if self.framework == Framework::None
+            && components[..components.len() - 1]
+                .iter()
+                .any(|c| matches!(*c, "script" | "scripts"))
+        {
+            FileRole::Script
+        } else {
+            FileRole::Source
+        }
+    }
+
+    /// Find the Solidity files to extract for this project.
+    ///
+    /// The project's own directories are searched, skipping build output and
+    /// dependencies (and, if `gitignore` is set, files ignored by git);
+    /// dependency files are then added if they are imported, directly or
+    /// transitively, by a file already found.
+    pub fn discover_files(&self, gitignore: bool) -> Vec<PathBuf> {
+        let search_roots: Vec<PathBuf> = if self.layout.sources.is_empty() {
+            vec![self.root.clone()]
+        } else {
+            let layout = &self.layout;
+            layout
+                .sources
+                .iter()
+                .chain(&layout.tests)
+                .chain(&layout.scripts)
+                .map(|dir| self.root.join(dir))
+                .filter(|dir| dir.is_dir())
+                .collect()
+        };
+
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+        for search_root in &search_roots {
+            let project = self.clone();
+            let walker = WalkBuilder::new(search_root)
+                .standard_filters(false)
+                .git_ignore(gitignore)
+                .git_exclude(gitignore)
+                .parents(gitignore)
+                .require_git(false)
+                .follow_links(true)
+                .filter_entry(move |entry| {
+                    !entry.file_type().is_some_and(|t| t.is_dir())
+                        || !project.is_skipped_dir(entry.path())
+                })
+                .build();
+            for entry in walker.filter_map(|entry| entry.ok()) {
+                if entry.file_type().is_some_and(|t| t.is_file()) && is_solidity_file(entry.path())
+                {
+                    let file = entry.path().canonicalize().unwrap_or(entry.into_path());
+                    if seen.insert(file.clone()) {
+                        files.push(file);
+                    }
+                }
+            }
+        }
+
+        // Follow imports out of the searched directories
+        let mut queue: VecDeque<PathBuf> = files.iter().cloned().collect();
+        while let Some(file) = queue.pop_front() {
+            let Ok(source) = SourceText::read(&file) else {
+                continue;
+            };
+            for import in scan_imports(&source.text) {
+                if let Some(target) = self.imports.resolve(&file, &import) {
+                    if is_solidity_file(&target) && seen.insert(target.clone()) {
+                        debug!("Including imported file {}", target.display());
+                        files.push(target.clone());
+                        queue.push_back(target);
+                    }
+                }
+            }
+        }
+
+        files.sort();
+        files
+    }
+
+    /// Check whether a directory is excluded from the search.
+    ///
+    /// Nested projects are searched separately, so they are excluded too.
+    fn is_skipped_dir(&self, dir: &Path) -> bool {
+        let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if ALWAYS_EXCLUDED.contains(&name)
+            || (self.framework == Framework::None && PLAIN_EXCLUDED.contains(&name))
+            || self.nested.iter().any(|nested| nested == dir)
+        {
+            return true;
+        }
+        let Ok(relative) = dir.strip_prefix(&self.root) else {
+            return false;
+        };
+        let relative = path_to_string(relative);
+        let layout = &self.layout;
+        layout
+            .excluded
+            .iter()
+            .chain(&layout.libraries)
+            .any(|excluded| *excluded == relative)
+    }
+}
+
+/// The projects found in a source tree.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    /// Projects, starting with the one at the root of the tree
+    projects: Vec<Project>,
+}
+
+impl Workspace {
+    /// Find the projects below a directory.
+    ///
+    /// The directory itself is always a project. Other projects are found by
+    /// looking for build configuration files; dependency and build output
+    /// directories are not searched, so vendored packages that carry their own
+    /// configuration stay part of the project that uses them.
+    pub fn detect(root: &Path) -> Self {
+        let mut projects = vec![Project::detect(root)];
+        let mut pending = vec![(0, projects[0].root.clone())];
+
+        while let Some((owner, dir)) = pending.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            // Symlinked directories are not followed, to avoid cycles
+            let mut children: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+                .map(|entry| entry.path())
+                .collect();
+            children.sort();
+
+            for child in children {
+                if projects[owner].is_skipped_dir(&child) {
+                    continue;
+                }
+                match Project::configured(&child) {
+                    Some(project) => {
+                        projects[owner].nested.push(child.clone());
+                        projects.push(project);
+                        pending.push((projects.len() - 1, child));
+                    }
+                    None => pending.push((owner, child)),
+                }
+            }
+        }
+
+        Workspace { projects }
+    }
+
+    /// Add remappings to every project, taking precedence over the project's own.
+    pub fn with_remappings(mut self, remappings: &[Remapping]) -> Self {
+        for project in &mut self.projects {
+            project.imports.add_remappings(remappings);
+        }
+        self
+    }
+
+    /// Get the projects, starting with the one at the root of the tree.
+    pub fn projects(&self) -> &[Project] {
+        &self.projects
+    }
+
+    /// Get the project a file (a canonical path) belongs to.
+    ///
+    /// This is the innermost project containing the file; files outside the
+    /// tree belong to the root project.
+    pub fn project_for(&self, file: &Path) -> &Project {
+        self.projects
+            .iter()
+            .filter(|project| file.starts_with(&project.root))
+            .max_by_key(|project| project.root.components().count())
+            .unwrap_or(&self.projects[0])
+    }
+
+    /// Find the Solidity files to extract for every project.
+    pub fn discover_files(&self, gitignore: bool) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .projects
+            .iter()
+            .flat_map(|project| project.discover_files(gitignore))
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+}
+
+/// Load a JavaScript build configuration, logging failures.
+fn load_js_config<T>(root: &Path, load: fn(&Path) -> anyhow::Result<Option<T>>) -> Option<T> {
+    load(root).unwrap_or_else(|e| {
+        warn!("Ignoring build configuration: {:#}", e);
+        None
+    })
+}
+
+/// Layout of a Foundry project.
+fn foundry_layout(config: &FoundryConfig) -> Layout {
+    let dir = |d: &str| normalize_dir(d);
+    Layout {
+        sources: vec![dir(&config.src)],
+        tests: vec![dir(&config.test)],
+        scripts: vec![dir(&config.script)],
+        libraries: config.libs.iter().map(|d| dir(d)).collect(),
+        excluded: vec![
+            dir(&config.out),
+            dir(&config.cache_path),
+            dir(&config.broadcast),
+        ],
+        build_info: vec![format!("{}/{}", dir(&config.out), BUILD_INFO_DIR)],
+    }
+}
+
+/// Layout of a Hardhat project.
+fn hardhat_layout(config: &HardhatConfig) -> Layout {
+    Layout {
+        sources: vec![normalize_dir(&config.sources)],
+        tests: vec![normalize_dir(&config.tests)],
+        scripts: vec!["scripts".to_string()],
+        libraries: Vec::new(),
+        excluded: vec![
+            normalize_dir(&config.cache),
+            normalize_dir(&config.artifacts),
+        ],
+        build_info: vec![format!(
+            "{}/{}",
+            normalize_dir(&config.artifacts),
+            BUILD_INFO_DIR
+        )],
+    }
+}
+
+/// Layout of a Truffle project.
+fn truffle_layout(config: &TruffleConfig) -> Layout {
+    Layout {
+        sources: vec![normalize_dir(&config.contracts)],
+        tests: vec![normalize_dir(&config.test)],
+        scripts: vec![normalize_dir(&config.migrations)],
+        libraries: Vec::new(),
+        excluded: vec![normalize_dir(&config.build)],
+        build_info: Vec::new(),
+    }
+}
+
+/// Normalize a configured directory: no `./` prefix or trailing `/`.
+fn normalize_dir(dir: &str) -> String {
+    let dir = dir.trim_start_matches("./").trim_end_matches('/');
+    if dir.is_empty() {
+        ".".to_string()
+    } else {
+        dir.to_string()
+    }
+}
+
+/// Check whether a relative path is inside one of the given directories.
+fn in_any(relative: &str, dirs: &[String]) -> bool {
+    dirs.iter().any(|dir| {
+        dir != "."
+            && relative
+                .strip_prefix(dir.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Check whether a path names a Solidity source file.
+fn is_solidity_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sol")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchDir;
+
+    fn relative(root: &Path, files: &[PathBuf]) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| path_to_string(f.strip_prefix(root).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_foundry_discovery() {
+        let root = ScratchDir::with_files(
+            "foundry",
+            &[
+                ("foundry.toml", "[profile.default]\nsrc = 'contracts'\n"),
+                ("contracts/Vault.sol", "import 'solmate/tokens/ERC20.sol';"),
+                ("test/Vault.t.sol", "import '../contracts/Vault.sol';"),
+                ("script/Deploy.s.sol", ""),
+                (
+                    "lib/solmate/src/tokens/ERC20.sol",
+                    "import '../utils/Math.sol';",
+                ),
+                ("lib/solmate/src/utils/Math.sol", ""),
+                ("lib/solmate/src/Unused.sol", ""),
+                ("out/Vault.sol/Vault.sol", ""),
+                ("broadcast/Deploy.s.sol/1/Deploy.sol", ""),
+                ("cache/Stale.sol", ""),
+            ],
+        );
+        let project = Project::detect(&root);
+        assert_eq!(project.framework(), Framework::Foundry);
+
+        let files = project.discover_files(false);
+        assert_eq!(
+            relative(&root, &files),
+            vec![
+                "contracts/Vault.sol",
+                "lib/solmate/src/tokens/ERC20.sol",
+                "lib/solmate/src/utils/Math.sol",
+                "script/Deploy.s.sol",
+                "test/Vault.t.sol",
+            ]
+        );
+
+        let roles: Vec<&str> = files.iter().map(|f| project.role_of(f).as_str()).collect();
+        assert_eq!(
+            roles,
+            vec!["source", "library", "library", "script", "test"]
+        );
+    }
+
+    #[test]
+    fn test_plain_discovery() {
+        let root = ScratchDir::with_files(
+            "plain",
+            &[
+                ("contracts/Token.sol", "import '@oz/ERC20.sol';"),
+                ("tests/Token.sol", ""),
+                ("node_modules/@oz/ERC20.sol", ""),
+                ("node_modules/@oz/Unused.sol", ""),
+                ("cache/Token.sol", ""),
+            ],
+        );
+        let project = Project::detect(&root);
+        assert_eq!(project.framework(), Framework::None);
+
+        let files = project.discover_files(false);
+        assert_eq!(
+            relative(&root, &files),
+            vec![
+                "contracts/Token.sol",
+                "node_modules/@oz/ERC20.sol",
+                "tests/Token.sol",
+            ]
+        );
+        let roles: Vec<&str> = files.iter().map(|f| project.role_of(f).as_str()).collect();
+        assert_eq!(roles, vec!["source", "library", "test"]);
+    }
+
+    #[test]
+    fn test_monorepo_discovery() {
+        let root = ScratchDir::with_files(
+            "monorepo",
+            &[
+                ("shared/Types.sol", ""),
+                (
+                    "packages/core/hardhat.config.ts",
+                    "export default { paths: { sources: './src' } };",
+                ),
+                ("packages/core/src/Core.sol", "import '@oz/Ownable.sol';"),
+                ("packages/core/artifacts/build-info/Core.sol", ""),
+                ("packages/core/node_modules/@oz/Ownable.sol", ""),
+                ("packages/legacy/truffle-config.js", "module.exports = {};"),
+                ("packages/legacy/contracts/Legacy.sol", ""),
+                ("packages/legacy/build/contracts/Legacy.sol", ""),
+                ("packages/vault/foundry.toml", ""),
+                ("packages/vault/src/Vault.sol", ""),
+                // A vendored package with its own configuration is not a project
+                ("packages/vault/lib/dep/foundry.toml", ""),
+                ("packages/vault/lib/dep/src/Dep.sol", ""),
+            ],
+        );
+        let workspace = Workspace::detect(&root);
+        let frameworks: Vec<&str> = workspace
+            .projects()
+            .iter()
+            .map(|p| p.framework().as_str())
+            .collect();
+        assert_eq!(frameworks, vec!["none", "hardhat", "truffle", "foundry"]);
+
+        let files = workspace.discover_files(false);
+        assert_eq!(
+            relative(&root, &files),
+            vec![
+                "packages/core/node_modules/@oz/Ownable.sol",
+                "packages/core/src/Core.sol",
+                "packages/legacy/contracts/Legacy.sol",
+                "packages/vault/src/Vault.sol",
+                "shared/Types.sol",
+            ]
+        );
+
+        let owner = |file: &str| {
+            let project = workspace.project_for(&root.join(file));
+            path_to_string(project.root().strip_prefix(&root).unwrap())
+        };
+        assert_eq!(owner("packages/core/src/Core.sol"), "packages/core");
+        assert_eq!(
+            owner("packages/vault/lib/dep/src/Dep.sol"),
+            "packages/vault"
+        );
+        assert_eq!(owner("shared/Types.sol"), "");
+    }
+
+    #[test]
+    fn test_gitignore_discovery() {
+        let root = ScratchDir::with_files(
+            "gitignore",
+            &[
+                (".gitignore", "generated/\n*.bak.sol\n"),
+                ("contracts/Token.sol", ""),
+                ("contracts/Token.bak.sol", ""),
+                ("generated/Bindings.sol", ""),
+            ],
+        );
+        let project = Project::detect(&root);
+        assert_eq!(
+            relative(&root, &project.discover_files(true)),
+            vec!["contracts/Token.sol"]
+        );
+        assert_eq!(project.discover_files(false).len(), 3);
+    }
+
+    #[test]
+    fn test_in_any() {
+        let dirs = vec!["lib".to_string(), "test/unit".to_string()];
+        assert!(in_any("lib/a/B.sol", &dirs));
+        assert!(in_any("test/unit/B.sol", &dirs));
+        assert!(!in_any("library/B.sol", &dirs));
+        assert!(!in_any("test/B.sol", &dirs));
+    }
+}
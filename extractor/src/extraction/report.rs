@@ -0,0 +1,303 @@
+//! Machine-readable extraction report.
+//!
+//! At the end of a run, a JSON report (by default `extraction-report.json` in
+//! the TRAP directory) records what happened to each file and how long it
+//! took, so regressions and slow files can be tracked across CI runs:
+//!
+//! ```json
+//! {
+//!   "version": "0.1.0",
+//!   "threads": 8,
+//!   "duration_ms": 1234.5,
+//!   "totals": { "files": 2, "extracted": 1, "cached": 0, "skipped": 0, "failed": 1, ... },
+//!   "files": [
+//!     { "path": "src/Token.sol", "status": "extracted", "parse_ms": 3.1, "trap_ms": 9.7,
+//!       "nodes": 5120, "tuples": 21044, "error_nodes": 0, "bytes_in": 4096, "bytes_out": 61234 },
+//!     { "path": "src/Broken.sol", "status": "failed", ..., "failure": "Failed to read file: ..." }
+//!   ]
+//! }
+//! ```
+//!
+//! Times are in milliseconds. `trap_ms` covers walking the syntax tree and
+//! writing (and compressing) the TRAP file; `bytes_out` is the size of the TRAP
+//! file on disk.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use super::FileResult;
+
+/// Default file name of the report, in the TRAP directory.
+pub const REPORT_FILE: &str = "extraction-report.json";
+
+/// What happened to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    /// Extracted to TRAP
+    Extracted,
+    /// TRAP reused from the cache
+    Cached,
+    /// Not extracted on purpose (a directory, or over a limit)
+    Skipped,
+    /// Could not be extracted
+    Failed,
+}
+
+/// Measurements for one processed file.
+#[derive(Debug, Clone, Default)]
+pub struct FileStats {
+    /// Whether the TRAP file was reused from the cache
+    pub cached: bool,
+    /// Time spent parsing
+    pub parse_time: Duration,
+    /// Time spent walking the syntax tree and writing TRAP
+    pub trap_time: Duration,
+    /// Number of syntax tree nodes extracted
+    pub nodes: usize,
+    /// Number of TRAP tuples written
+    pub tuples: usize,
+    /// Number of ERROR and MISSING nodes
+    pub error_nodes: usize,
+    /// Size of the TRAP file
+    pub bytes_out: u64,
+}
+
+/// Report entry for one file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    /// Path relative to the source root (absolute for a file outside it)
+    pub path: String,
+    /// What happened to the file
+    pub status: FileStatus,
+    /// Parse time in milliseconds
+    pub parse_ms: f64,
+    /// Tree walk and TRAP write time in milliseconds
+    pub trap_ms: f64,
+    /// Number of syntax tree nodes extracted
+    pub nodes: usize,
+    /// Number of TRAP tuples written
+    pub tuples: usize,
+    /// Number of ERROR and MISSING nodes
+    pub error_nodes: usize,
+    /// Size of the source file in bytes
+    pub bytes_in: u64,
+    /// Size of the TRAP file in bytes
+    pub bytes_out: u64,
+    /// Why the file was skipped or failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+impl FileReport {
+    /// Build the entry for a file.
+    pub fn new(result: &FileResult) -> Self {
+        let path = result.relative.clone();
+        let bytes_in = result.bytes_in;
+        match &result.outcome {
+            Ok(stats) => FileReport {
+                path,
+                status: if stats.cached {
+                    FileStatus::Cached
+                } else {
+                    FileStatus::Extracted
+                },
+                parse_ms: millis(stats.parse_time),
+                trap_ms: millis(stats.trap_time),
+                nodes: stats.nodes,
+                tuples: stats.tuples,
+                error_nodes: stats.error_nodes,
+                bytes_in,
+                bytes_out: stats.bytes_out,
+                failure: None,
+            },
+            Err(e) => FileReport {
+                path,
+                status: if e.is_skip() {
+                    FileStatus::Skipped
+                } else {
+                    FileStatus::Failed
+                },
+                parse_ms: 0.0,
+                trap_ms: 0.0,
+                nodes: 0,
+                tuples: 0,
+                error_nodes: 0,
+                bytes_in,
+                bytes_out: 0,
+                failure: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Totals over all files of a run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReportTotals {
+    /// Number of files in the report
+    pub files: usize,
+    /// Number of files extracted
+    pub extracted: usize,
+    /// Number of files whose TRAP was reused from the cache
+    pub cached: usize,
+    /// Number of files skipped
+    pub skipped: usize,
+    /// Number of files that failed
+    pub failed: usize,
+    /// Total parse time in milliseconds (summed over threads)
+    pub parse_ms: f64,
+    /// Total tree walk and TRAP write time in milliseconds (summed over threads)
+    pub trap_ms: f64,
+    /// Total number of syntax tree nodes
+    pub nodes: usize,
+    /// Total number of TRAP tuples
+    pub tuples: usize,
+    /// Total number of ERROR and MISSING nodes
+    pub error_nodes: usize,
+    /// Total size of the source files in bytes
+    pub bytes_in: u64,
+    /// Total size of the TRAP files in bytes
+    pub bytes_out: u64,
+}
+
+/// Report for a whole run.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractionReport {
+    /// Extractor version
+    pub version: &'static str,
+    /// Number of extraction threads
+    pub threads: usize,
+    /// Wall-clock time of the run in milliseconds
+    pub duration_ms: f64,
+    /// Totals over all files
+    pub totals: ReportTotals,
+    /// One entry per file, sorted by path
+    pub files: Vec<FileReport>,
+}
+
+impl ExtractionReport {
+    /// Build the report of a run from its file entries.
+    pub fn new(threads: usize, duration: Duration, mut files: Vec<FileReport>) -> Self {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut totals = ReportTotals {
+            files: files.len(),
+            ..ReportTotals::default()
+        };
+        for file in &files {
+            match file.status {
+                FileStatus::Extracted => totals.extracted += 1,
+                FileStatus::Cached => totals.cached += 1,
+                FileStatus::Skipped => totals.skipped += 1,
+                FileStatus::Failed => totals.failed += 1,
+            }
+            totals.parse_ms += file.parse_ms;
+            totals.trap_ms += file.trap_ms;
+            totals.nodes += file.nodes;
+            totals.tuples += file.tuples;
+            totals.error_nodes += file.error_nodes;
+            totals.bytes_in += file.bytes_in;
+            totals.bytes_out += file.bytes_out;
+        }
+        ExtractionReport {
+            version: env!("CARGO_PKG_VERSION"),
+            threads,
+            duration_ms: millis(duration),
+            totals,
+            files,
+        }
+    }
+
+    /// Write the report as JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create report: {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Convert a duration to (fractional) milliseconds.
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_totals() {
+        use crate::extraction::FileError;
+        use std::path::PathBuf;
+
+        let result = |path: &str, bytes_in, outcome| FileResult {
+            path: PathBuf::from(path),
+            relative: path.to_string(),
+            bytes_in,
+            outcome,
+        };
+        let extracted = FileStats {
+            parse_time: Duration::from_millis(2),
+            trap_time: Duration::from_millis(3),
+            nodes: 10,
+            tuples: 40,
+            error_nodes: 1,
+            bytes_out: 50,
+            ..FileStats::default()
+        };
+        let cached = FileStats {
+            cached: true,
+            bytes_out: 5,
+            ..FileStats::default()
+        };
+        let files = vec![
+            FileReport::new(&result("b.sol", 100, Ok(extracted))),
+            FileReport::new(&result("a.sol", 10, Ok(cached))),
+            FileReport::new(&result(
+                "c.sol",
+                7,
+                Err(FileError::Panic("boom".to_string())),
+            )),
+            FileReport::new(&result("d", 0, Err(FileError::Directory))),
+        ];
+        let report = ExtractionReport::new(4, Duration::from_secs(1), files);
+
+        assert_eq!(report.files[0].path, "a.sol");
+        assert_eq!(report.files[0].status, FileStatus::Cached);
+        assert_eq!(report.files[2].status, FileStatus::Failed);
+        assert_eq!(
+            report.files[2].failure.as_deref(),
+            Some("Extractor panicked: boom")
+        );
+        assert_eq!(report.files[3].status, FileStatus::Skipped);
+        let totals = &report.totals;
+        assert_eq!(
+            (
+                totals.files,
+                totals.extracted,
+                totals.cached,
+                totals.skipped,
+                totals.failed
+            ),
+            (4, 1, 1, 1, 1)
+        );
+        assert_eq!(
+            (totals.nodes, totals.tuples, totals.error_nodes),
+            (10, 40, 1)
+        );
+        assert_eq!((totals.bytes_in, totals.bytes_out), (117, 55));
+        assert_eq!(totals.parse_ms, 2.0);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["threads"], 4);
+        assert_eq!(json["files"][1]["status"], "extracted");
+        assert!(json["files"][1].get("failure").is_none());
+    }
+}
@@ -0,0 +1,1011 @@
+//! Library API for extracting a set of files.
+//!
+//! An [`ExtractionSession`] extracts files given as paths or as in-memory
+//! sources, on a thread pool of its own, so a process can run any number of
+//! sessions (one per project, say). Each file's outcome is returned rather than
+//! turned into an error: deciding whether a run failed is up to the caller.
+//!
+//! ```no_run
+//! use codeql_extractor_solidity::extraction::{ExtractionSession, SourceInput};
+//!
+//! let results = ExtractionSession::new("out/trap", "out/src")
+//!     .with_source_root("project")
+//!     .with_threads(4)
+//!     .extract(vec![
+//!         SourceInput::from("project/src/Token.sol"),
+//!         SourceInput::source("project/src/Generated.sol", "contract G {}"),
+//!     ])?;
+//! for file in &results.files {
+//!     if let Err(e) = &file.outcome {
+//!         eprintln!("{}: {}", file.relative, e);
+//!     }
+//! }
+//! # anyhow::Ok(())
+//! ```
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+use super::bundle::{DeployedContract, VerifiedBundle};
+use super::cache::TrapCache;
+use super::config::ExtractorConfig;
+use super::extractor::LimitExceeded;
+use super::import_graph::{DiffReason, ImportGraph};
+use super::imports::ImportResolver;
+use super::names::{FileSymbols, SymbolIndex};
+use super::report::{ExtractionReport, FileReport, FileStats};
+use super::standard_json::{CompilerSettings, StandardJsonInput};
+use super::{
+    is_cached, process_file, reuse_file, summarize_file, write_compiler_settings_file,
+    write_diff_scope, write_source_location_prefix, Diagnostic, DiagnosticsWriter, FileError,
+    ParsedFile, RunContext, SourceRoot, Workspace,
+};
+use crate::trap::Compression;
+
+/// A file to extract.
+#[derive(Debug, Clone)]
+pub enum SourceInput {
+    /// A file on disk
+    Path(PathBuf),
+    /// A file whose contents are given. The path is recorded in the database
+    /// and imports are resolved relative to it; it need not exist.
+    Source {
+        /// Path of the file
+        path: PathBuf,
+        /// Contents of the file
+        text: String,
+    },
+    /// A solc standard-JSON input file, whose sources are extracted at their
+    /// source unit names (see `StandardJsonInput`)
+    StandardJson(PathBuf),
+    /// A verified-source bundle of a deployed contract, whose sources are
+    /// extracted below the contract's address (see `VerifiedBundle`)
+    Bundle(PathBuf),
+}
+
+impl SourceInput {
+    /// A file whose contents are given.
+    pub fn source(path: impl Into<PathBuf>, text: impl Into<String>) -> Self {
+        SourceInput::Source {
+            path: path.into(),
+            text: text.into(),
+        }
+    }
+
+    /// Get the path of the file.
+    pub fn path(&self) -> &Path {
+        match self {
+            SourceInput::Path(path)
+            | SourceInput::Source { path, .. }
+            | SourceInput::StandardJson(path)
+            | SourceInput::Bundle(path) => path,
+        }
+    }
+
+    /// Get the contents of the file, if they were given.
+    fn text(&self) -> Option<&str> {
+        match self {
+            SourceInput::Path(_) | SourceInput::StandardJson(_) | SourceInput::Bundle(_) => None,
+            SourceInput::Source { text, .. } => Some(text),
+        }
+    }
+}
+
+impl From<PathBuf> for SourceInput {
+    fn from(path: PathBuf) -> Self {
+        SourceInput::Path(path)
+    }
+}
+
+impl From<&Path> for SourceInput {
+    fn from(path: &Path) -> Self {
+        SourceInput::Path(path.to_path_buf())
+    }
+}
+
+impl From<&str> for SourceInput {
+    fn from(path: &str) -> Self {
+        SourceInput::Path(PathBuf::from(path))
+    }
+}
+
+impl From<(PathBuf, String)> for SourceInput {
+    fn from((path, text): (PathBuf, String)) -> Self {
+        SourceInput::Source { path, text }
+    }
+}
+
+/// A standard-JSON input or bundle, expanded into its sources.
+struct MultiSourceInput {
+    /// Path of the input recorded in the database
+    recorded: String,
+    /// Compiler settings of the sources
+    settings: CompilerSettings,
+    /// The deployment, for a bundle
+    contract: Option<DeployedContract>,
+    /// Resolver of imports among the sources
+    resolver: Arc<ImportResolver>,
+    /// Paths recorded in the database of the sources extracted
+    files: Vec<String>,
+}
+
+/// The outcome of extracting one file.
+#[derive(Debug)]
+pub struct FileResult {
+    /// Canonical path of the file
+    pub path: PathBuf,
+    /// Path shown in logs, diagnostics and the report: relative to the source
+    /// root (absolute for a file outside it). The database records `path`.
+    pub relative: String,
+    /// Size of the source in bytes
+    pub bytes_in: u64,
+    /// Measurements of the extraction, or why the file was not extracted
+    pub outcome: Result<FileStats, FileError>,
+}
+
+impl FileResult {
+    /// Check whether the file was skipped on purpose.
+    pub fn is_skipped(&self) -> bool {
+        self.outcome.as_ref().is_err_and(FileError::is_skip)
+    }
+
+    /// Check whether the file failed to extract.
+    pub fn is_failed(&self) -> bool {
+        self.outcome.as_ref().is_err_and(|e| !e.is_skip())
+    }
+}
+
+/// The outcome of a session's run.
+#[derive(Debug)]
+pub struct ExtractionResults {
+    /// One result per file, sorted by relative path. Files excluded by the
+    /// configuration, or left out in diff mode, are not included.
+    pub files: Vec<FileResult>,
+    /// Number of threads used
+    pub threads: usize,
+    /// Wall-clock time of the run
+    pub duration: Duration,
+}
+
+impl ExtractionResults {
+    /// Get the number of files extracted (or reused from the cache).
+    pub fn succeeded(&self) -> usize {
+        self.files.iter().filter(|f| f.outcome.is_ok()).count()
+    }
+
+    /// Get the number of files skipped on purpose.
+    pub fn skipped(&self) -> usize {
+        self.files.iter().filter(|f| f.is_skipped()).count()
+    }
+
+    /// Get the number of files that failed to extract.
+    pub fn failed(&self) -> usize {
+        self.files.iter().filter(|f| f.is_failed()).count()
+    }
+
+    /// Build the machine-readable report of the run.
+    pub fn report(&self) -> ExtractionReport {
+        let files = self.files.iter().map(FileReport::new).collect();
+        ExtractionReport::new(self.threads, self.duration, files)
+    }
+}
+
+/// Extracts sets of files into a TRAP directory and source archive.
+pub struct ExtractionSession {
+    /// Output directory for TRAP files
+    trap_dir: PathBuf,
+    /// Output directory for source archive
+    source_archive_dir: PathBuf,
+    /// Source root, recorded in `sourceLocationPrefix` (None = current directory)
+    source_root: Option<PathBuf>,
+    /// File selection, remappings and limits
+    config: ExtractorConfig,
+    /// Compression mode (None = the configuration's, or gzip)
+    compression: Option<Compression>,
+    /// Number of threads (None = one per CPU)
+    threads: Option<usize>,
+    /// Directory for reusing TRAP files between runs (None = no cache)
+    trap_cache: Option<PathBuf>,
+    /// Files changed by a pull request (None = extract everything)
+    changed_files: Option<Vec<PathBuf>>,
+    /// Where to write the JSON report (None = no report)
+    report: Option<PathBuf>,
+    /// Status-page diagnostics sink
+    diagnostics: DiagnosticsWriter,
+}
+
+impl ExtractionSession {
+    /// Create a session writing to the given TRAP directory and source archive.
+    ///
+    /// Diagnostics go to the CodeQL diagnostic directory, if one is set in the
+    /// environment; nothing else is read from the environment.
+    pub fn new(trap_dir: impl Into<PathBuf>, source_archive_dir: impl Into<PathBuf>) -> Self {
+        ExtractionSession {
+            trap_dir: trap_dir.into(),
+            source_archive_dir: source_archive_dir.into(),
+            source_root: None,
+            config: ExtractorConfig::default(),
+            compression: None,
+            threads: None,
+            trap_cache: None,
+            changed_files: None,
+            report: None,
+            diagnostics: DiagnosticsWriter::from_env(),
+        }
+    }
+
+    /// Extract files from this source root (by default, the current directory).
+    /// Relative input paths are resolved against it, and the paths in the
+    /// results, diagnostics and report are relative to it.
+    pub fn with_source_root(mut self, source_root: impl Into<PathBuf>) -> Self {
+        self.source_root = Some(source_root.into());
+        self
+    }
+
+    /// Use the given configuration (see `ExtractorConfig::load`).
+    pub fn with_config(mut self, config: ExtractorConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Compress TRAP files with the given mode, rather than the configuration's.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Extract on this many threads (by default, one per CPU).
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Reuse TRAP files of unchanged files from this directory.
+    pub fn with_trap_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.trap_cache = Some(dir.into());
+        self
+    }
+
+    /// Extract only the given changed files and the files they affect through
+    /// imports (paths may be relative to the source root).
+    ///
+    /// The names of all files are still collected, but the other files are
+    /// not extracted: their TRAP is copied from the TRAP cache (see
+    /// `with_trap_cache`), from a run on the base branch, say, when it has an
+    /// entry for them and for the files they import. Files it has no entry
+    /// for are left out of the database. Reading a base database is not
+    /// implemented.
+    pub fn with_changed_files(mut self, changed_files: Vec<PathBuf>) -> Self {
+        self.changed_files = Some(changed_files);
+        self
+    }
+
+    /// Write the JSON extraction report to this path after each run.
+    pub fn with_report(mut self, path: impl Into<PathBuf>) -> Self {
+        self.report = Some(path.into());
+        self
+    }
+
+    /// Write status-page diagnostics with the given writer.
+    pub fn with_diagnostics(mut self, diagnostics: DiagnosticsWriter) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &ExtractorConfig {
+        &self.config
+    }
+
+    /// Get the status-page diagnostics sink.
+    pub fn diagnostics(&self) -> &DiagnosticsWriter {
+        &self.diagnostics
+    }
+
+    /// Extract files.
+    ///
+    /// Fails only if the run cannot start (say, an output directory cannot be
+    /// created); the outcome of each file is in the results.
+    pub fn extract<I>(&self, inputs: I) -> Result<ExtractionResults>
+    where
+        I: IntoIterator,
+        I::Item: Into<SourceInput>,
+    {
+        let start = Instant::now();
+        let inputs: Vec<SourceInput> = inputs.into_iter().map(Into::into).collect();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads.unwrap_or(0))
+            .build()
+            .context("Failed to create thread pool")?;
+        let files = pool.install(|| self.extract_on_pool(&inputs))?;
+
+        let results = ExtractionResults {
+            files,
+            threads: pool.current_num_threads(),
+            duration: start.elapsed(),
+        };
+        info!(
+            "Extraction complete: {} succeeded, {} skipped, {} failed",
+            results.succeeded(),
+            results.skipped(),
+            results.failed()
+        );
+        if let Some(path) = &self.report {
+            match results.report().write(path) {
+                Ok(()) => info!("Extraction report: {}", path.display()),
+                Err(e) => warn!("Failed to write extraction report: {:#}", e),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Extract files on the session's thread pool.
+    fn extract_on_pool(&self, inputs: &[SourceInput]) -> Result<Vec<FileResult>> {
+        let config = &self.config;
+
+        // Ensure output directories exist
+        fs::create_dir_all(&self.trap_dir).context("Failed to create TRAP directory")?;
+        fs::create_dir_all(&self.source_archive_dir)
+            .context("Failed to create source archive directory")?;
+
+        let source_root = match &self.source_root {
+            Some(root) => SourceRoot::new(root)?,
+            None => SourceRoot::new(&std::env::current_dir()?)?,
+        };
+        info!("Source root: {}", source_root.path().display());
+        let compression = self.compression.or(config.compression).unwrap_or_default();
+        write_source_location_prefix(&self.trap_dir, &source_root, compression)?;
+
+        // Expand standard-JSON inputs and bundles into their sources, whose
+        // imports resolve among themselves. The sources are placed below the
+        // input's file, where no file on disk can be, so they never collide
+        // with checked-out files; a bundle's below a directory of their own.
+        let mut results = Vec::new();
+        let mut multi_source = Vec::new();
+        let mut expanded = Vec::new();
+        for input in inputs {
+            let (path, read): (&PathBuf, fn(&Path) -> Result<_>) = match input {
+                SourceInput::StandardJson(path) => (path, |path| {
+                    Ok((StandardJsonInput::read(path)?, None, path.to_path_buf()))
+                }),
+                SourceInput::Bundle(path) => (path, |path| {
+                    let bundle = VerifiedBundle::read(path)?;
+                    let root = VerifiedBundle::file(path).join(&bundle.prefix);
+                    Ok((bundle.input, Some(bundle.contract), root))
+                }),
+                _ => continue,
+            };
+            let canonical = source_root.canonicalize(&source_root.path().join(path));
+            let recorded = source_root.recorded_path(&canonical);
+            let relative = source_root.relative_path(&canonical);
+            match read(&canonical) {
+                Ok((json, contract, root)) => {
+                    info!("Input {}: {} sources", relative, json.sources.len());
+                    let sources = json
+                        .sources
+                        .iter()
+                        .map(|(unit, _)| root.join(unit))
+                        .collect();
+                    let resolver = Arc::new(ImportResolver::in_memory(
+                        &root,
+                        &json.settings.remappings,
+                        sources,
+                    ));
+                    let index = multi_source.len();
+                    for (unit, text) in json.sources {
+                        expanded.push((SourceInput::source(root.join(unit), text), Some(index)));
+                    }
+                    multi_source.push(MultiSourceInput {
+                        recorded,
+                        settings: json.settings,
+                        contract,
+                        resolver,
+                        files: Vec::new(),
+                    });
+                }
+                Err(e) => results.push(FileResult {
+                    bytes_in: fs::metadata(&canonical).map_or(0, |m| m.len()),
+                    path: canonical,
+                    outcome: Err(match input {
+                        SourceInput::Bundle(_) => FileError::Bundle(e),
+                        _ => FileError::StandardJson(e),
+                    }),
+                    relative,
+                }),
+            }
+        }
+
+        // Resolve symlinks and duplicate entries to one canonical file each.
+        // Directories and other non-files cannot be extracted, and the
+        // configuration or code scanning path filters may exclude files.
+        let mut seen = HashSet::new();
+        let mut files: Vec<(PathBuf, Option<&str>)> = Vec::new();
+        let mut source_imports = HashMap::new();
+        let listed = inputs
+            .iter()
+            .filter(|input| !matches!(input, SourceInput::StandardJson(_) | SourceInput::Bundle(_)))
+            .map(|input| (input, None));
+        for (input, multi_index) in listed.chain(expanded.iter().map(|(input, i)| (input, *i))) {
+            // The sources of an input have no file on disk to resolve
+            let canonical = match multi_index {
+                Some(_) => input.path().to_path_buf(),
+                None => source_root.canonicalize(&source_root.path().join(input.path())),
+            };
+            let relative = source_root.relative_path(&canonical);
+            let size = match input.text() {
+                Some(text) => text.len() as u64,
+                None => fs::metadata(&canonical).map_or(0, |m| m.len()),
+            };
+            let skip = |error: FileError| FileResult {
+                path: canonical.clone(),
+                relative: relative.clone(),
+                bytes_in: size,
+                outcome: Err(error),
+            };
+            if input.text().is_none() && canonical.is_dir() {
+                results.push(skip(FileError::Directory));
+            } else if !config.selects(&relative) {
+                debug!("Excluded by path filters: {}", input.path().display());
+            } else if let Some(max) = config.max_file_size.filter(|&max| size > max) {
+                results.push(skip(FileError::LimitExceeded(LimitExceeded::FileSize(max))));
+            } else if !seen.insert(canonical.clone()) {
+                info!(
+                    "Skipping duplicate of {}: {}",
+                    canonical.display(),
+                    input.path().display()
+                );
+            } else {
+                if let Some(index) = multi_index {
+                    let multi = &mut multi_source[index];
+                    source_imports.insert(canonical.clone(), multi.resolver.clone());
+                    multi.files.push(source_root.recorded_path(&canonical));
+                }
+                files.push((canonical, input.text()));
+            }
+        }
+        for result in &results {
+            self.log_failure(result);
+        }
+        for multi in &multi_source {
+            write_compiler_settings_file(
+                &self.trap_dir,
+                &multi.recorded,
+                &multi.settings,
+                multi.contract.as_ref(),
+                &multi.files,
+                compression,
+            )?;
+        }
+
+        let workspace = Workspace::detect(source_root.path()).with_remappings(&config.remappings);
+
+        // In diff mode, only the files a change can affect are extracted
+        let mut in_scope: Option<HashSet<PathBuf>> = None;
+        if let Some(changed_files) = &self.changed_files {
+            let texts: HashMap<&Path, &str> = files
+                .iter()
+                .filter_map(|(file, text)| Some((file.as_path(), (*text)?)))
+                .collect();
+            let paths: Vec<PathBuf> = files.iter().map(|(file, _)| file.clone()).collect();
+            let listed: HashSet<&PathBuf> = paths.iter().collect();
+            // Deleted and unselected files are kept, for their importers
+            let changed: Vec<PathBuf> = changed_files
+                .iter()
+                .map(|file| source_root.canonicalize(&source_root.path().join(file)))
+                .collect();
+            let scope: Vec<(PathBuf, DiffReason)> = ImportGraph::build(&paths, &workspace, &texts)
+                .diff_scope(&changed)
+                .into_iter()
+                .filter(|(file, _)| listed.contains(file))
+                .collect();
+            info!(
+                "Diff mode: {} changed files affect {} of {} files",
+                changed.len(),
+                scope.len(),
+                files.len()
+            );
+            write_diff_scope(&self.trap_dir, &source_root, &scope, compression)?;
+            in_scope = Some(scope.into_iter().map(|(file, _)| file).collect());
+        }
+
+        let cache = match &self.trap_cache {
+            Some(dir) => {
+                info!("Using TRAP cache: {}", dir.display());
+                Some(TrapCache::new(dir)?)
+            }
+            None => None,
+        };
+
+        let mut context = RunContext {
+            trap_dir: &self.trap_dir,
+            source_archive_dir: &self.source_archive_dir,
+            compression,
+            source_root: &source_root,
+            workspace: &workspace,
+            cache: cache.as_ref(),
+            limits: config.limits,
+            source_imports: &source_imports,
+            symbols: SymbolIndex::default(),
+        };
+
+        let file_result =
+            |file: &PathBuf, text: Option<&str>, outcome: Result<FileStats, FileError>| {
+                let result = FileResult {
+                    path: file.clone(),
+                    relative: source_root.relative_path(file),
+                    bytes_in: match text {
+                        Some(text) => text.len() as u64,
+                        None => fs::metadata(file).map_or(0, |m| m.len()),
+                    },
+                    outcome,
+                };
+                self.log_failure(&result);
+                result
+            };
+
+        // Collect the names every file declares first, so names declared in
+        // other files can be bound; a file that fails here is not extracted
+        let start = Instant::now();
+        let summaries: Vec<Result<(FileSymbols, Option<ParsedFile>), FileError>> = files
+            .par_iter()
+            .map(|(file, text)| summarize_file(file, *text, &context))
+            .collect();
+        let mut symbols = Vec::new();
+        let mut summarized = Vec::new();
+        let mut parsed = Vec::new();
+        for ((file, text), summary) in files.iter().zip(summaries) {
+            match summary {
+                Ok((summary, parsed_file)) => {
+                    symbols.push(summary);
+                    summarized.push((file, *text));
+                    parsed.push(parsed_file);
+                }
+                // Files outside the scope of a diff-mode run are not reported
+                Err(e) if in_scope.as_ref().is_none_or(|scope| scope.contains(file)) => {
+                    results.push(file_result(file, *text, Err(e)))
+                }
+                Err(_) => {}
+            }
+        }
+        debug!(
+            "Collected the names of {} files in {:?}",
+            symbols.len(),
+            start.elapsed()
+        );
+        context.symbols = symbols.into_iter().collect();
+
+        // Files outside the scope of a diff-mode run are not extracted. Their
+        // names were collected above, so names in the files in scope can be
+        // bound to them, and their TRAP is reused from the cache when it has
+        // an entry for them and for every file they import; the others are
+        // left out of the database.
+        if let Some(in_scope) = &in_scope {
+            let mut unchanged = Vec::new();
+            let mut kept = Vec::new();
+            for (file, parsed_file) in summarized.into_iter().zip(parsed) {
+                if in_scope.contains(file.0) {
+                    kept.push((file, parsed_file));
+                } else {
+                    unchanged.push(file);
+                }
+            }
+            (summarized, parsed) = kept.into_iter().unzip();
+            let unchanged_count = unchanged.len();
+
+            // A file whose imports are left out has another cache key, so
+            // files are left out until the rest are all cached
+            let mut reusable = unchanged;
+            loop {
+                let (cached, missing): (Vec<_>, Vec<_>) = reusable
+                    .into_par_iter()
+                    .partition(|&(file, text)| is_cached(file, text, &context));
+                reusable = cached;
+                if missing.is_empty() {
+                    break;
+                }
+                for (file, _) in missing {
+                    context.symbols.exclude(&source_root.recorded_path(file));
+                }
+            }
+            let reused: Vec<(&PathBuf, Option<(FileResult, Vec<Diagnostic>)>)> = reusable
+                .par_iter()
+                .map(|&(file, text)| {
+                    let mut diagnostics = Vec::new();
+                    let outcome = reuse_file(file, text, &context, &mut diagnostics).transpose();
+                    let result = outcome.map(|outcome| file_result(file, text, outcome));
+                    (file, result.map(|result| (result, diagnostics)))
+                })
+                .collect();
+            let mut reused_count = 0;
+            for (file, reused) in reused {
+                match reused {
+                    Some((result, diagnostics)) if result.outcome.is_ok() => {
+                        for diagnostic in &diagnostics {
+                            self.diagnostics.write(diagnostic);
+                        }
+                        results.push(result);
+                        reused_count += 1;
+                    }
+                    // Removed from the cache meanwhile, or not archived
+                    reused => {
+                        context.symbols.exclude(&source_root.recorded_path(file));
+                        results.extend(reused.map(|(result, _)| result));
+                    }
+                }
+            }
+            info!(
+                "Diff mode: reused the TRAP of {} of {} unchanged files",
+                reused_count, unchanged_count
+            );
+        }
+
+        info!("Processing {} files", summarized.len());
+
+        // Process files in parallel, reusing the trees parsed for their names
+        let process = |context: &RunContext,
+                       &(file, text): &(&PathBuf, Option<&str>),
+                       parsed: Option<ParsedFile>| {
+            let mut diagnostics = Vec::new();
+            let outcome = process_file(file, text, parsed, context, &mut diagnostics);
+            (file_result(file, text, outcome), diagnostics)
+        };
+        let mut extracted: Vec<(FileResult, Vec<Diagnostic>)> = summarized
+            .par_iter()
+            .zip(parsed)
+            .map(|(file, parsed)| process(&context, file, parsed))
+            .collect();
+
+        // A file that failed after its names were collected is not in the
+        // database, so the files bound to its declarations are extracted again
+        // once, without rows referring to them. Bindings to the declarations
+        // of files failing in that round are left unresolved and reported.
+        let failed = failed_files(&extracted, &context.symbols, &source_root);
+        if !failed.is_empty() {
+            for recorded in &failed {
+                context.symbols.exclude(recorded);
+            }
+            let dependents = dependent_files(
+                &extracted,
+                &summarized,
+                &failed,
+                &context.symbols,
+                &source_root,
+            );
+            info!(
+                "Extracting {} files again without the names of {} failed files",
+                dependents.len(),
+                failed.len()
+            );
+            let again: Vec<(FileResult, Vec<Diagnostic>)> = dependents
+                .par_iter()
+                .map(|&i| process(&context, &summarized[i], None))
+                .collect();
+            for (i, result) in dependents.into_iter().zip(again) {
+                extracted[i] = result;
+            }
+
+            let failed = failed_files(&extracted, &context.symbols, &source_root);
+            for i in dependent_files(
+                &extracted,
+                &summarized,
+                &failed,
+                &context.symbols,
+                &source_root,
+            ) {
+                let recorded = source_root.recorded_path(summarized[i].0);
+                let missing: Vec<String> = context
+                    .symbols
+                    .imported_files(&recorded)
+                    .into_iter()
+                    .filter(|file| failed.contains(*file))
+                    .map(|file| source_root.relative_path(Path::new(file)))
+                    .collect();
+                let (result, diagnostics) = &mut extracted[i];
+                warn!(
+                    "{}: bindings to declarations in {} failed files are unresolved",
+                    result.relative,
+                    missing.len()
+                );
+                let missing: Vec<&str> = missing.iter().map(String::as_str).collect();
+                diagnostics.push(Diagnostic::unresolved_bindings(
+                    Path::new(&result.relative),
+                    &missing,
+                ));
+            }
+        }
+
+        if let Some(cache) = &cache {
+            info!(
+                "TRAP cache: {} hits, {} misses",
+                cache.hits(),
+                cache.misses()
+            );
+        }
+
+        for (result, diagnostics) in extracted {
+            for diagnostic in &diagnostics {
+                self.diagnostics.write(diagnostic);
+            }
+            results.push(result);
+        }
+        results.sort_by(|a, b| a.relative.cmp(&b.relative));
+        Ok(results)
+    }
+
+    /// Log a file that was not extracted and report it on the status page.
+    fn log_failure(&self, result: &FileResult) {
+        if let Err(e) = &result.outcome {
+            if e.is_skip() {
+                warn!("{}: {}", result.relative, e);
+            } else {
+                error!("{}: {}", result.relative, e);
+            }
+            self.diagnostics
+                .write(&e.to_diagnostic(Path::new(&result.relative)));
+        }
+    }
+}
+
+/// Get the database paths of the files that failed to extract although their
+/// declarations are in the database.
+fn failed_files(
+    extracted: &[(FileResult, Vec<Diagnostic>)],
+    symbols: &SymbolIndex,
+    source_root: &SourceRoot,
+) -> HashSet<String> {
+    extracted
+        .iter()
+        .filter(|(result, _)| result.outcome.is_err())
+        .map(|(result, _)| source_root.recorded_path(&result.path))
+        .filter(|recorded| symbols.includes(recorded))
+        .collect()
+}
+
+/// Get the indices of the extracted files that import one of `failed`,
+/// directly or not.
+fn dependent_files(
+    extracted: &[(FileResult, Vec<Diagnostic>)],
+    summarized: &[(&PathBuf, Option<&str>)],
+    failed: &HashSet<String>,
+    symbols: &SymbolIndex,
+    source_root: &SourceRoot,
+) -> Vec<usize> {
+    (0..extracted.len())
+        .filter(|&i| extracted[i].0.outcome.is_ok())
+        .filter(|&i| {
+            let recorded = source_root.recorded_path(summarized[i].0);
+            let imported = symbols.imported_files(&recorded);
+            imported.iter().any(|file| failed.contains(*file))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::compute_trap_path;
+    use crate::extraction::source_root::path_to_string;
+    use crate::test_support::ScratchDir;
+
+    #[test]
+    fn test_sessions_with_own_pools() {
+        let dir = ScratchDir::new("session");
+        let root = dir.join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/A.sol"), "contract A {}\n").unwrap();
+
+        // Several sessions in one process, each with its own thread count
+        for threads in [1, 2] {
+            let out = dir.join(format!("out{}", threads));
+            let results = ExtractionSession::new(out.join("trap"), out.join("src"))
+                .with_source_root(&root)
+                .with_threads(threads)
+                .with_compression(Compression::None)
+                .extract(vec![
+                    SourceInput::from(root.join("src/A.sol")),
+                    SourceInput::source("src/B.sol", "contract B {}\n"),
+                    SourceInput::from(root.join("src")),
+                    SourceInput::from(root.join("src/Missing.sol")),
+                ])
+                .unwrap();
+
+            assert_eq!(results.threads, threads);
+            let outcomes: Vec<(&str, bool, bool)> = results
+                .files
+                .iter()
+                .map(|f| (f.relative.as_str(), f.is_skipped(), f.is_failed()))
+                .collect();
+            assert_eq!(
+                outcomes,
+                vec![
+                    ("src", true, false),
+                    ("src/A.sol", false, false),
+                    ("src/B.sol", false, false),
+                    ("src/Missing.sol", false, true),
+                ]
+            );
+            assert_eq!(
+                (results.succeeded(), results.skipped(), results.failed()),
+                (2, 1, 1)
+            );
+            // The in-memory source is archived like a file on disk, at its
+            // absolute path
+            let archived = root.canonicalize().unwrap().join("src/B.sol");
+            let archived = archived.to_string_lossy();
+            assert_eq!(
+                fs::read_to_string(out.join("src").join(archived.trim_start_matches('/'))).unwrap(),
+                "contract B {}\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_diff_mode_with_deleted_file() {
+        let dir = ScratchDir::new("diff");
+        let root = dir.join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(
+            root.join("src/A.sol"),
+            "import \"./Gone.sol\";\ncontract A {}\n",
+        )
+        .unwrap();
+        fs::write(root.join("src/B.sol"), "contract B {}\n").unwrap();
+
+        // src/Gone.sol was deleted by the change, so only its importer is extracted
+        let results = ExtractionSession::new(dir.join("trap"), dir.join("src"))
+            .with_source_root(&root)
+            .with_compression(Compression::None)
+            .with_changed_files(vec![PathBuf::from("src/Gone.sol")])
+            .extract(vec![
+                SourceInput::from(root.join("src/A.sol")),
+                SourceInput::from(root.join("src/B.sol")),
+            ])
+            .unwrap();
+        let extracted: Vec<&str> = results.files.iter().map(|f| f.relative.as_str()).collect();
+        assert_eq!(extracted, vec!["src/A.sol"]);
+    }
+
+    #[test]
+    fn test_diff_mode_reuses_cached_files() {
+        let dir = ScratchDir::new("diff-cached");
+        let root = dir.join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/A.sol"), "contract A {}\n").unwrap();
+        fs::write(root.join("src/B.sol"), "contract B {}\n").unwrap();
+
+        let run = |out: &str, changed: Option<&str>, files: &[&str]| {
+            let out = dir.join(out);
+            let mut session = ExtractionSession::new(out.join("trap"), out.join("src"))
+                .with_source_root(&root)
+                .with_compression(Compression::None)
+                .with_trap_cache(dir.join("cache"));
+            if let Some(changed) = changed {
+                session = session.with_changed_files(vec![PathBuf::from(changed)]);
+            }
+            let inputs = files.iter().map(|file| SourceInput::from(root.join(file)));
+            session.extract(inputs).unwrap()
+        };
+        run("base", None, &["src/A.sol", "src/B.sol"]);
+
+        // A.sol is extracted, B.sol copied from the cache and the uncached
+        // C.sol left out
+        fs::write(root.join("src/A.sol"), "contract A { uint x; }\n").unwrap();
+        fs::write(root.join("src/C.sol"), "contract C {}\n").unwrap();
+        let results = run(
+            "diff",
+            Some("src/A.sol"),
+            &["src/A.sol", "src/B.sol", "src/C.sol"],
+        );
+        let files: Vec<(&str, bool)> = results
+            .files
+            .iter()
+            .map(|f| (f.relative.as_str(), f.outcome.as_ref().unwrap().cached))
+            .collect();
+        assert_eq!(files, vec![("src/A.sol", false), ("src/B.sol", true)]);
+
+        // The reused file is archived
+        let b = path_to_string(&root.join("src/B.sol").canonicalize().unwrap());
+        let archived = dir.join("diff/src").join(b.trim_start_matches('/'));
+        assert_eq!(fs::read_to_string(archived).unwrap(), "contract B {}\n");
+    }
+
+    #[test]
+    fn test_cached_run_reuses_names() {
+        let dir = ScratchDir::new("cached");
+        let root = dir.join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(
+            root.join("src/A.sol"),
+            "import \"./B.sol\";\ncontract A is B {}\n",
+        )
+        .unwrap();
+        fs::write(root.join("src/B.sol"), "contract B {}\n").unwrap();
+
+        let run = |out: &str, compression: Compression| {
+            ExtractionSession::new(dir.join(out).join("trap"), dir.join(out).join("src"))
+                .with_source_root(&root)
+                .with_compression(compression)
+                .with_trap_cache(dir.join("cache"))
+                .extract(vec![
+                    SourceInput::from(root.join("src/A.sol")),
+                    SourceInput::from(root.join("src/B.sol")),
+                ])
+                .unwrap()
+        };
+        let cached = |results: &ExtractionResults| -> Vec<bool> {
+            let stats = results.files.iter().map(|f| f.outcome.as_ref().unwrap());
+            stats.map(|stats| stats.cached).collect()
+        };
+        assert_eq!(cached(&run("first", Compression::None)), vec![false, false]);
+        // The names of both files are cached next to their TRAP files
+        let names = fs::read_dir(dir.join("cache"))
+            .unwrap()
+            .flat_map(|shard| fs::read_dir(shard.unwrap().path()).unwrap())
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().ends_with(".symbols.json")
+            })
+            .count();
+        assert_eq!(names, 2);
+        assert_eq!(cached(&run("second", Compression::None)), vec![true, true]);
+        // Entries are kept apart by compression level, not just by mode
+        assert_eq!(
+            cached(&run("gzip1", Compression::Gzip(1))),
+            vec![false, false]
+        );
+        assert_eq!(
+            cached(&run("gzip9", Compression::Gzip(9))),
+            vec![false, false]
+        );
+        assert_eq!(
+            cached(&run("again", Compression::Gzip(9))),
+            vec![true, true]
+        );
+    }
+
+    #[test]
+    fn test_failed_file_is_not_bound_to() {
+        let dir = ScratchDir::new("failed");
+        let root = dir.join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(
+            root.join("src/A.sol"),
+            "import \"./B.sol\";\ncontract A is B { function g() public { f(); } }\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src/B.sol"),
+            "contract B { function f() public {} }\n",
+        )
+        .unwrap();
+
+        // B.sol cannot be archived, so it fails after its names were collected
+        let out = dir.join("out");
+        let b = path_to_string(&root.join("src/B.sol").canonicalize().unwrap());
+        fs::create_dir_all(out.join("src").join(b.trim_start_matches('/'))).unwrap();
+        let results = ExtractionSession::new(out.join("trap"), out.join("src"))
+            .with_source_root(&root)
+            .with_compression(Compression::None)
+            .extract(vec![
+                SourceInput::from(root.join("src/A.sol")),
+                SourceInput::from(root.join("src/B.sol")),
+            ])
+            .unwrap();
+        let failed: Vec<bool> = results.files.iter().map(|f| f.is_failed()).collect();
+        assert_eq!(failed, vec![false, true]);
+
+        // A.sol was extracted again, without references into B.sol
+        let a = path_to_string(&root.join("src/A.sol").canonicalize().unwrap());
+        let trap = compute_trap_path(&out.join("trap"), Path::new(&a), Compression::None);
+        let trap = fs::read_to_string(trap).unwrap();
+        assert!(trap.contains(&format!("declaration;{};", a)));
+        assert!(!trap.contains(&format!("declaration;{};", b)));
+    }
+}
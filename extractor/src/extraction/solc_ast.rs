@@ -0,0 +1,499 @@
+//! Semantic information from the compiler's AST.
+//!
+//! The syntax tree knows nothing about what names refer to or what types
+//! expressions have. When a project has been compiled, the compiler's compact
+//! AST is already on disk, in the build-info files Hardhat
+//! (`artifacts/build-info/*.json`) and Foundry (`out/build-info/*.json`)
+//! write. Each of its nodes has a `src` byte range (`start:length:source`);
+//! nodes are matched to the syntax tree nodes with the same range (a trailing
+//! `;` aside), and their `referencedDeclaration`, `overloadedDeclarations`,
+//! `typeDescriptions` and `linearizedBaseContracts` are recorded in the
+//! `solidity_solc_*` tables.
+//!
+//! Compiler nodes are labelled by build-info file and node id. References to
+//! other nodes (declarations, base contracts) are recorded as node ids, which
+//! resolve to the node of the same build once its file is extracted too, so
+//! they never point to a node missing from the database.
+//! A file is only matched against a build whose copy of its source is
+//! identical, so stale artifacts are ignored; when several builds compiled the
+//! file, the newest is used.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+
+use crate::trap::{Label, TrapValue, TrapWriter};
+
+/// Directory of build-info files within a build output directory.
+pub const BUILD_INFO_DIR: &str = "build-info";
+
+/// UTF-8 byte order mark, which the compiler keeps in source offsets.
+const BOM: &str = "\u{feff}";
+
+/// A build-info file.
+#[derive(Debug)]
+pub struct BuildInfo {
+    /// Canonical path of the file
+    pub path: PathBuf,
+    /// Version of the compiler that produced it
+    pub solc_version: String,
+    /// SHA-256 of the file
+    pub hash: [u8; 32],
+}
+
+/// A source file as compiled in a build.
+#[derive(Debug)]
+pub struct CompiledSource {
+    /// The build
+    pub build: Arc<BuildInfo>,
+    /// The compiler's nodes for the file, in pre-order
+    pub nodes: Vec<SolcNode>,
+    /// SHA-256 of the compiled content, without a byte order mark
+    /// (None = the build does not include the content)
+    content_hash: Option<[u8; 32]>,
+    /// Length of the byte order mark the compiled content starts with
+    bom_len: usize,
+}
+
+/// A node of the compiler's AST, with what the syntax tree lacks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SolcNode {
+    /// Node id, unique within the build
+    pub id: i64,
+    /// Node type (`Identifier`, `FunctionDefinition`, ...)
+    pub node_type: String,
+    /// Byte offset of the node in the compiled content
+    pub start: usize,
+    /// Length of the node in bytes
+    pub length: usize,
+    /// Declaration a name refers to (`referencedDeclaration`)
+    pub referenced_declaration: Option<i64>,
+    /// Candidate declarations of an overloaded name (`overloadedDeclarations`)
+    pub overloaded_declarations: Vec<i64>,
+    /// Type string and identifier (`typeDescriptions`)
+    pub type_descriptions: Option<(String, String)>,
+    /// Linearized base contracts, most derived first (`linearizedBaseContracts`)
+    pub linearized_base_contracts: Vec<i64>,
+}
+
+/// The build-info files of a project, indexed by source file.
+#[derive(Debug, Default)]
+pub struct BuildInfoIndex {
+    /// Builds of each source file (a canonical path), newest first
+    sources: HashMap<PathBuf, Vec<Arc<CompiledSource>>>,
+}
+
+/// Build-info file, as written by Hardhat and Foundry.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawBuildInfo {
+    solc_long_version: Option<String>,
+    solc_version: Option<String>,
+    #[serde(default)]
+    input: RawInput,
+    output: RawOutput,
+}
+
+#[derive(Default, Deserialize)]
+struct RawInput {
+    #[serde(default)]
+    sources: HashMap<String, RawInputSource>,
+}
+
+#[derive(Deserialize)]
+struct RawInputSource {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawOutput {
+    #[serde(default)]
+    sources: HashMap<String, RawOutputSource>,
+}
+
+#[derive(Deserialize)]
+struct RawOutputSource {
+    id: i64,
+    ast: Option<Value>,
+}
+
+impl BuildInfoIndex {
+    /// Load the build-info files in the given directories.
+    ///
+    /// Source unit names are resolved relative to `root` (the project
+    /// directory), then to its `node_modules`. Files that cannot be read are
+    /// skipped with a warning.
+    pub fn load(root: &Path, dirs: &[PathBuf]) -> Self {
+        // Newest first, so the newest build of a file is preferred
+        let mut files: Vec<(SystemTime, PathBuf)> = dirs
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified());
+                (modified.unwrap_or(SystemTime::UNIX_EPOCH), path)
+            })
+            .collect();
+        files.sort_by(|a, b| b.cmp(a));
+
+        let mut index = BuildInfoIndex::default();
+        for (_, path) in &files {
+            match load_build_info(path, root) {
+                Ok(sources) => {
+                    debug!("Build info {}: {} sources", path.display(), sources.len());
+                    for (file, source) in sources {
+                        index
+                            .sources
+                            .entry(file)
+                            .or_default()
+                            .push(Arc::new(source));
+                    }
+                }
+                Err(e) => warn!("Ignoring build info {}: {:#}", path.display(), e),
+            }
+        }
+        if !files.is_empty() {
+            info!(
+                "Loaded {} build info files covering {} sources in {}",
+                files.len(),
+                index.sources.len(),
+                root.display()
+            );
+        }
+        index
+    }
+
+    /// Find the newest build of a file (a canonical path) that compiled the
+    /// given text.
+    pub fn find(&self, file: &Path, text: &str) -> Option<&Arc<CompiledSource>> {
+        let hash: [u8; 32] = Sha256::digest(text.as_bytes()).into();
+        self.sources
+            .get(file)?
+            .iter()
+            .find(|source| source.content_hash.is_none_or(|h| h == hash))
+    }
+}
+
+/// Read a build-info file into the sources it compiled, keyed by canonical path.
+fn load_build_info(path: &Path, root: &Path) -> Result<Vec<(PathBuf, CompiledSource)>> {
+    let bytes = fs::read(path).context("Failed to read")?;
+    let raw: RawBuildInfo = serde_json::from_slice(&bytes).context("Invalid build info")?;
+    let build = Arc::new(BuildInfo {
+        path: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+        solc_version: raw
+            .solc_long_version
+            .or(raw.solc_version)
+            .unwrap_or_default(),
+        hash: Sha256::digest(&bytes).into(),
+    });
+
+    let mut sources = Vec::new();
+    for (unit, output) in raw.output.sources {
+        let (Some(ast), Some(file)) = (output.ast, unit_file(root, &unit)) else {
+            continue;
+        };
+        let content = raw
+            .input
+            .sources
+            .get(&unit)
+            .and_then(|source| source.content.as_deref());
+        let bom_len = content
+            .filter(|c| c.starts_with(BOM))
+            .map_or(0, |_| BOM.len());
+        sources.push((
+            file,
+            CompiledSource {
+                build: build.clone(),
+                nodes: collect_nodes(&ast, output.id),
+                content_hash: content.map(|c| Sha256::digest(&c.as_bytes()[bom_len..]).into()),
+                bom_len,
+            },
+        ));
+    }
+    Ok(sources)
+}
+
+/// Find the file a source unit name refers to.
+fn unit_file(root: &Path, unit: &str) -> Option<PathBuf> {
+    let unit = Path::new(unit);
+    let candidates = if unit.is_absolute() {
+        vec![unit.to_path_buf()]
+    } else {
+        vec![root.join(unit), root.join("node_modules").join(unit)]
+    };
+    candidates.into_iter().find_map(|c| c.canonicalize().ok())
+}
+
+/// Collect the nodes of a source unit's AST in pre-order.
+///
+/// Nodes located in other sources (or nowhere, for generated code) are left
+/// out. The tree is walked with an explicit stack, as ASTs can be deep.
+fn collect_nodes(ast: &Value, source_id: i64) -> Vec<SolcNode> {
+    let mut nodes = Vec::new();
+    let mut stack = vec![ast];
+    while let Some(value) = stack.pop() {
+        match value {
+            Value::Object(object) => {
+                if let Some(node) = solc_node(object, source_id) {
+                    nodes.push(node);
+                }
+                stack.extend(object.values().rev());
+            }
+            Value::Array(values) => stack.extend(values.iter().rev()),
+            _ => {}
+        }
+    }
+    nodes
+}
+
+/// Read a compiler node, if the object is a node in the given source.
+fn solc_node(object: &serde_json::Map<String, Value>, source_id: i64) -> Option<SolcNode> {
+    let id = object.get("id")?.as_i64()?;
+    let node_type = object.get("nodeType")?.as_str()?;
+    let mut src = object.get("src")?.as_str()?.split(':');
+    let start = src.next()?.parse().ok()?;
+    let length = src.next()?.parse().ok()?;
+    if src.next()?.parse::<i64>().ok()? != source_id {
+        return None;
+    }
+    let ids = |key: &str| -> Vec<i64> {
+        object
+            .get(key)
+            .and_then(Value::as_array)
+            .map(|ids| ids.iter().filter_map(Value::as_i64).collect())
+            .unwrap_or_default()
+    };
+    let types = object.get("typeDescriptions");
+    let type_field = |key: &str| types.and_then(|t| t.get(key)).and_then(Value::as_str);
+    Some(SolcNode {
+        id,
+        node_type: node_type.to_string(),
+        start,
+        length,
+        // Negative ids refer to built-ins (`msg`, `require`, ...)
+        referenced_declaration: object
+            .get("referencedDeclaration")
+            .and_then(Value::as_i64)
+            .filter(|&id| id >= 0),
+        overloaded_declarations: ids("overloadedDeclarations"),
+        type_descriptions: type_field("typeString").map(|s| {
+            (
+                s.to_string(),
+                type_field("typeIdentifier").unwrap_or("").to_string(),
+            )
+        }),
+        linearized_base_contracts: ids("linearizedBaseContracts"),
+    })
+}
+
+/// Record the compiler's nodes for a file.
+///
+/// `build` is the database path of the build-info file; `node_at` finds the
+/// syntax tree node with the given byte range. Nodes that match no syntax tree
+/// node are left out. Returns the number of nodes recorded.
+pub fn write_compiler_nodes(
+    trap: &mut TrapWriter,
+    build: &str,
+    source: &CompiledSource,
+    node_at: impl Fn(usize, usize) -> Option<Label>,
+) -> usize {
+    let build_label = trap.string_label(&format!("build_info;{}", build));
+    trap.emit(
+        "solidity_build_info",
+        vec![
+            TrapValue::Label(build_label.clone()),
+            TrapValue::String(build.to_string()),
+            TrapValue::String(source.build.solc_version.clone()),
+        ],
+    );
+    let mut matched = 0;
+    for node in &source.nodes {
+        let Some(start) = node.start.checked_sub(source.bom_len) else {
+            continue;
+        };
+        let Some(ast_node) = node_at(start, start + node.length) else {
+            continue;
+        };
+        matched += 1;
+        let label = trap.string_label(&format!("solc_node;{};{}", build, node.id));
+        trap.emit(
+            "solidity_solc_node",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::Label(build_label.clone()),
+                TrapValue::Int(node.id),
+                TrapValue::String(node.node_type.clone()),
+                TrapValue::Label(ast_node),
+            ],
+        );
+        if let Some(declaration) = node.referenced_declaration {
+            trap.emit(
+                "solidity_solc_referenced_declaration",
+                vec![TrapValue::Label(label.clone()), TrapValue::Int(declaration)],
+            );
+        }
+        for (index, &declaration) in node.overloaded_declarations.iter().enumerate() {
+            trap.emit(
+                "solidity_solc_overloaded_declaration",
+                vec![
+                    TrapValue::Label(label.clone()),
+                    TrapValue::UInt(index as u64),
+                    TrapValue::Int(declaration),
+                ],
+            );
+        }
+        if let Some((type_string, type_identifier)) = &node.type_descriptions {
+            trap.emit(
+                "solidity_solc_type",
+                vec![
+                    TrapValue::Label(label.clone()),
+                    TrapValue::String(type_string.clone()),
+                    TrapValue::String(type_identifier.clone()),
+                ],
+            );
+        }
+        for (index, &base) in node.linearized_base_contracts.iter().enumerate() {
+            trap.emit(
+                "solidity_solc_linearized_base",
+                vec![
+                    TrapValue::Label(label.clone()),
+                    TrapValue::UInt(index as u64),
+                    TrapValue::Int(base),
+                ],
+            );
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchDir;
+    use serde_json::json;
+
+    const SOURCE: &str = "contract A { function f() public { g(); } function g() public {} }";
+
+    /// Compact AST of `SOURCE` (abridged).
+    fn ast() -> Value {
+        json!({
+            "id": 10, "nodeType": "SourceUnit", "src": "0:66:0",
+            "nodes": [{
+                "id": 9, "nodeType": "ContractDefinition", "src": "0:66:0",
+                "linearizedBaseContracts": [9],
+                "nodes": [{
+                    "id": 5, "nodeType": "FunctionDefinition", "src": "13:28:0",
+                    "body": { "id": 4, "nodeType": "Block", "src": "33:8:0", "statements": [{
+                        "id": 3, "nodeType": "ExpressionStatement", "src": "35:4:0",
+                        "expression": {
+                            "id": 2, "nodeType": "FunctionCall", "src": "35:3:0",
+                            "typeDescriptions": { "typeString": "tuple()", "typeIdentifier": "t_tuple$__$" },
+                            "expression": {
+                                "id": 1, "nodeType": "Identifier", "src": "35:1:0",
+                                "referencedDeclaration": 8,
+                                "overloadedDeclarations": [],
+                                "typeDescriptions": {
+                                    "typeString": "function ()",
+                                    "typeIdentifier": "t_function_internal_nonpayable$__$returns$__$"
+                                }
+                            }
+                        }
+                    }]}
+                }, {
+                    "id": 8, "nodeType": "FunctionDefinition", "src": "42:22:0"
+                }, {
+                    "id": 7, "nodeType": "Identifier", "src": "0:1:1"
+                }]
+            }]
+        })
+    }
+
+    #[test]
+    fn test_load_build_info() {
+        let dir = ScratchDir::new("solc");
+        let build_dir = dir.join("out").join(BUILD_INFO_DIR);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(&build_dir).unwrap();
+        fs::write(dir.join("src/A.sol"), SOURCE).unwrap();
+        let build_info = json!({
+            "solcVersion": "0.8.19",
+            "solcLongVersion": "0.8.19+commit.7dd6d404",
+            "input": { "language": "Solidity", "sources": { "src/A.sol": { "content": SOURCE } } },
+            "output": {
+                "sources": {
+                    "src/A.sol": { "id": 0, "ast": ast() },
+                    "src/Missing.sol": { "id": 1, "ast": { "id": 11, "nodeType": "SourceUnit", "src": "0:0:1" } }
+                },
+                "contracts": { "src/A.sol": { "A": { "abi": [] } } }
+            }
+        });
+        fs::write(build_dir.join("abc.json"), build_info.to_string()).unwrap();
+        fs::write(build_dir.join("broken.json"), "{").unwrap();
+
+        let index = BuildInfoIndex::load(&dir, std::slice::from_ref(&build_dir));
+        let file = dir.join("src/A.sol").canonicalize().unwrap();
+        let source = index.find(&file, SOURCE).unwrap();
+        assert_eq!(source.build.solc_version, "0.8.19+commit.7dd6d404");
+        // Pre-order, without the node located in another source
+        let ids: Vec<i64> = source.nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![10, 9, 5, 4, 3, 2, 1, 8]);
+        let identifier = &source.nodes[6];
+        assert_eq!((identifier.start, identifier.length), (35, 1));
+        assert_eq!(identifier.referenced_declaration, Some(8));
+        assert_eq!(source.nodes[1].linearized_base_contracts, vec![9]);
+
+        // Builds of other versions of the file are not used
+        assert!(index.find(&file, "contract B {}").is_none());
+    }
+
+    #[test]
+    fn test_write_compiler_nodes() {
+        let source = CompiledSource {
+            build: Arc::new(BuildInfo {
+                path: PathBuf::from("out/build-info/abc.json"),
+                solc_version: "0.8.19".to_string(),
+                hash: [0; 32],
+            }),
+            nodes: collect_nodes(&ast(), 0),
+            content_hash: None,
+            bom_len: 0,
+        };
+        // Syntax tree nodes of the identifier `g` and both functions
+        let mut trap = TrapWriter::new("A.sol");
+        let spans: HashMap<(usize, usize), Label> = [(35, 36), (13, 41), (42, 64)]
+            .into_iter()
+            .map(|span| (span, trap.fresh_label()))
+            .collect();
+        let matched =
+            write_compiler_nodes(&mut trap, "out/build-info/abc.json", &source, |s, e| {
+                spans.get(&(s, e)).cloned()
+            });
+        assert_eq!(matched, 3);
+
+        let output = trap.format();
+        assert!(output.contains("solidity_build_info(#"));
+        assert_eq!(output.matches("solidity_solc_node(").count(), 3);
+        assert!(output.contains(", 1, \"Identifier\", #"));
+        assert!(output.contains("=@\"solc_node;out/build-info/abc.json;8\""));
+        // `g` refers to its declaration by compiler id
+        assert!(output.lines().any(|line| {
+            line.starts_with("solidity_solc_referenced_declaration(#") && line.ends_with(", 8)")
+        }));
+        assert!(
+            output.contains("\"function ()\", \"t_function_internal_nonpayable$__$returns$__$\")")
+        );
+        // The call and the contract are not in the syntax tree given
+        assert!(!output.contains("tuple()"));
+        assert!(!output.contains("solidity_solc_linearized_base"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Source root handling.
+//!
+//! Paths recorded in the database (the `files` and `folders` tables, the source
+//! archive and TRAP file names) are canonical absolute paths, as the CodeQL CLI
+//! expects when it renders locations and reads the source archive. The root is
+//! recorded once per database in the `sourceLocationPrefix` table, which the
+//! CLI strips from result locations, so results do not depend on the machine
+//! the database was built on.
+//!
+//! Paths relative to the root are what users see: in logs, status-page
+//! diagnostics and the extraction report, and when matching path filters.
+
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// The directory that source files are extracted from.
+#[derive(Debug, Clone)]
+pub struct SourceRoot {
+    /// Canonical path of the root directory
+    root: PathBuf,
+}
+
+impl SourceRoot {
+    /// Create a source root for the given directory.
+    pub fn new(root: &Path) -> Result<Self> {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve source root: {}", root.display()))?;
+        Ok(SourceRoot { root })
+    }
+
+    /// Get the canonical root directory.
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the value recorded in `sourceLocationPrefix`.
+    pub fn prefix(&self) -> String {
+        path_to_string(&self.root)
+    }
+
+    /// Resolve a file to its canonical location on disk.
+    ///
+    /// Symlinks and `..` components are resolved so that the same file reached
+    /// through different paths is only extracted once. Files that do not exist
+    /// are returned as given.
+    pub fn canonicalize(&self, file: &Path) -> PathBuf {
+        file.canonicalize().unwrap_or_else(|_| file.to_path_buf())
+    }
+
+    /// Get the path recorded in the database for a canonical file path.
+    pub fn recorded_path(&self, canonical: &Path) -> String {
+        path_to_string(canonical)
+    }
+
+    /// Get the path of a canonical file path relative to the source root, as
+    /// shown to users.
+    ///
+    /// Files outside the root keep their absolute path.
+    pub fn relative_path(&self, canonical: &Path) -> String {
+        match canonical.strip_prefix(&self.root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => path_to_string(relative),
+            _ => path_to_string(canonical),
+        }
+    }
+}
+
+/// Render a path with `/` separators, as recorded in the database.
+pub fn path_to_string(path: &Path) -> String {
+    let mut result = String::new();
+    for component in path.components() {
+        match component {
+            Component::RootDir => result.push('/'),
+            Component::Prefix(prefix) => result.push_str(&prefix.as_os_str().to_string_lossy()),
+            other => {
+                if !result.is_empty() && !result.ends_with('/') {
+                    result.push('/');
+                }
+                result.push_str(&other.as_os_str().to_string_lossy());
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(path: &str) -> SourceRoot {
+        SourceRoot {
+            root: PathBuf::from(path),
+        }
+    }
+
+    #[test]
+    fn test_relative_path_inside_root() {
+        let root = root("/home/user/project");
+        assert_eq!(
+            root.relative_path(Path::new("/home/user/project/contracts/Token.sol")),
+            "contracts/Token.sol"
+        );
+    }
+
+    #[test]
+    fn test_relative_path_outside_root() {
+        let root = root("/home/user/project");
+        assert_eq!(
+            root.relative_path(Path::new("/opt/lib/Token.sol")),
+            "/opt/lib/Token.sol"
+        );
+    }
+
+    #[test]
+    fn test_recorded_path_is_absolute() {
+        let root = root("/home/user/project");
+        assert_eq!(
+            root.recorded_path(Path::new("/home/user/project/contracts/Token.sol")),
+            "/home/user/project/contracts/Token.sol"
+        );
+    }
+
+    #[test]
+    fn test_path_to_string() {
+        assert_eq!(path_to_string(Path::new("/a/b/c.sol")), "/a/b/c.sol");
+        assert_eq!(path_to_string(Path::new("a/./b.sol")), "a/b.sol");
+    }
+}
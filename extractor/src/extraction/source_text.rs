@@ -0,0 +1,158 @@
+//! Source text decoding and line numbering.
+//!
+//! Solidity source is UTF-8, but files in the wild contain stray Latin-1
+//! comments, byte order marks and Windows or classic Mac line endings. Such
+//! files are extracted rather than dropped: invalid bytes are replaced with
+//! U+FFFD, a leading BOM is removed (so columns on the first line start at 1),
+//! and `\r\n`, `\r` and `\n` all end a line.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// UTF-8 byte order mark.
+const BOM: &str = "\u{feff}";
+
+/// Text decoded from a source file.
+#[derive(Debug, Clone)]
+pub struct SourceText {
+    /// Decoded text, without a byte order mark
+    pub text: String,
+    /// Whether a byte order mark was removed
+    pub had_bom: bool,
+    /// Number of invalid UTF-8 sequences replaced with U+FFFD
+    pub invalid_sequences: usize,
+}
+
+impl SourceText {
+    /// Read and decode a source file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        fs::read(path).map(Self::decode)
+    }
+
+    /// Decode the contents of a source file.
+    pub fn decode(bytes: Vec<u8>) -> Self {
+        let (mut text, invalid_sequences) = match String::from_utf8(bytes) {
+            Ok(text) => (text, 0),
+            Err(e) => {
+                let bytes = e.into_bytes();
+                let invalid_sequences = count_invalid_sequences(&bytes);
+                (
+                    String::from_utf8_lossy(&bytes).into_owned(),
+                    invalid_sequences,
+                )
+            }
+        };
+        let had_bom = text.starts_with(BOM);
+        if had_bom {
+            text.drain(..BOM.len());
+        }
+        SourceText {
+            text,
+            had_bom,
+            invalid_sequences,
+        }
+    }
+}
+
+/// Count the invalid sequences that lossy decoding replaces.
+fn count_invalid_sequences(mut bytes: &[u8]) -> usize {
+    let mut count = 0;
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(_) => return count,
+            Err(e) => {
+                count += 1;
+                let skip = e.valid_up_to() + e.error_len().unwrap_or(bytes.len() - e.valid_up_to());
+                bytes = &bytes[skip..];
+            }
+        }
+    }
+}
+
+/// Maps byte offsets in a text to lines and columns.
+///
+/// Lines end at `\r\n`, `\r` or `\n`; columns count bytes, as the database's
+/// `utf8` column kind requires.
+#[derive(Debug, Clone, Default)]
+pub struct LineIndex {
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    /// Index the lines of a text.
+    pub fn new(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let mut line_starts = vec![0];
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\r' if bytes.get(i + 1) == Some(&b'\n') => {
+                    line_starts.push(i + 2);
+                    i += 2;
+                    continue;
+                }
+                b'\r' | b'\n' => line_starts.push(i + 1),
+                _ => {}
+            }
+            i += 1;
+        }
+        LineIndex { line_starts }
+    }
+
+    /// Get the 0-based line and column of a byte offset.
+    ///
+    /// The `\n` of a `\r\n` pair belongs to the line it ends.
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        (line, offset - self.line_starts[line])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let clean = SourceText::decode(b"contract A {}".to_vec());
+        assert_eq!(clean.text, "contract A {}");
+        assert!(!clean.had_bom);
+        assert_eq!(clean.invalid_sequences, 0);
+
+        let bom = SourceText::decode(b"\xef\xbb\xbfcontract A {}".to_vec());
+        assert_eq!(bom.text, "contract A {}");
+        assert!(bom.had_bom);
+
+        // A Latin-1 comment: "// café" and "// naïve"
+        let latin1 = SourceText::decode(b"// caf\xe9\n// na\xefve\ncontract A {}".to_vec());
+        assert_eq!(
+            latin1.text,
+            "// caf\u{fffd}\n// na\u{fffd}ve\ncontract A {}"
+        );
+        assert_eq!(latin1.invalid_sequences, 2);
+
+        let truncated = SourceText::decode(b"// \xe2\x82".to_vec());
+        assert_eq!(truncated.invalid_sequences, 1);
+    }
+
+    #[test]
+    fn test_line_index() {
+        for text in ["ab\ncd\n\nef", "ab\r\ncd\r\n\r\nef", "ab\rcd\r\ref"] {
+            let index = LineIndex::new(text);
+            let offset = |s: &str| text.find(s).unwrap();
+            assert_eq!(index.position(0), (0, 0), "{:?}", text);
+            assert_eq!(index.position(offset("b")), (0, 1), "{:?}", text);
+            assert_eq!(index.position(offset("d")), (1, 1), "{:?}", text);
+            assert_eq!(index.position(offset("f")), (3, 1), "{:?}", text);
+            assert_eq!(index.position(text.len()), (3, 2), "{:?}", text);
+        }
+
+        // The line terminator belongs to the line it ends
+        let index = LineIndex::new("a\r\nb");
+        assert_eq!(index.position(1), (0, 1));
+        assert_eq!(index.position(2), (0, 2));
+        assert_eq!(index.position(3), (1, 0));
+    }
+}
@@ -0,0 +1,349 @@
+//! solc standard-JSON input files.
+//!
+//! Audits often come as the `--standard-json` input given to solc rather than
+//! as a checkout of the project:
+//!
+//! ```json
+//! {
+//!   "language": "Solidity",
+//!   "sources": { "contracts/Token.sol": { "content": "pragma solidity ^0.8.0; ..." } },
+//!   "settings": {
+//!     "remappings": ["@oz/=lib/openzeppelin-contracts/"],
+//!     "optimizer": { "enabled": true, "runs": 200 },
+//!     "evmVersion": "paris",
+//!     "viaIR": true
+//!   }
+//! }
+//! ```
+//!
+//! Each source with a `content` is extracted as a file at its source unit name
+//! below the input file (`input.json/contracts/Token.sol`): no file on disk can
+//! have that path, so the sources never collide with a checkout of the project.
+//! Imports are resolved among the input's sources with the input's remappings,
+//! as solc would. The settings
+//! are recorded in the `solidity_compiler_*` tables. Sources given only by
+//! `urls` are skipped.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+use crate::trap::{Label, TrapValue, TrapWriter};
+
+/// Default optimizer runs of solc.
+const DEFAULT_OPTIMIZER_RUNS: u64 = 200;
+
+/// A solc standard-JSON input file.
+#[derive(Debug, Clone)]
+pub struct StandardJsonInput {
+    /// Sources: normalized source unit name and content
+    pub sources: Vec<(String, String)>,
+    /// Compiler settings
+    pub settings: CompilerSettings,
+}
+
+/// Compiler settings of a standard-JSON input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompilerSettings {
+    /// Import remappings, as written
+    pub remappings: Vec<String>,
+    /// Whether the optimizer is enabled and its runs (None = not configured)
+    pub optimizer: Option<(bool, u64)>,
+    /// Target EVM version (None = the compiler's default)
+    pub evm_version: Option<String>,
+    /// Whether code is generated through the IR (None = not configured)
+    pub via_ir: Option<bool>,
+    /// Compiler version (None = unknown)
+    pub compiler_version: Option<String>,
+}
+
+/// Standard-JSON input, as given to solc.
+#[derive(Deserialize)]
+struct RawInput {
+    language: Option<String>,
+    sources: BTreeMap<String, RawSource>,
+    #[serde(default)]
+    settings: RawSettings,
+}
+
+#[derive(Deserialize)]
+struct RawSource {
+    content: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSettings {
+    #[serde(default)]
+    remappings: Vec<String>,
+    optimizer: Option<RawOptimizer>,
+    evm_version: Option<String>,
+    #[serde(rename = "viaIR")]
+    via_ir: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct RawOptimizer {
+    enabled: Option<bool>,
+    runs: Option<u64>,
+}
+
+impl StandardJsonInput {
+    /// Read a standard-JSON input file.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text)
+            .with_context(|| format!("Invalid standard JSON input {}", path.display()))
+    }
+
+    /// Check whether a file is a standard-JSON input for Solidity: a JSON
+    /// object whose `language` is `Solidity` and that has `sources`.
+    ///
+    /// Other JSON files are not standard-JSON inputs, whatever their name.
+    pub fn is_standard_json(path: &Path) -> bool {
+        let Ok(text) = fs::read_to_string(path) else {
+            return false;
+        };
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(&text) else {
+            return false;
+        };
+        value.get("language").and_then(|l| l.as_str()) == Some("Solidity")
+            && value.get("sources").is_some_and(|s| s.is_object())
+    }
+
+    /// Parse the contents of a standard-JSON input file.
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::from_value(serde_json::from_str(text)?)
+    }
+
+    /// Read standard-JSON input from parsed JSON.
+    pub fn from_value(value: serde_json::Value) -> Result<Self> {
+        let raw: RawInput = serde_json::from_value(value)?;
+        if let Some(language) = raw.language.filter(|l| l != "Solidity") {
+            bail!("unsupported language {}", language);
+        }
+
+        let mut sources = Vec::new();
+        for (name, source) in raw.sources {
+            let Some(content) = source.content else {
+                warn!("Skipping source given only by URL: {}", name);
+                continue;
+            };
+            match unit_path(&name) {
+                Some(path) => sources.push((path, content)),
+                None => warn!("Skipping source outside the input's directory: {}", name),
+            }
+        }
+
+        let settings = raw.settings;
+        Ok(StandardJsonInput {
+            sources,
+            settings: CompilerSettings {
+                remappings: settings.remappings,
+                optimizer: settings.optimizer.map(|o| {
+                    (
+                        o.enabled.unwrap_or(false),
+                        o.runs.unwrap_or(DEFAULT_OPTIMIZER_RUNS),
+                    )
+                }),
+                evm_version: settings.evm_version,
+                via_ir: settings.via_ir,
+                compiler_version: None,
+            },
+        })
+    }
+}
+
+/// Get the path, relative to the input file, a source unit is extracted at.
+///
+/// `./` and `../` segments are resolved and absolute names made relative;
+/// names that would leave the input's directory are rejected.
+pub(super) fn unit_path(name: &str) -> Option<String> {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in name.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            other => segments.push(other),
+        }
+    }
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+/// Record the settings of a standard-JSON input and the files extracted from
+/// it, and return the label of the settings.
+///
+/// `input` and `files` are database paths.
+pub fn write_compiler_settings(
+    trap: &mut TrapWriter,
+    input: &str,
+    settings: &CompilerSettings,
+    files: &[String],
+) -> Label {
+    let label = trap.string_label(&format!("compiler_settings;{}", input));
+    trap.emit(
+        "solidity_compiler_settings",
+        vec![
+            TrapValue::Label(label.clone()),
+            TrapValue::String(input.to_string()),
+        ],
+    );
+    for (index, remapping) in settings.remappings.iter().enumerate() {
+        trap.emit(
+            "solidity_compiler_remapping",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::UInt(index as u64),
+                TrapValue::String(remapping.clone()),
+            ],
+        );
+    }
+    if let Some((enabled, runs)) = settings.optimizer {
+        trap.emit(
+            "solidity_compiler_optimizer",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::UInt(enabled as u64),
+                TrapValue::UInt(runs),
+            ],
+        );
+    }
+    if let Some(evm_version) = &settings.evm_version {
+        trap.emit(
+            "solidity_compiler_evm_version",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::String(evm_version.clone()),
+            ],
+        );
+    }
+    if let Some(via_ir) = settings.via_ir {
+        trap.emit(
+            "solidity_compiler_via_ir",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::UInt(via_ir as u64),
+            ],
+        );
+    }
+    if let Some(version) = &settings.compiler_version {
+        trap.emit(
+            "solidity_compiler_version",
+            vec![
+                TrapValue::Label(label.clone()),
+                TrapValue::String(version.clone()),
+            ],
+        );
+    }
+    for file in files {
+        let file_label = trap.emit_file(file);
+        trap.emit(
+            "solidity_compiler_settings_file",
+            vec![
+                TrapValue::Label(file_label),
+                TrapValue::Label(label.clone()),
+            ],
+        );
+    }
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchDir;
+
+    #[test]
+    fn test_parse() {
+        let input = StandardJsonInput::parse(
+            r#"{
+                "language": "Solidity",
+                "sources": {
+                    "contracts/Token.sol": { "content": "contract T {}" },
+                    "/abs/./Lib.sol": { "content": "library L {}" },
+                    "../outside.sol": { "content": "" },
+                    "remote.sol": { "urls": ["bzz-raw://..."] }
+                },
+                "settings": {
+                    "remappings": ["@oz/=lib/oz/"],
+                    "optimizer": { "enabled": true },
+                    "evmVersion": "paris",
+                    "outputSelection": { "*": { "*": ["abi"] } }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            input.sources,
+            vec![
+                ("abs/Lib.sol".to_string(), "library L {}".to_string()),
+                (
+                    "contracts/Token.sol".to_string(),
+                    "contract T {}".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            input.settings,
+            CompilerSettings {
+                remappings: vec!["@oz/=lib/oz/".to_string()],
+                optimizer: Some((true, 200)),
+                evm_version: Some("paris".to_string()),
+                via_ir: None,
+                compiler_version: None,
+            }
+        );
+
+        assert!(StandardJsonInput::parse(r#"{"language": "Yul", "sources": {}}"#).is_err());
+        assert!(StandardJsonInput::parse("{}").is_err());
+    }
+
+    #[test]
+    fn test_is_standard_json() {
+        let dir = ScratchDir::new("json");
+        let cases = [
+            (r#"{"language": "Solidity", "sources": {}}"#, true),
+            (r#"{"language": "Yul", "sources": {}}"#, false),
+            (r#"{"name": "token", "version": "1.0.0"}"#, false),
+            ("[1, 2]", false),
+        ];
+        for (index, (text, expected)) in cases.iter().enumerate() {
+            let path = dir.join(format!("{}.json", index));
+            fs::write(&path, text).unwrap();
+            assert_eq!(
+                StandardJsonInput::is_standard_json(&path),
+                *expected,
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_compiler_settings() {
+        let settings = CompilerSettings {
+            remappings: vec!["a/=b/".to_string()],
+            optimizer: Some((false, 200)),
+            evm_version: None,
+            via_ir: Some(true),
+            compiler_version: Some("0.8.20".to_string()),
+        };
+        let mut trap = TrapWriter::new("compilerSettings");
+        write_compiler_settings(&mut trap, "input.json", &settings, &["A.sol".to_string()]);
+        let output = trap.format();
+        assert!(output.contains("solidity_compiler_settings(#"));
+        assert!(output.contains(", 0, \"a/=b/\")"));
+        assert!(output.contains(", 0, 200)"));
+        assert!(output.contains("solidity_compiler_via_ir("));
+        assert!(!output.contains("solidity_compiler_evm_version"));
+        assert!(output.contains("solidity_compiler_version(#"));
+        assert!(output.contains("files(#"));
+        assert!(output.contains("solidity_compiler_settings_file(#"));
+    }
+}
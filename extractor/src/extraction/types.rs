@@ -0,0 +1,1266 @@
+//! Syntactic type inference.
+//!
+//! The types of expressions are inferred from the declarations their names are
+//! bound to (see the `names` module) and recorded in `solidity_expr_type` as
+//! canonical type strings, close to those the compiler prints:
+//!
+//! - elementary types with their sizes spelled out (`uint256`, `bytes1`,
+//!   `address payable`, `string`)
+//! - `contract C` (interfaces too) and `library L`
+//! - `struct C.S`, `enum C.E` and user-defined value types `C.T`, qualified by
+//!   the contract declaring them, if any
+//! - `mapping(K => V)`, `T[]`, `T[N]`, and `tuple(T1,T2)` for the values of a
+//!   call returning several
+//! - `function (T1,T2) returns (R)`
+//! - `type(T)` for a type used as an expression (`IERC20` in `IERC20(a)`)
+//! - `int_const N`, `rational_const` and `literal_string` for literals, and
+//!   for arithmetic on number literals, whose value is folded (a bare
+//!   `int_const` is a value too large to compute)
+//! - `msg`, `block`, `tx` and `abi`
+//!
+//! Data locations are left out. The members of structs (`s.amount`), of
+//! contract-typed values (`token.transfer`) and functions attached with
+//! `using for` (`x.add(y)`), which name resolution alone cannot bind, are
+//! bound from the types of their objects.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tree_sitter::{Node, Tree};
+
+use super::names::{
+    call_arity, dedup, filter_arity, is_reference, named_children, text, unwrap, Contract,
+    Resolver, Symbol, Target, CONTRACT_KINDS,
+};
+
+/// Types of the members of the builtins.
+const BUILTIN_MEMBER_TYPES: &[(&str, &str, &str)] = &[
+    ("block", "basefee", "uint256"),
+    ("block", "blobbasefee", "uint256"),
+    ("block", "chainid", "uint256"),
+    ("block", "coinbase", "address payable"),
+    ("block", "difficulty", "uint256"),
+    ("block", "gaslimit", "uint256"),
+    ("block", "number", "uint256"),
+    ("block", "prevrandao", "uint256"),
+    ("block", "timestamp", "uint256"),
+    ("msg", "data", "bytes"),
+    ("msg", "sender", "address"),
+    ("msg", "sig", "bytes4"),
+    ("msg", "value", "uint256"),
+    ("tx", "gasprice", "uint256"),
+    ("tx", "origin", "address"),
+];
+
+/// Types returned by the builtin functions; `abi.decode` returns the types it
+/// is given.
+const BUILTIN_RETURN_TYPES: &[(&str, &str)] = &[
+    ("abi.encode", "bytes"),
+    ("abi.encodeCall", "bytes"),
+    ("abi.encodePacked", "bytes"),
+    ("abi.encodeWithSelector", "bytes"),
+    ("abi.encodeWithSignature", "bytes"),
+    ("addmod", "uint256"),
+    ("blobhash", "bytes32"),
+    ("blockhash", "bytes32"),
+    ("ecrecover", "address"),
+    ("gasleft", "uint256"),
+    ("keccak256", "bytes32"),
+    ("mulmod", "uint256"),
+    ("ripemd160", "bytes20"),
+    ("sha256", "bytes32"),
+];
+
+/// Ether and time units, and the values they multiply by.
+const NUMBER_UNITS: &[(&str, u128)] = &[
+    ("wei", 1),
+    ("gwei", 1_000_000_000),
+    ("szabo", 1_000_000_000_000),
+    ("finney", 1_000_000_000_000_000),
+    ("ether", 1_000_000_000_000_000_000),
+    ("seconds", 1),
+    ("minutes", 60),
+    ("hours", 3_600),
+    ("days", 86_400),
+    ("weeks", 604_800),
+    ("years", 31_536_000),
+];
+
+/// A type as written in a declaration, before its names are resolved.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypeName {
+    /// An elementary type, with its size spelled out (`uint` is `uint256`)
+    Elementary(String),
+    /// A user-defined type, by its name as written (`IERC20`, `Lib.Info`)
+    UserDefined(Vec<String>),
+    /// `mapping(K => V)`
+    Mapping(Box<TypeName>, Box<TypeName>),
+    /// An array, and its length as written (None = dynamic)
+    Array(Box<TypeName>, Option<String>),
+    /// A function type
+    Function,
+    /// The values a function returns
+    Tuple(Vec<TypeName>),
+}
+
+/// A `using for` directive.
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
+pub struct Using {
+    /// Library whose functions are attached (`using L for T`), as written
+    pub library: Option<Vec<String>>,
+    /// Functions attached (`using {f, L.g} for T`), as written
+    pub functions: Vec<Vec<String>>,
+    /// Type they are attached to (None = `*`, any type)
+    pub target: Option<TypeName>,
+    /// Whether the directive is `global`
+    pub global: bool,
+}
+
+/// Read a type as written (a `type_name`, `primitive_type` or
+/// `user_defined_type` node).
+pub(super) fn type_name(node: Node, source: &str) -> Option<TypeName> {
+    match node.kind() {
+        "primitive_type" => Some(TypeName::Elementary(elementary_name(text(node, source)))),
+        "user_defined_type" => Some(TypeName::UserDefined(
+            named_children(node)
+                .map(|segment| text(segment, source).to_string())
+                .collect(),
+        )),
+        "type_name" => {
+            if let Some(key) = node.child_by_field_name("key_type") {
+                let value = node.child_by_field_name("value_type")?;
+                return Some(TypeName::Mapping(
+                    Box::new(type_name(key, source)?),
+                    Box::new(type_name(value, source)?),
+                ));
+            }
+            if node.child(0).is_some_and(|c| c.kind() == "function") {
+                return Some(TypeName::Function);
+            }
+            let mut children = named_children(node);
+            let element = type_name(children.next()?, source)?;
+            let mut cursor = node.walk();
+            if !node.children(&mut cursor).any(|c| c.kind() == "[") {
+                return Some(element);
+            }
+            let length = children
+                .next()
+                .map(|length| text(length, source).to_string());
+            Some(TypeName::Array(Box::new(element), length))
+        }
+        _ => None,
+    }
+}
+
+/// Spell out the size of an elementary type name.
+fn elementary_name(name: &str) -> String {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    match name.as_str() {
+        "uint" => "uint256".to_string(),
+        "int" => "int256".to_string(),
+        "byte" => "bytes1".to_string(),
+        "ufixed" => "ufixed128x18".to_string(),
+        "fixed" => "fixed128x18".to_string(),
+        _ => name,
+    }
+}
+
+/// Read a `using for` directive.
+pub(super) fn using(node: Node, source: &str) -> Using {
+    let path = |node: Node| {
+        named_children(node)
+            .filter(|c| c.kind() == "identifier")
+            .map(|c| text(c, source).to_string())
+            .collect::<Vec<_>>()
+    };
+    let mut using = Using {
+        library: None,
+        functions: Vec::new(),
+        target: node
+            .child_by_field_name("source")
+            .and_then(|target| type_name(target, source)),
+        global: false,
+    };
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match child.kind() {
+            "type_alias" => using.library = Some(path(child)),
+            "using_alias" => using.functions.push(path(child)),
+            "global" => using.global = true,
+            _ => {}
+        }
+    }
+    using
+}
+
+/// A declaration in some file.
+type Declaration<'a> = (&'a str, &'a Symbol);
+
+/// An inferred type.
+#[derive(Debug, Clone)]
+enum Type<'a> {
+    /// An elementary type or the type of a literal, as rendered
+    Elementary(String),
+    Contract(Contract<'a>),
+    Struct(Declaration<'a>),
+    Enum(Declaration<'a>),
+    /// A user-defined value type
+    UserValue(Declaration<'a>),
+    Mapping(Box<Type<'a>>, Box<Type<'a>>),
+    /// An array, and its length as written (None = dynamic)
+    Array(Box<Type<'a>>, Option<String>),
+    /// Values returned by a call, which may be unknown
+    Tuple(Vec<Option<Type<'a>>>),
+    /// A type used as an expression (`IERC20` in `IERC20(a)`)
+    Named(Box<Type<'a>>),
+    /// `type(T)`
+    Meta(Box<Type<'a>>),
+    /// `msg`, `block`, `tx` or `abi`
+    Magic(&'static str),
+    /// A function, or its overloads
+    Function(Vec<Declaration<'a>>),
+    /// The getter of a state variable of another contract (`token.owner`)
+    Getter(Declaration<'a>),
+    /// A builtin function, and the type it returns
+    Returns(Box<Type<'a>>),
+}
+
+impl Type<'_> {
+    /// Check whether this is the type of a number literal.
+    fn is_literal(&self) -> bool {
+        matches!(self, Type::Elementary(name) if name.starts_with("int_const") || name == "rational_const")
+    }
+}
+
+fn elementary<'a>(name: &str) -> Type<'a> {
+    Type::Elementary(name.to_string())
+}
+
+/// Kinds of nodes a type can be inferred for (see `Inference::infer`).
+pub(super) const TYPED_KINDS: &[&str] = &[
+    "array_access",
+    "assignment_expression",
+    "augmented_assignment_expression",
+    "binary_expression",
+    "boolean_literal",
+    "call_expression",
+    "expression",
+    "hex_string_literal",
+    "identifier",
+    "inline_array_expression",
+    "member_expression",
+    "meta_type_expression",
+    "new_expression",
+    "number_literal",
+    "parenthesized_expression",
+    "payable_conversion_expression",
+    "primitive_type",
+    "slice_access",
+    "string_literal",
+    "struct_expression",
+    "ternary_expression",
+    "tuple_expression",
+    "type_cast_expression",
+    "unary_expression",
+    "unicode_string_literal",
+    "update_expression",
+];
+
+/// Types of a file's expressions, and the members bound from the types of
+/// their objects.
+pub(super) struct Inferred<'a> {
+    /// Expressions and their types, in source order
+    pub types: Vec<(Node<'a>, String)>,
+    pub bindings: Vec<(Node<'a>, Vec<Target<'a>>)>,
+}
+
+/// A node to type, given its field and parent, the number of arguments it is
+/// called with if it is the function of a call, and the innermost contract.
+#[derive(Clone, Copy)]
+struct Visit<'a> {
+    node: Node<'a>,
+    field: Option<&'static str>,
+    parent: Option<Node<'a>>,
+    arity: Option<usize>,
+    contract: Option<Contract<'a>>,
+}
+
+/// Infers the types of a file's expressions.
+struct Inference<'a, 'r> {
+    resolver: &'r Resolver<'a>,
+    source: &'a str,
+    /// What names were bound to, by node id
+    bindings: &'r HashMap<usize, Vec<Target<'a>>>,
+    /// Types inferred so far, by node id
+    types: HashMap<usize, Type<'a>>,
+    /// Members bound from the types of their objects
+    found: Vec<(Node<'a>, Vec<Target<'a>>)>,
+}
+
+/// Infer the types of the expressions of a file whose names are bound.
+///
+/// `bindings` are what names were bound to, by node id.
+pub(super) fn infer_types<'a>(
+    resolver: &Resolver<'a>,
+    tree: &'a Tree,
+    source: &'a str,
+    bindings: &HashMap<usize, Vec<Target<'a>>>,
+) -> Inferred<'a> {
+    // Nodes in pre-order, visited in reverse so that operands are typed
+    // before the expressions using them
+    let mut visits = Vec::new();
+    let mut pending = vec![Visit {
+        node: tree.root_node(),
+        field: None,
+        parent: None,
+        arity: None,
+        contract: None,
+    }];
+    while let Some(visit) = pending.pop() {
+        let node = visit.node;
+        // Yul is untyped
+        if node.is_missing() || node.kind() == "assembly_statement" {
+            continue;
+        }
+        let contract = match CONTRACT_KINDS.contains(&node.kind()) {
+            true => resolver.own_contract(node),
+            false => visit.contract,
+        };
+        let call_arity = (node.kind() == "call_expression").then(|| call_arity(node));
+        let mut cursor = node.walk();
+        if cursor.goto_first_child() {
+            loop {
+                let child = cursor.node();
+                if child.is_named() {
+                    let field = cursor.field_name();
+                    let arity = match (node.kind(), field) {
+                        ("call_expression", Some("function")) => call_arity,
+                        ("expression", _) => visit.arity,
+                        _ => None,
+                    };
+                    pending.push(Visit {
+                        node: child,
+                        field,
+                        parent: Some(node),
+                        arity,
+                        contract,
+                    });
+                }
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+        // Syntax errors are not extracted, but the nodes recovered in them are
+        if !node.is_error() {
+            visits.push(Visit { contract, ..visit });
+        }
+    }
+    visits.sort_by_key(|visit| visit.node.start_byte());
+
+    let mut inference = Inference {
+        resolver,
+        source,
+        bindings,
+        types: HashMap::new(),
+        found: Vec::new(),
+    };
+    for visit in visits.iter().rev() {
+        if let Some(inferred) = inference.infer(*visit) {
+            inference.types.insert(visit.node.id(), inferred);
+        }
+    }
+    let types = visits
+        .iter()
+        .filter_map(|visit| {
+            let inferred = inference.types.get(&visit.node.id())?;
+            Some((visit.node, inference.render(inferred)?))
+        })
+        .collect();
+    Inferred {
+        types,
+        bindings: inference.found,
+    }
+}
+
+impl<'a> Inference<'a, '_> {
+    fn type_of(&self, node: Node) -> Option<Type<'a>> {
+        self.types.get(&node.id()).cloned()
+    }
+
+    /// Infer the type of a node from those of its children. Only nodes of
+    /// [`TYPED_KINDS`] have one.
+    fn infer(&mut self, visit: Visit<'a>) -> Option<Type<'a>> {
+        let Visit {
+            node,
+            field,
+            parent,
+            arity,
+            contract,
+        } = visit;
+        let field_type = |name: &str| {
+            node.child_by_field_name(name)
+                .and_then(|child| self.type_of(child))
+        };
+        match node.kind() {
+            "expression" | "parenthesized_expression" => self.type_of(named_children(node).next()?),
+            "identifier" if is_reference(field, parent) => match text(node, self.source) {
+                "this" => contract.map(Type::Contract),
+                _ => self.targets_type(self.bindings.get(&node.id())?, contract),
+            },
+            "primitive_type" if parent.is_some_and(|p| p.kind() == "expression") => {
+                let name = elementary_name(text(node, self.source));
+                Some(Type::Named(Box::new(Type::Elementary(name))))
+            }
+            "member_expression" => self.member_type(node, arity, contract),
+            "array_access" => match field_type("base")? {
+                Type::Mapping(_, value) => Some(*value),
+                Type::Array(element, _) => Some(*element),
+                Type::Elementary(name) if name.starts_with("bytes") => Some(elementary("bytes1")),
+                // An array type (`uint[]` in `abi.decode(data, (uint[]))`)
+                Type::Named(element) => {
+                    let length = node
+                        .child_by_field_name("index")
+                        .map(|index| text(index, self.source).to_string());
+                    Some(Type::Named(Box::new(Type::Array(element, length))))
+                }
+                _ => None,
+            },
+            "slice_access" => field_type("base"),
+            "call_expression" => self.call_type(node),
+            "new_expression" => {
+                let created = self.resolve_node(node.child_by_field_name("name")?, contract)?;
+                Some(Type::Named(Box::new(created)))
+            }
+            "type_cast_expression" => {
+                let converted = named_children(node).find(|c| c.kind() == "primitive_type")?;
+                self.resolve_node(converted, contract)
+            }
+            "payable_conversion_expression" => Some(elementary("address payable")),
+            "meta_type_expression" => {
+                let named = named_children(node).find_map(|c| self.resolve_node(c, contract))?;
+                Some(Type::Meta(Box::new(named)))
+            }
+            "struct_expression" => match field_type("type")? {
+                Type::Named(created) => Some(*created),
+                _ => None,
+            },
+            "binary_expression" => {
+                let operator = text(node.child_by_field_name("operator")?, self.source);
+                if matches!(
+                    operator,
+                    "==" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||"
+                ) {
+                    return Some(elementary("bool"));
+                }
+                let left = field_type("left");
+                if matches!(operator, "<<" | ">>" | ">>>" | "**") {
+                    return left;
+                }
+                match (left, field_type("right")) {
+                    (Some(left), Some(right)) if left.is_literal() && right.is_literal() => {
+                        Some(fold_literals(operator, &left, &right))
+                    }
+                    (Some(left), right) if left.is_literal() => right.or(Some(left)),
+                    (left, right) => left.or(right),
+                }
+            }
+            "unary_expression" => {
+                let operator = text(node.child_by_field_name("operator")?, self.source);
+                let argument = field_type("argument");
+                match operator {
+                    "!" => Some(elementary("bool")),
+                    "delete" => Some(Type::Tuple(Vec::new())),
+                    "-" => Some(negate(argument?)),
+                    _ => argument,
+                }
+            }
+            "update_expression" => field_type("argument"),
+            "assignment_expression" | "augmented_assignment_expression" => field_type("left"),
+            "ternary_expression" => {
+                let mut branches = named_children(node).skip(1).map(|b| self.type_of(b));
+                match (branches.next().flatten(), branches.next().flatten()) {
+                    (Some(left), right) if left.is_literal() => right.or(Some(left)),
+                    (left, right) => left.or(right),
+                }
+            }
+            "tuple_expression" => {
+                let mut components: Vec<Option<Type<'a>>> =
+                    named_children(node).map(|c| self.type_of(c)).collect();
+                match components.len() {
+                    1 => components.pop().flatten(),
+                    _ => Some(Type::Tuple(components)),
+                }
+            }
+            "inline_array_expression" => {
+                let elements: Vec<Node> = named_children(node).collect();
+                let element = mobile(self.type_of(*elements.first()?)?)?;
+                Some(Type::Array(
+                    Box::new(element),
+                    Some(elements.len().to_string()),
+                ))
+            }
+            "number_literal" => Some(Type::Elementary(number_type(text(node, self.source)))),
+            "string_literal" | "hex_string_literal" | "unicode_string_literal" => {
+                Some(elementary("literal_string"))
+            }
+            "boolean_literal" => Some(elementary("bool")),
+            _ => None,
+        }
+    }
+
+    /// Get the type of a name bound to the given declarations.
+    fn targets_type(
+        &self,
+        targets: &[Target<'a>],
+        contract: Option<Contract<'a>>,
+    ) -> Option<Type<'a>> {
+        let functions: Vec<Declaration<'a>> = targets
+            .iter()
+            .filter_map(|&target| match target {
+                Target::Global(file, symbol) if symbol.kind == "function_definition" => {
+                    Some((file, symbol))
+                }
+                _ => None,
+            })
+            .collect();
+        if !functions.is_empty() {
+            return Some(Type::Function(functions));
+        }
+        targets
+            .iter()
+            .find_map(|&target| self.target_type(target, contract))
+    }
+
+    /// Get the type of a name bound to a declaration.
+    fn target_type(&self, target: Target<'a>, contract: Option<Contract<'a>>) -> Option<Type<'a>> {
+        match target {
+            Target::Local(node) => match node.kind() {
+                "variable_declaration" | "parameter" => {
+                    self.resolve_node(node.child_by_field_name("type")?, contract)
+                }
+                _ => None,
+            },
+            Target::Global(file, symbol) => self.declaration_type(file, symbol),
+            Target::Builtin(builtin, None) => match builtin {
+                "abi" | "block" | "msg" | "tx" => Some(Type::Magic(builtin)),
+                "now" => Some(elementary("uint256")),
+                _ => builtin_returns(builtin),
+            },
+            Target::Builtin(builtin, Some(member)) => BUILTIN_MEMBER_TYPES
+                .iter()
+                .find(|&&(b, m, _)| b == builtin && m == member)
+                .map(|&(_, _, member_type)| elementary(member_type))
+                .or_else(|| builtin_returns(&format!("{}.{}", builtin, member))),
+            Target::Unit(_) => None,
+        }
+    }
+
+    /// Get the type of a name bound to a declaration other files can refer to.
+    fn declaration_type(&self, file: &'a str, symbol: &'a Symbol) -> Option<Type<'a>> {
+        match symbol.kind {
+            "state_variable_declaration" | "constant_variable_declaration" | "struct_member" => {
+                let context = self.resolver.context_contract(file, symbol);
+                self.resolve(file, context, symbol.type_name.as_ref()?)
+            }
+            "enum_value" => Some(Type::Enum((file, self.resolver.container(file, symbol)?))),
+            "function_definition" => Some(Type::Function(vec![(file, symbol)])),
+            _ => Some(Type::Named(Box::new(self.named_type(file, symbol)?))),
+        }
+    }
+
+    /// Get the type a contract, struct, enum or user-defined value type declares.
+    fn named_type(&self, file: &'a str, symbol: &'a Symbol) -> Option<Type<'a>> {
+        match symbol.kind {
+            kind if CONTRACT_KINDS.contains(&kind) => Some(Type::Contract((file, symbol))),
+            "struct_declaration" => Some(Type::Struct((file, symbol))),
+            "enum_declaration" => Some(Type::Enum((file, symbol))),
+            "user_defined_type_definition" => Some(Type::UserValue((file, symbol))),
+            _ => None,
+        }
+    }
+
+    /// Resolve a type written in a file, within a contract if given.
+    fn resolve(
+        &self,
+        file: &str,
+        contract: Option<Contract<'a>>,
+        written: &TypeName,
+    ) -> Option<Type<'a>> {
+        let resolve = |written: &TypeName| self.resolve(file, contract, written).map(Box::new);
+        match written {
+            TypeName::Elementary(name) => Some(Type::Elementary(name.clone())),
+            TypeName::UserDefined(path) => self
+                .resolver
+                .resolve_path(file, contract, path)
+                .into_iter()
+                .find_map(|target| match target {
+                    Target::Global(file, symbol) => self.named_type(file, symbol),
+                    _ => None,
+                }),
+            TypeName::Mapping(key, value) => Some(Type::Mapping(resolve(key)?, resolve(value)?)),
+            TypeName::Array(element, length) => {
+                Some(Type::Array(resolve(element)?, length.clone()))
+            }
+            TypeName::Function => Some(elementary("function")),
+            TypeName::Tuple(components) => Some(Type::Tuple(
+                components
+                    .iter()
+                    .map(|c| self.resolve(file, contract, c))
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Resolve a type written in the file being typed.
+    fn resolve_node(&self, node: Node, contract: Option<Contract<'a>>) -> Option<Type<'a>> {
+        let written = type_name(node, self.source)?;
+        self.resolve(&self.resolver.own().path, contract, &written)
+    }
+
+    /// Get the type of a member access, binding the member from the type of
+    /// its object if name resolution could not.
+    fn member_type(
+        &mut self,
+        node: Node<'a>,
+        arity: Option<usize>,
+        contract: Option<Contract<'a>>,
+    ) -> Option<Type<'a>> {
+        let property = node.child_by_field_name("property")?;
+        let name = text(property, self.source);
+        // `this.f`, `C.f`, `E.A`, `msg.sender`, ...
+        if let Some(targets) = self.bindings.get(&property.id()).filter(|t| !t.is_empty()) {
+            return self.targets_type(targets, contract);
+        }
+
+        let object = self.type_of(node.child_by_field_name("object")?)?;
+        let members = match &object {
+            Type::Struct((file, symbol)) => symbol
+                .members
+                .iter()
+                .filter(|member| member.name == name)
+                .map(|member| Target::Global(file, member))
+                .collect(),
+            Type::Contract(contract) => filter_arity(
+                self.resolver
+                    .lookup_in(&self.resolver.linearization(*contract), name),
+                arity,
+            ),
+            _ => Vec::new(),
+        };
+        let member_type = match members.as_slice() {
+            [] => None,
+            &[Target::Global(file, variable), ..]
+                if variable.kind == "state_variable_declaration"
+                    && matches!(object, Type::Contract(_)) =>
+            {
+                Some(Type::Getter((file, variable)))
+            }
+            _ => self.targets_type(&members, contract),
+        };
+        if !members.is_empty() {
+            self.found.push((property, members));
+            return member_type;
+        }
+
+        if let Some(member_type) = self.builtin_member_type(&object, name) {
+            return Some(member_type);
+        }
+        let attached = self.attached(&object, name, arity, contract);
+        let attached_type = self.targets_type(&attached, contract);
+        if !attached.is_empty() {
+            self.found.push((property, attached));
+        }
+        attached_type
+    }
+
+    /// Get the type of a member of addresses, arrays, functions and types.
+    fn builtin_member_type(&self, object: &Type<'a>, name: &str) -> Option<Type<'a>> {
+        let returns = |returned: Type<'a>| Some(Type::Returns(Box::new(returned)));
+        match (object, name) {
+            (Type::Elementary(t), _) if t.starts_with("address") => match name {
+                "balance" => Some(elementary("uint256")),
+                "code" => Some(elementary("bytes")),
+                "codehash" => Some(elementary("bytes32")),
+                "send" => returns(elementary("bool")),
+                "transfer" => returns(Type::Tuple(Vec::new())),
+                "call" | "delegatecall" | "staticcall" => returns(Type::Tuple(vec![
+                    Some(elementary("bool")),
+                    Some(elementary("bytes")),
+                ])),
+                _ => None,
+            },
+            (Type::Array(..), "length") => Some(elementary("uint256")),
+            (Type::Elementary(t), "length") if t == "bytes" => Some(elementary("uint256")),
+            (Type::Function(_), "selector") => Some(elementary("bytes4")),
+            (Type::Function(_), "address") => Some(elementary("address")),
+            (Type::Meta(named), "min" | "max") => Some((**named).clone()),
+            (Type::Meta(_), "interfaceId") => Some(elementary("bytes4")),
+            (Type::Meta(_), "name") => Some(elementary("string")),
+            (Type::Meta(_), "creationCode" | "runtimeCode") => Some(elementary("bytes")),
+            (Type::Named(named), "concat") => match &**named {
+                Type::Elementary(t) if t == "string" || t == "bytes" => returns((**named).clone()),
+                _ => None,
+            },
+            (Type::Named(named), "wrap" | "unwrap") => {
+                let Type::UserValue((file, symbol)) = **named else {
+                    return None;
+                };
+                match name {
+                    "wrap" => returns((**named).clone()),
+                    _ => returns(self.declaration_underlying(file, symbol)?),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the underlying type of a user-defined value type.
+    fn declaration_underlying(&self, file: &'a str, symbol: &'a Symbol) -> Option<Type<'a>> {
+        self.resolve(file, None, symbol.type_name.as_ref()?)
+    }
+
+    /// Get the functions attached to a type with `using for` that have the
+    /// given name: those of the file's and the current contract's directives,
+    /// and the `global` ones of the file declaring the type.
+    fn attached(
+        &self,
+        object: &Type<'a>,
+        name: &str,
+        arity: Option<usize>,
+        contract: Option<Contract<'a>>,
+    ) -> Vec<Target<'a>> {
+        let Some(rendered) = self.render(object) else {
+            return Vec::new();
+        };
+        let own = self.resolver.own();
+        let mut usings: Vec<(&'a str, Option<Contract<'a>>, &'a Using)> = own
+            .usings
+            .iter()
+            .map(|using| (own.path.as_str(), None, using))
+            .collect();
+        if let Some(contract) = contract {
+            usings.extend(
+                contract
+                    .1
+                    .usings
+                    .iter()
+                    .map(|u| (contract.0, Some(contract), u)),
+            );
+        }
+        if let Type::Struct((file, _)) | Type::Enum((file, _)) | Type::UserValue((file, _)) = object
+        {
+            if let Some(declaring) = self.resolver.file(file).filter(|_| *file != own.path) {
+                let global = declaring.usings.iter().filter(|using| using.global);
+                usings.extend(global.map(|using| (*file, None, using)));
+            }
+        }
+
+        let mut attached = Vec::new();
+        for (file, context, using) in usings {
+            if let Some(target) = &using.target {
+                let target = self.resolve(file, context, target);
+                if target.and_then(|t| self.render(&t)).as_ref() != Some(&rendered) {
+                    continue;
+                }
+            }
+            let mut candidates = Vec::new();
+            if let Some(library) = &using.library {
+                for (library_file, library) in self
+                    .resolver
+                    .resolve_path(file, context, library)
+                    .into_iter()
+                    .filter_map(Target::contract)
+                {
+                    let functions = library.members.iter().filter(|m| m.name == name);
+                    candidates.extend(functions.map(|f| Target::Global(library_file, f)));
+                }
+            }
+            for function in &using.functions {
+                if function.last().is_some_and(|last| last == name) {
+                    candidates.extend(self.resolver.resolve_path(file, context, function));
+                }
+            }
+            attached.extend(
+                candidates
+                    .into_iter()
+                    .filter(|&candidate| self.takes_first(candidate, &rendered)),
+            );
+        }
+        dedup(&mut attached);
+        filter_arity(attached, arity.map(|arity| arity + 1))
+    }
+
+    /// Check whether a function's first parameter has the given type.
+    fn takes_first(&self, function: Target<'a>, rendered: &str) -> bool {
+        let Target::Global(file, symbol) = function else {
+            return false;
+        };
+        let Some(first) = symbol.parameters.first() else {
+            return false;
+        };
+        let context = self.resolver.context_contract(file, symbol);
+        let first = self.resolve(file, context, first);
+        first.and_then(|t| self.render(&t)).as_deref() == Some(rendered)
+    }
+
+    /// Get the type of a call: what a function returns, or the type converted
+    /// to or created.
+    fn call_type(&self, call: Node<'a>) -> Option<Type<'a>> {
+        let callee = call.child_by_field_name("function")?;
+        let Some(callee_type) = self.type_of(callee) else {
+            return self.decoded_type(call, callee);
+        };
+        match callee_type {
+            Type::Named(converted) => Some(*converted),
+            Type::Returns(returned) => Some(*returned),
+            Type::Function(functions) => {
+                let (file, function) = *functions.first()?;
+                let context = self.resolver.context_contract(file, function);
+                match self.resolve(file, context, function.type_name.as_ref()?)? {
+                    Type::Tuple(mut returned) if returned.len() == 1 => returned.pop().flatten(),
+                    returned => Some(returned),
+                }
+            }
+            // A getter takes a key or index for each mapping or array level
+            Type::Getter((file, variable)) => {
+                let mut returned = self.declaration_type(file, variable)?;
+                for _ in 0..call_arity(call) {
+                    returned = match returned {
+                        Type::Mapping(_, value) => *value,
+                        Type::Array(element, _) => *element,
+                        _ => return None,
+                    };
+                }
+                Some(returned)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the types `abi.decode` returns, given as its second argument.
+    fn decoded_type(&self, call: Node<'a>, callee: Node<'a>) -> Option<Type<'a>> {
+        let member = unwrap(callee, "expression");
+        let property = member.child_by_field_name("property")?;
+        let decode = Target::Builtin("abi", Some("decode"));
+        if !self.bindings.get(&property.id())?.contains(&decode) {
+            return None;
+        }
+        let types = named_children(call)
+            .filter(|c| c.kind() == "call_argument")
+            .nth(1)
+            .and_then(|argument| named_children(argument).next())?;
+        match self.type_of(types)? {
+            Type::Named(decoded) => Some(*decoded),
+            Type::Tuple(components) => Some(Type::Tuple(
+                components
+                    .into_iter()
+                    .map(|component| match component {
+                        Some(Type::Named(decoded)) => Some(*decoded),
+                        _ => None,
+                    })
+                    .collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Render a type as a canonical type string, if it has one.
+    fn render(&self, rendered: &Type<'a>) -> Option<String> {
+        Some(match rendered {
+            Type::Elementary(name) => name.clone(),
+            Type::Contract((_, contract)) => match contract.kind {
+                "library_declaration" => format!("library {}", contract.name),
+                _ => format!("contract {}", contract.name),
+            },
+            Type::Struct((file, symbol)) => format!("struct {}", self.qualified_name(file, symbol)),
+            Type::Enum((file, symbol)) => format!("enum {}", self.qualified_name(file, symbol)),
+            Type::UserValue((file, symbol)) => self.qualified_name(file, symbol),
+            Type::Mapping(key, value) => {
+                format!("mapping({} => {})", self.render(key)?, self.render(value)?)
+            }
+            Type::Array(element, length) => format!(
+                "{}[{}]",
+                self.render(element)?,
+                length.as_deref().unwrap_or("")
+            ),
+            Type::Tuple(components) => format!("tuple({})", self.render_list(components)),
+            Type::Named(named) | Type::Meta(named) => format!("type({})", self.render(named)?),
+            Type::Magic(name) => name.to_string(),
+            Type::Function(functions) => {
+                let (file, function) = *functions.first()?;
+                let context = self.resolver.context_contract(file, function);
+                let parameters: Vec<Option<Type<'a>>> = function
+                    .parameters
+                    .iter()
+                    .map(|p| self.resolve(file, context, p))
+                    .collect();
+                let parameters = self.render_list(&parameters);
+                match function
+                    .type_name
+                    .as_ref()
+                    .and_then(|returned| self.resolve(file, context, returned))
+                {
+                    Some(Type::Tuple(returned)) if !returned.is_empty() => format!(
+                        "function ({}) returns ({})",
+                        parameters,
+                        self.render_list(&returned)
+                    ),
+                    _ => format!("function ({})", parameters),
+                }
+            }
+            Type::Getter(_) | Type::Returns(_) => return None,
+        })
+    }
+
+    /// Render types separated by commas, unknown ones as empty strings.
+    fn render_list(&self, types: &[Option<Type<'a>>]) -> String {
+        types
+            .iter()
+            .map(|t| t.as_ref().and_then(|t| self.render(t)).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Get the name of a declaration qualified by its contract, if any.
+    fn qualified_name(&self, file: &str, symbol: &Symbol) -> String {
+        match self.resolver.container(file, symbol) {
+            Some(container) if CONTRACT_KINDS.contains(&container.kind) => {
+                format!("{}.{}", container.name, symbol.name)
+            }
+            _ => symbol.name.clone(),
+        }
+    }
+}
+
+/// Get the types of the parameters of a function as canonical type strings,
+/// which tell its overloads apart.
+pub(super) fn parameter_types<'a>(
+    resolver: &Resolver<'a>,
+    file: &'a str,
+    function: &'a Symbol,
+) -> Vec<Option<String>> {
+    let bindings = HashMap::new();
+    let inference = Inference {
+        resolver,
+        source: "",
+        bindings: &bindings,
+        types: HashMap::new(),
+        found: Vec::new(),
+    };
+    let context = resolver.context_contract(file, function);
+    function
+        .parameters
+        .iter()
+        .map(|parameter| {
+            let resolved = inference.resolve(file, context, parameter)?;
+            inference.render(&resolved)
+        })
+        .collect()
+}
+
+/// Get the builtin function with the given name, if it returns a known type.
+fn builtin_returns<'a>(name: &str) -> Option<Type<'a>> {
+    BUILTIN_RETURN_TYPES
+        .iter()
+        .find(|&&(builtin, _)| builtin == name)
+        .map(|&(_, returned)| Type::Returns(Box::new(elementary(returned))))
+}
+
+/// Get the type a literal takes when it is not converted: the smallest integer
+/// type holding a number, `string` for a string.
+fn mobile(literal: Type) -> Option<Type> {
+    let Type::Elementary(name) = &literal else {
+        return Some(literal);
+    };
+    if name == "literal_string" {
+        return Some(elementary("string"));
+    }
+    if name == "rational_const" {
+        return None;
+    }
+    let Some(value) = name.strip_prefix("int_const") else {
+        return Some(literal);
+    };
+    let value = value.trim();
+    let (signed, magnitude) = match value.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, value),
+    };
+    let Ok(magnitude) = magnitude.parse::<u128>() else {
+        return Some(elementary(if signed { "int256" } else { "uint256" }));
+    };
+    // `-0` is zero
+    let signed = signed && magnitude != 0;
+    let bits = match signed {
+        true => 129 - (magnitude - 1).leading_zeros(),
+        false => 128 - magnitude.leading_zeros(),
+    };
+    let bits = bits.max(1).div_ceil(8) * 8;
+    let prefix = if signed { "int" } else { "uint" };
+    Some(Type::Elementary(format!("{}{}", prefix, bits)))
+}
+
+/// Get the type of a negated expression: the negated value of an
+/// `int_const`, the same type otherwise.
+fn negate(argument: Type) -> Type {
+    let Type::Elementary(name) = &argument else {
+        return argument;
+    };
+    let Some(value) = name.strip_prefix("int_const ") else {
+        return argument;
+    };
+    Type::Elementary(match value.strip_prefix('-') {
+        Some(positive) => format!("int_const {}", positive),
+        None if value == "0" => "int_const 0".to_string(),
+        None => format!("int_const -{}", value),
+    })
+}
+
+/// Get the value of an `int_const` type, if it is known.
+fn const_value(literal: &Type) -> Option<i128> {
+    match literal {
+        Type::Elementary(name) => name.strip_prefix("int_const ")?.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Get the type of a binary operation on two number literals, folding their
+/// values as the compiler does: `int_const` and the value, `rational_const`
+/// for a fraction, or a bare `int_const` if the value cannot be computed.
+fn fold_literals<'a>(operator: &str, left: &Type, right: &Type) -> Type<'a> {
+    let rational =
+        |literal: &Type| matches!(literal, Type::Elementary(name) if name == "rational_const");
+    if rational(left) || rational(right) {
+        return elementary("rational_const");
+    }
+    let (Some(left), Some(right)) = (const_value(left), const_value(right)) else {
+        return elementary("int_const");
+    };
+    let value = match operator {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" if right != 0 && left % right != 0 => return elementary("rational_const"),
+        "/" => left.checked_div(right),
+        "%" => left.checked_rem(right),
+        "&" => Some(left & right),
+        "|" => Some(left | right),
+        "^" => Some(left ^ right),
+        _ => None,
+    };
+    match value {
+        Some(value) => Type::Elementary(format!("int_const {}", value)),
+        None => elementary("int_const"),
+    }
+}
+
+/// Get the type of a number literal (`1 ether`, `0x10`, `2.5e3`):
+/// `int_const` and its value, `rational_const` for a fraction, `address` for
+/// an address.
+fn number_type(literal: &str) -> String {
+    let int_const = |value: Option<u128>| match value {
+        Some(value) => format!("int_const {}", value),
+        // Too large to compute
+        None => "int_const".to_string(),
+    };
+    let mut parts = literal.split_whitespace();
+    let number = parts.next().unwrap_or_default().replace('_', "");
+    let unit = parts
+        .next()
+        .and_then(|unit| NUMBER_UNITS.iter().find(|&&(u, _)| u == unit))
+        .map_or(1, |&(_, multiplier)| multiplier);
+
+    if let Some(digits) = number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        if digits.len() == 40 {
+            return "address".to_string();
+        }
+        let value = u128::from_str_radix(digits, 16).ok();
+        return int_const(value.and_then(|v| v.checked_mul(unit)));
+    }
+
+    let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => match exponent.parse::<i32>() {
+            Ok(exponent) => (mantissa, exponent),
+            Err(_) => return int_const(None),
+        },
+        None => (number.as_str(), 0),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let fraction = fraction.trim_end_matches('0');
+    let exponent = exponent - fraction.len() as i32;
+    let Some(value) = format!("{}{}", whole, fraction)
+        .parse::<u128>()
+        .ok()
+        .and_then(|v| v.checked_mul(unit))
+    else {
+        return int_const(None);
+    };
+    if exponent >= 0 {
+        return int_const(
+            10u128
+                .checked_pow(exponent as u32)
+                .and_then(|scale| value.checked_mul(scale)),
+        );
+    }
+    match 10u128.checked_pow(exponent.unsigned_abs()) {
+        Some(scale) if value % scale == 0 => int_const(Some(value / scale)),
+        Some(_) => "rational_const".to_string(),
+        None if value == 0 => int_const(Some(0)),
+        None => "rational_const".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::names::{FileSymbols, SymbolIndex};
+
+    #[test]
+    fn test_number_type() {
+        assert_eq!(number_type("42"), "int_const 42");
+        assert_eq!(number_type("1_000"), "int_const 1000");
+        assert_eq!(number_type("1 ether"), "int_const 1000000000000000000");
+        assert_eq!(number_type("2 days"), "int_const 172800");
+        assert_eq!(number_type("2.5e3"), "int_const 2500");
+        assert_eq!(number_type("0.5"), "rational_const");
+        assert_eq!(number_type("0xff"), "int_const 255");
+        assert_eq!(
+            number_type("0x5B38Da6a701c568545dCfcB03FcB875f56beddC4"),
+            "address"
+        );
+        assert_eq!(number_type("1e100"), "int_const");
+    }
+
+    #[test]
+    fn test_fold_literals() {
+        let fold = |operator: &str, left: &str, right: &str| match fold_literals(
+            operator,
+            &elementary(left),
+            &elementary(right),
+        ) {
+            Type::Elementary(name) => name,
+            _ => unreachable!(),
+        };
+        assert_eq!(fold("+", "int_const 2", "int_const 3"), "int_const 5");
+        assert_eq!(fold("-", "int_const 2", "int_const 3"), "int_const -1");
+        assert_eq!(fold("*", "int_const 4", "int_const -2"), "int_const -8");
+        assert_eq!(fold("/", "int_const 6", "int_const 3"), "int_const 2");
+        assert_eq!(fold("/", "int_const 1", "int_const 2"), "rational_const");
+        assert_eq!(fold("|", "int_const 1", "int_const 6"), "int_const 7");
+        assert_eq!(fold("%", "int_const 1", "int_const 0"), "int_const");
+        assert_eq!(fold("+", "int_const", "int_const 1"), "int_const");
+        assert_eq!(fold("+", "rational_const", "int_const 1"), "rational_const");
+        assert_eq!(fold("*", "int_const -0", "int_const 5"), "int_const 0");
+    }
+
+    #[test]
+    fn test_negate() {
+        let negated = |name: &str| match negate(elementary(name)) {
+            Type::Elementary(name) => name,
+            _ => unreachable!(),
+        };
+        assert_eq!(negated("int_const 5"), "int_const -5");
+        assert_eq!(negated("int_const -5"), "int_const 5");
+        assert_eq!(negated("int_const 0"), "int_const 0");
+        assert_eq!(negated("int_const"), "int_const");
+        assert_eq!(negated("int256"), "int256");
+    }
+
+    #[test]
+    fn test_mobile_type() {
+        let mobile_name = |name: &str| match mobile(elementary(name)) {
+            Some(Type::Elementary(name)) => Some(name),
+            _ => None,
+        };
+        assert_eq!(mobile_name("int_const 0").as_deref(), Some("uint8"));
+        assert_eq!(mobile_name("int_const -0").as_deref(), Some("uint8"));
+        assert_eq!(mobile_name("int_const 256").as_deref(), Some("uint16"));
+        assert_eq!(mobile_name("int_const -128").as_deref(), Some("int8"));
+        assert_eq!(mobile_name("int_const -129").as_deref(), Some("int16"));
+        assert_eq!(mobile_name("int_const").as_deref(), Some("uint256"));
+        assert_eq!(mobile_name("literal_string").as_deref(), Some("string"));
+        assert_eq!(mobile_name("rational_const"), None);
+    }
+
+    fn symbol(name: &str, kind: &'static str, start: usize) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            kind,
+            start,
+            arity: None,
+            members: Vec::new(),
+            bases: Vec::new(),
+            type_name: None,
+            parameters: Vec::new(),
+            usings: Vec::new(),
+            is_virtual: false,
+            overrides: None,
+        }
+    }
+
+    fn user_defined(name: &str) -> TypeName {
+        TypeName::UserDefined(vec![name.to_string()])
+    }
+
+    #[test]
+    fn test_render_declared_types() {
+        // `Token.sol`: contract Token { struct Info { uint amount; }
+        // mapping(address => Info[]) infos; } interface IERC20 {}
+        let info = Symbol {
+            members: vec![Symbol {
+                type_name: Some(TypeName::Elementary("uint256".to_string())),
+                ..symbol("amount", "struct_member", 20)
+            }],
+            ..symbol("Info", "struct_declaration", 10)
+        };
+        let infos = Symbol {
+            type_name: Some(TypeName::Mapping(
+                Box::new(TypeName::Elementary("address".to_string())),
+                Box::new(TypeName::Array(Box::new(user_defined("Info")), None)),
+            )),
+            ..symbol("infos", "state_variable_declaration", 40)
+        };
+        let token = Symbol {
+            members: vec![info, infos],
+            ..symbol("Token", "contract_declaration", 0)
+        };
+        let own = FileSymbols {
+            path: "Token.sol".to_string(),
+            declarations: vec![token, symbol("IERC20", "interface_declaration", 100)],
+            ..FileSymbols::default()
+        };
+        let index = SymbolIndex::default();
+        let resolver = Resolver::new(&index, &own);
+        let bindings = HashMap::new();
+        let inference = Inference {
+            resolver: &resolver,
+            source: "",
+            bindings: &bindings,
+            types: HashMap::new(),
+            found: Vec::new(),
+        };
+        let token = &own.declarations[0];
+        let infos = inference.declaration_type("Token.sol", &token.members[1]);
+        assert_eq!(
+            infos.and_then(|t| inference.render(&t)).as_deref(),
+            Some("mapping(address => struct Token.Info[])")
+        );
+        let amount = inference.declaration_type("Token.sol", &token.members[0].members[0]);
+        assert_eq!(
+            amount.and_then(|t| inference.render(&t)).as_deref(),
+            Some("uint256")
+        );
+        let erc20 = inference.declaration_type("Token.sol", &own.declarations[1]);
+        assert_eq!(
+            erc20.and_then(|t| inference.render(&t)).as_deref(),
+            Some("type(contract IERC20)")
+        );
+
+        // Outside the contract, the struct is only known by its qualified name
+        let outside = inference.resolve("Token.sol", None, &user_defined("Info"));
+        assert!(outside.is_none());
+    }
+}
@@ -8,5 +8,8 @@ pub mod extraction;
 pub mod schema;
 pub mod trap;
 
-pub use extraction::Extractor;
+#[cfg(test)]
+mod test_support;
+
+pub use extraction::{ExtractionSession, Extractor, SourceInput};
 pub use trap::{Compression, Label, TrapValue, TrapWriter};
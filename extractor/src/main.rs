@@ -3,18 +3,15 @@
 //! This extractor parses Solidity source files using tree-sitter and generates
 //! TRAP files for CodeQL database population.
 
-mod codegen;
-mod extraction;
-mod schema;
-mod trap;
-
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use std::io;
 use std::path::PathBuf;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 
-use crate::trap::Compression;
+use codeql_extractor_solidity::trap::{parse_line_range, Compression, TrapFilter};
+use codeql_extractor_solidity::{codegen, extraction, schema};
 
 /// CodeQL Extractor for Solidity smart contracts
 #[derive(Parser)]
@@ -31,9 +28,16 @@ struct Cli {
 enum Commands {
     /// Extract Solidity source files to TRAP
     Extract {
-        /// File containing list of source files to extract (one per line)
-        #[arg(long)]
-        file_list: PathBuf,
+        /// File containing list of source files to extract (one per line);
+        /// `.json` files holding solc standard-JSON input for Solidity are read as such
+        #[arg(long, required_unless_present = "bundles")]
+        file_list: Option<PathBuf>,
+
+        /// Verified-source bundle of a deployed contract to extract: a saved
+        /// Etherscan getsourcecode response, or a Sourcify contract directory
+        /// (may be repeated)
+        #[arg(long = "bundle", value_name = "PATH")]
+        bundles: Vec<PathBuf>,
 
         /// Output directory for TRAP files
         #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_TRAP_DIR")]
@@ -43,13 +47,67 @@ enum Commands {
         #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_SOURCE_ARCHIVE_DIR")]
         source_archive_dir: PathBuf,
 
-        /// Compression mode for TRAP files (gzip or none)
-        #[arg(long, default_value = "gzip")]
-        compression: String,
+        /// Compression mode for TRAP files (gzip, zstd or none, optionally with
+        /// a level as in zstd:19; defaults to the configuration file's, or gzip)
+        #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_TRAP_COMPRESSION")]
+        compression: Option<String>,
 
         /// Number of threads for parallel extraction
         #[arg(long, short = 'j')]
         threads: Option<usize>,
+
+        /// Source root, recorded as sourceLocationPrefix (defaults to the current directory)
+        #[arg(long, env = "LGTM_SRC")]
+        source_root: Option<PathBuf>,
+
+        /// Directory for reusing TRAP files of unchanged files between runs
+        #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_TRAP_CACHE")]
+        trap_cache: Option<PathBuf>,
+
+        /// File listing changed files (one per line, relative to the source root);
+        /// only they and the files they affect through imports are extracted. Other
+        /// files are copied from --trap-cache (of a base-branch run) if cached, and
+        /// left out otherwise; reading a base database is not implemented
+        #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_CHANGED_FILES")]
+        changed_files: Option<PathBuf>,
+
+        /// Path of the JSON extraction report (defaults to
+        /// extraction-report.json in the TRAP directory)
+        #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_REPORT")]
+        report: Option<PathBuf>,
+    },
+
+    /// Extract a single Solidity file and print its uncompressed TRAP to stdout
+    ExtractFile {
+        /// Source file to extract (`-` or none to read standard input)
+        file: Option<PathBuf>,
+
+        /// Path recorded for source read from standard input
+        #[arg(long, default_value = "stdin.sol")]
+        stdin_path: PathBuf,
+
+        /// Source root, recorded as sourceLocationPrefix (defaults to the current directory)
+        #[arg(long)]
+        source_root: Option<PathBuf>,
+
+        /// Only print tuples about code on these lines (N or START-END)
+        #[arg(long, value_parser = parse_line_range)]
+        lines: Option<(u64, u64)>,
+
+        /// Only print tuples of these tables (comma-separated, globs allowed
+        /// as in solidity_*_def)
+        #[arg(long, value_delimiter = ',')]
+        tables: Vec<String>,
+    },
+
+    /// Print the syntax tree of a Solidity file as the TRAP records it
+    DumpAst {
+        /// Source file to parse (`-` or none to read standard input)
+        file: Option<PathBuf>,
+
+        /// Output form: indented, sexp or json
+        #[arg(long, default_value = "indented")]
+        format: extraction::AstFormat,
     },
 
     /// Generate database schema and QL library from tree-sitter grammar
@@ -76,12 +134,38 @@ enum Commands {
         /// Output directory for source archive
         #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_SOURCE_ARCHIVE_DIR")]
         source_archive_dir: PathBuf,
+
+        /// Source root, recorded as sourceLocationPrefix (defaults to --root)
+        #[arg(long)]
+        source_root: Option<PathBuf>,
+
+        /// Compression mode for TRAP files (gzip, zstd or none, optionally with
+        /// a level as in zstd:19; defaults to the configuration file's, or gzip)
+        #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_TRAP_COMPRESSION")]
+        compression: Option<String>,
+
+        /// Directory for reusing TRAP files of unchanged files between runs
+        #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_TRAP_CACHE")]
+        trap_cache: Option<PathBuf>,
+
+        /// File listing changed files (one per line, relative to the source root);
+        /// only they and the files they affect through imports are extracted. Other
+        /// files are copied from --trap-cache (of a base-branch run) if cached, and
+        /// left out otherwise; reading a base database is not implemented
+        #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_CHANGED_FILES")]
+        changed_files: Option<PathBuf>,
+
+        /// Path of the JSON extraction report (defaults to
+        /// extraction-report.json in the TRAP directory)
+        #[arg(long, env = "CODEQL_EXTRACTOR_SOLIDITY_OPTION_REPORT")]
+        report: Option<PathBuf>,
     },
 }
 
 fn main() -> Result<()> {
-    // Initialize logging
+    // Initialize logging (on stderr, so extract-file can print TRAP to stdout)
     tracing_subscriber::fmt()
+        .with_writer(io::stderr)
         .with_env_filter(
             EnvFilter::try_from_env("CODEQL_EXTRACTOR_SOLIDITY_LOG")
                 .unwrap_or_else(|_| EnvFilter::new("info")),
@@ -94,24 +178,72 @@ fn main() -> Result<()> {
     match cli.command {
         Commands::Extract {
             file_list,
+            bundles,
             trap_dir,
             source_archive_dir,
             compression,
             threads,
+            source_root,
+            trap_cache,
+            changed_files,
+            report,
         } => {
-            let compression = compression.parse::<Compression>().unwrap_or_default();
+            let compression = compression
+                .map(|c| c.parse::<Compression>().map_err(anyhow::Error::msg))
+                .transpose()?;
 
-            info!("Extracting from file list: {}", file_list.display());
+            if let Some(file_list) = &file_list {
+                info!("Extracting from file list: {}", file_list.display());
+            }
 
             extraction::run(extraction::ExtractOptions {
                 file_list,
+                bundles,
                 trap_dir,
                 source_archive_dir,
                 compression,
                 threads,
+                source_root,
+                trap_cache,
+                changed_files,
+                report,
             })?;
         }
 
+        Commands::ExtractFile {
+            file,
+            stdin_path,
+            source_root,
+            lines,
+            tables,
+        } => {
+            let mut filter = TrapFilter::default();
+            if !tables.is_empty() {
+                filter = filter.with_tables(&tables)?;
+            }
+            if let Some((start, end)) = lines {
+                filter = filter.with_lines(start, end);
+            }
+
+            ignore_broken_pipe(extraction::extract_single(
+                extraction::ExtractFileOptions {
+                    file: file.filter(|file| file.as_os_str() != "-"),
+                    stdin_path,
+                    source_root,
+                    filter,
+                },
+                &mut io::stdout().lock(),
+            ))?;
+        }
+
+        Commands::DumpAst { file, format } => {
+            ignore_broken_pipe(extraction::dump_file(
+                file.as_deref().filter(|file| file.as_os_str() != "-"),
+                format,
+                &mut io::stdout().lock(),
+            ))?;
+        }
+
         Commands::Generate { dbscheme, library } => {
             info!("Generating schema: {}", dbscheme.display());
             info!("Generating library: {}", library.display());
@@ -124,16 +256,43 @@ fn main() -> Result<()> {
             root,
             trap_dir,
             source_archive_dir,
+            source_root,
+            compression,
+            trap_cache,
+            changed_files,
+            report,
         } => {
+            let compression = compression
+                .map(|c| c.parse::<Compression>().map_err(anyhow::Error::msg))
+                .transpose()?;
+
             info!("Autobuilding from: {}", root.display());
 
             extraction::autobuild(extraction::AutobuildOptions {
                 root,
                 trap_dir,
                 source_archive_dir,
+                source_root,
+                compression,
+                trap_cache,
+                changed_files,
+                report,
             })?;
         }
     }
 
     Ok(())
 }
+
+/// Treat output closed by the reader (as with `| head`) as success.
+fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<io::Error>().map(io::Error::kind)
+                == Some(io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
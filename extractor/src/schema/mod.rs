@@ -183,7 +183,250 @@ diagnostics(
     int location: @location_default ref
 );
 
-// Source location prefix for URL generation
+case @diagnostic.severity of
+  10 = @diagnostic_debug
+| 20 = @diagnostic_info
+| 30 = @diagnostic_warning
+| 40 = @diagnostic_error
+;
+
+// Number of syntax errors (ERROR and MISSING nodes) found in each file
+solidity_parse_error_count(
+    unique int file: @file ref,
+    int count: int ref
+);
+
+// Build projects (Foundry, Hardhat, Truffle or unconfigured) in the source tree
+solidity_project(
+    unique int id: @solidity_project,
+    string root: string ref,
+    string framework: string ref
+);
+
+// Project each file belongs to
+solidity_project_file(
+    unique int file: @file ref,
+    int project: @solidity_project ref
+);
+
+// Role of each file in its project: `source`, `test`, `script` or `library`
+solidity_file_role(
+    unique int file: @file ref,
+    string role: string ref
+);
+
+// Files extracted in diff mode and why: `changed`, `importer` (imports a
+// changed file) or `dependency` (imported by one of those)
+solidity_diff_scope(
+    unique int file: @file ref,
+    string reason: string ref
+);
+
+// Compiler settings of each solc standard-JSON input (by its path)
+solidity_compiler_settings(
+    unique int id: @solidity_compiler_settings,
+    string input: string ref
+);
+
+// Import remappings of compiler settings, in order
+#keyset[settings, index]
+solidity_compiler_remapping(
+    int settings: @solidity_compiler_settings ref,
+    int index: int ref,
+    string remapping: string ref
+);
+
+// Optimizer settings: whether it is enabled (1) or not (0), and its runs
+solidity_compiler_optimizer(
+    unique int settings: @solidity_compiler_settings ref,
+    int enabled: int ref,
+    int runs: int ref
+);
+
+// Target EVM version of compiler settings
+solidity_compiler_evm_version(
+    unique int settings: @solidity_compiler_settings ref,
+    string version: string ref
+);
+
+// Whether code is generated through the IR (1) or not (0)
+solidity_compiler_via_ir(
+    unique int settings: @solidity_compiler_settings ref,
+    int via_ir: int ref
+);
+
+// Compiler version of compiler settings (as solc reports it)
+solidity_compiler_version(
+    unique int settings: @solidity_compiler_settings ref,
+    string version: string ref
+);
+
+// Compiler settings each file extracted from a standard-JSON input or
+// verified-source bundle was given
+#keyset[file, settings]
+solidity_compiler_settings_file(
+    int file: @file ref,
+    int settings: @solidity_compiler_settings ref
+);
+
+// Deployed contract whose verified source was extracted from a bundle: its
+// name ("" if unknown) and the settings it was compiled with
+solidity_deployed_contract(
+    unique int id: @solidity_deployed_contract,
+    string name: string ref,
+    int settings: @solidity_compiler_settings ref
+);
+
+// Address of a deployed contract
+solidity_deployed_contract_address(
+    unique int contract: @solidity_deployed_contract ref,
+    string address: string ref
+);
+
+// Chain id of the chain a contract is deployed on
+solidity_deployed_contract_chain(
+    unique int contract: @solidity_deployed_contract ref,
+    int chain_id: int ref
+);
+
+// ABI-encoded constructor arguments of a deployed contract, in hex
+solidity_deployed_contract_constructor_args(
+    unique int contract: @solidity_deployed_contract ref,
+    string args: string ref
+);
+
+// Build-info file (Hardhat or Foundry compiler output) that the compiler's
+// view of files was imported from, with the compiler version
+solidity_build_info(
+    unique int id: @solidity_build_info,
+    string path: string ref,
+    string solc_version: string ref
+);
+
+// Node of the compiler's AST, matched by source range to a syntax tree node
+solidity_solc_node(
+    unique int id: @solidity_solc_node,
+    int build: @solidity_build_info ref,
+    int solc_id: int ref,
+    string node_type: string ref,
+    int node: @solidity_ast_node ref
+);
+
+// Compiler id of the declaration a name refers to, as resolved by the
+// compiler (`referencedDeclaration`); the declaration is the node of the same
+// build with that id, if its file was extracted
+solidity_solc_referenced_declaration(
+    unique int node: @solidity_solc_node ref,
+    int declaration_id: int ref
+);
+
+// Compiler ids of the candidate declarations of an overloaded name
+// (`overloadedDeclarations`)
+#keyset[node, index]
+solidity_solc_overloaded_declaration(
+    int node: @solidity_solc_node ref,
+    int index: int ref,
+    int declaration_id: int ref
+);
+
+// Type of an expression or declaration (`typeDescriptions`)
+solidity_solc_type(
+    unique int node: @solidity_solc_node ref,
+    string type_string: string ref,
+    string type_identifier: string ref
+);
+
+// Compiler ids of the linearized base contracts of a contract, most derived
+// (itself) first
+#keyset[contract, index]
+solidity_solc_linearized_base(
+    int contract: @solidity_solc_node ref,
+    int index: int ref,
+    int base_id: int ref
+);
+
+// Lexical scope (`file`, `contract`, `function`, `block` or `yul_block`) and
+// the node introducing it
+solidity_scope(
+    unique int id: @solidity_scope,
+    string kind: string ref,
+    int node: @solidity_ast_node ref
+);
+
+// Scope enclosing each scope but the file's
+solidity_scope_parent(
+    unique int scope: @solidity_scope ref,
+    int parent: @solidity_scope ref
+);
+
+// Name declared in a scope; a file's scope includes the names it imports
+solidity_scope_declaration(
+    int scope: @solidity_scope ref,
+    string name: string ref,
+    int declaration: @solidity_binding_target ref
+);
+
+// Builtin name (`msg`) or member (`msg.sender`) names can be bound to
+solidity_builtin(
+    unique int id: @solidity_builtin,
+    string name: string ref
+);
+
+@solidity_binding_target = @solidity_ast_node | @solidity_builtin | @file
+
+// Declaration a name refers to, found from the syntax; a call to an
+// overloaded function may have several
+solidity_binding(
+    int reference: @solidity_ast_node ref,
+    int declaration: @solidity_binding_target ref
+);
+
+// Declaration hiding another of the same name in an enclosing scope or a
+// base contract
+solidity_shadowing(
+    int declaration: @solidity_ast_node ref,
+    int shadowed: @solidity_binding_target ref
+);
+
+// Type of an expression inferred from the syntax, as a canonical type string
+// (`uint256`, `contract IERC20`, `mapping(address => uint256)`)
+solidity_expr_type(
+    unique int expr: @solidity_ast_node ref,
+    string type: string ref
+);
+
+// Linearized bases of a contract, most derived (itself) first, as C3 orders
+// them across files
+#keyset[contract, index]
+solidity_contract_linearization(
+    int contract: @solidity_ast_node ref,
+    int index: int ref,
+    int base: @solidity_ast_node ref
+);
+
+// Function or modifier overridden by one declared `override`
+solidity_function_override(
+    int function: @solidity_ast_node ref,
+    int overridden: @solidity_ast_node ref
+);
+
+// File loaded by each resolved import directive
+solidity_import_resolution(
+    unique int import: @solidity_import_directive ref,
+    int file: @file ref
+);
+
+// Symbols bound by import directives (`*` for a whole-unit alias)
+#keyset[import, index]
+solidity_import_symbol(
+    int import: @solidity_import_directive ref,
+    int index: int ref,
+    string name: string ref,
+    string alias: string ref
+);
+
+// Source location prefix for URL generation: the absolute path of the source
+// root, which is stripped from the (absolute) paths in `files` in results
 sourceLocationPrefix(
     string prefix: string ref
 );
@@ -365,5 +608,16 @@ mod tests {
         assert!(schema.contains("locations_default"));
         assert!(schema.contains("files"));
         assert!(schema.contains("folders"));
+        assert!(schema.contains("solidity_parse_error_count"));
+        assert!(schema.contains("solidity_import_resolution"));
+        assert!(schema.contains("solidity_file_role"));
+        assert!(schema.contains("solidity_project_file"));
+        assert!(schema.contains("solidity_diff_scope"));
+        assert!(schema.contains("solidity_compiler_settings_file"));
+        assert!(schema.contains("solidity_deployed_contract_constructor_args"));
+        assert!(schema.contains("solidity_solc_referenced_declaration"));
+        assert!(schema.contains("solidity_scope_declaration"));
+        assert!(schema.contains("solidity_expr_type"));
+        assert!(schema.contains("solidity_contract_linearization"));
     }
 }
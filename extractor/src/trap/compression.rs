@@ -1,7 +1,11 @@
 //! TRAP file compression support.
 //!
-//! CodeQL supports both gzip-compressed and uncompressed TRAP files.
-//! Gzip is the default for production use; uncompressed is useful for debugging.
+//! CodeQL supports gzip- and zstd-compressed TRAP files as well as uncompressed
+//! ones. Gzip is the default for production use; zstd is much faster to write
+//! and import; uncompressed is useful for debugging.
+//!
+//! A mode is written `gzip`, `zstd` or `none`, optionally followed by a level
+//! for the compressed modes (`gzip:9`, `zstd:19`).
 
 use flate2::write::GzEncoder;
 use flate2::Compression as GzCompression;
@@ -9,59 +13,112 @@ use std::fs::File;
 use std::io::{self, BufWriter, Write};
 use std::path::Path;
 
+/// Default gzip level (that of `gzip` itself).
+pub const GZIP_DEFAULT_LEVEL: u32 = 6;
+
+/// Highest gzip level.
+const GZIP_MAX_LEVEL: u32 = 9;
+
+/// Default zstd level (that of `zstd` itself).
+pub const ZSTD_DEFAULT_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+/// Highest zstd level.
+const ZSTD_MAX_LEVEL: i32 = 22;
+
 /// Compression mode for TRAP files.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Compression {
-    /// Gzip compression (default, recommended for production)
-    #[default]
-    Gzip,
+    /// Gzip compression at the given level, 0-9 (default, recommended for production)
+    Gzip(u32),
+    /// Zstd compression at the given level, 1-22
+    Zstd(i32),
     /// No compression (useful for debugging)
     None,
 }
 
-impl Compression {
-    /// Parse compression mode from environment variable.
-    ///
-    /// Reads from `CODEQL_EXTRACTOR_SOLIDITY_OPTION_TRAP_COMPRESSION`.
-    /// Valid values: "gzip" (default), "none"
-    pub fn from_env() -> Self {
-        match std::env::var("CODEQL_EXTRACTOR_SOLIDITY_OPTION_TRAP_COMPRESSION")
-            .as_deref()
-            .unwrap_or("gzip")
-        {
-            "none" => Compression::None,
-            _ => Compression::Gzip,
-        }
-    }
-
-    /// Parse compression mode from string representation.
-    #[allow(dead_code)]
-    pub fn parse_str(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "none" => Compression::None,
-            _ => Compression::Gzip,
-        }
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Gzip(GZIP_DEFAULT_LEVEL)
     }
+}
 
+impl Compression {
     /// Get the file extension for this compression mode.
     pub fn extension(&self) -> &'static str {
         match self {
-            Compression::Gzip => ".trap.gz",
+            Compression::Gzip(_) => ".trap.gz",
+            Compression::Zstd(_) => ".trap.zst",
             Compression::None => ".trap",
         }
     }
 
     /// Create a writer for the given file path with appropriate compression.
-    pub fn create_writer(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+    pub fn create_writer(&self, path: &Path) -> io::Result<TrapFileWriter> {
         let file = File::create(path)?;
         let buffered = BufWriter::new(file);
 
-        match self {
-            Compression::Gzip => {
-                let encoder = GzEncoder::new(buffered, GzCompression::default());
-                Ok(Box::new(encoder))
+        match *self {
+            Compression::Gzip(level) => Ok(TrapFileWriter::Gzip(GzEncoder::new(
+                buffered,
+                GzCompression::new(level),
+            ))),
+            Compression::Zstd(level) => {
+                Ok(TrapFileWriter::Zstd(zstd::Encoder::new(buffered, level)?))
             }
-            Compression::None => Ok(Box::new(buffered)),
+            Compression::None => Ok(TrapFileWriter::Plain(buffered)),
+        }
+    }
+}
+
+/// A TRAP file being written.
+///
+/// The file is only complete once [`TrapFileWriter::finish`] has returned:
+/// dropping a writer loses the errors of writing the end of the compressed
+/// stream.
+pub enum TrapFileWriter {
+    /// Gzip-compressed output
+    Gzip(GzEncoder<BufWriter<File>>),
+    /// Zstd-compressed output
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+    /// Uncompressed output
+    Plain(BufWriter<File>),
+}
+
+impl TrapFileWriter {
+    /// End the compressed stream and flush the file.
+    pub fn finish(self) -> io::Result<()> {
+        let buffered = match self {
+            TrapFileWriter::Gzip(encoder) => encoder.finish()?,
+            TrapFileWriter::Zstd(encoder) => encoder.finish()?,
+            TrapFileWriter::Plain(buffered) => buffered,
+        };
+        buffered.into_inner().map_err(|e| e.into_error())?;
+        Ok(())
+    }
+}
+
+impl Write for TrapFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            TrapFileWriter::Gzip(encoder) => encoder.write(buf),
+            TrapFileWriter::Zstd(encoder) => encoder.write(buf),
+            TrapFileWriter::Plain(buffered) => buffered.write(buf),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            TrapFileWriter::Gzip(encoder) => encoder.write_all(buf),
+            TrapFileWriter::Zstd(encoder) => encoder.write_all(buf),
+            TrapFileWriter::Plain(buffered) => buffered.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            TrapFileWriter::Gzip(encoder) => encoder.flush(),
+            TrapFileWriter::Zstd(encoder) => encoder.flush(),
+            TrapFileWriter::Plain(buffered) => buffered.flush(),
         }
     }
 }
@@ -70,11 +127,38 @@ impl std::str::FromStr for Compression {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "gzip" | "gz" => Ok(Compression::Gzip),
-            "none" | "uncompressed" => Ok(Compression::None),
+        let lower = s.to_lowercase();
+        let (mode, level) = match lower.split_once(':') {
+            Some((mode, level)) => (mode, Some(level)),
+            None => (lower.as_str(), None),
+        };
+        let invalid_level = || {
+            format!(
+                "Invalid compression level in {}. Valid levels: 0-{} for gzip, 1-{} for zstd",
+                s, GZIP_MAX_LEVEL, ZSTD_MAX_LEVEL
+            )
+        };
+
+        match mode {
+            "gzip" | "gz" => match level {
+                None => Ok(Compression::Gzip(GZIP_DEFAULT_LEVEL)),
+                Some(level) => match level.parse() {
+                    Ok(level) if level <= GZIP_MAX_LEVEL => Ok(Compression::Gzip(level)),
+                    _ => Err(invalid_level()),
+                },
+            },
+            "zstd" | "zst" => match level {
+                None => Ok(Compression::Zstd(ZSTD_DEFAULT_LEVEL)),
+                Some(level) => match level.parse() {
+                    Ok(level) if (1..=ZSTD_MAX_LEVEL).contains(&level) => {
+                        Ok(Compression::Zstd(level))
+                    }
+                    _ => Err(invalid_level()),
+                },
+            },
+            "none" | "uncompressed" if level.is_none() => Ok(Compression::None),
             _ => Err(format!(
-                "Unknown compression mode: {}. Valid values: gzip, none",
+                "Unknown compression mode: {}. Valid values: gzip, zstd, none (gzip and zstd take an optional level, as in gzip:9)",
                 s
             )),
         }
@@ -83,8 +167,11 @@ impl std::str::FromStr for Compression {
 
 impl std::fmt::Display for Compression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Compression::Gzip => write!(f, "gzip"),
+        match *self {
+            Compression::Gzip(GZIP_DEFAULT_LEVEL) => write!(f, "gzip"),
+            Compression::Gzip(level) => write!(f, "gzip:{}", level),
+            Compression::Zstd(ZSTD_DEFAULT_LEVEL) => write!(f, "zstd"),
+            Compression::Zstd(level) => write!(f, "zstd:{}", level),
             Compression::None => write!(f, "none"),
         }
     }
@@ -93,17 +180,71 @@ impl std::fmt::Display for Compression {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::ScratchDir;
+    use std::io::Read;
 
     #[test]
     fn test_compression_extension() {
-        assert_eq!(Compression::Gzip.extension(), ".trap.gz");
+        assert_eq!(Compression::default().extension(), ".trap.gz");
+        assert_eq!(
+            Compression::Zstd(ZSTD_DEFAULT_LEVEL).extension(),
+            ".trap.zst"
+        );
         assert_eq!(Compression::None.extension(), ".trap");
     }
 
     #[test]
     fn test_compression_from_str() {
-        assert_eq!("gzip".parse::<Compression>().unwrap(), Compression::Gzip);
+        assert_eq!(
+            "gzip".parse::<Compression>().unwrap(),
+            Compression::default()
+        );
         assert_eq!("none".parse::<Compression>().unwrap(), Compression::None);
+        assert_eq!(
+            "zstd".parse::<Compression>().unwrap(),
+            Compression::Zstd(ZSTD_DEFAULT_LEVEL)
+        );
+        assert_eq!(
+            "gzip:9".parse::<Compression>().unwrap(),
+            Compression::Gzip(9)
+        );
+        assert_eq!(
+            "ZSTD:19".parse::<Compression>().unwrap(),
+            Compression::Zstd(19)
+        );
         assert!("invalid".parse::<Compression>().is_err());
+        assert!("gzip:10".parse::<Compression>().is_err());
+        assert!("zstd:0".parse::<Compression>().is_err());
+        assert!("none:1".parse::<Compression>().is_err());
+
+        for mode in ["gzip", "gzip:1", "zstd", "zstd:19", "none"] {
+            assert_eq!(mode.parse::<Compression>().unwrap().to_string(), mode);
+        }
+    }
+
+    #[test]
+    fn test_compressed_writers() {
+        let dir = ScratchDir::new("zst");
+        let text = "files(#1, \"A.sol\")\n".repeat(100);
+
+        for compression in [Compression::Gzip(1), Compression::Zstd(19)] {
+            let path = dir.join(format!("A{}", compression.extension()));
+            let mut writer = compression.create_writer(&path).unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+            writer.finish().unwrap();
+
+            let file = File::open(&path).unwrap();
+            let mut decoded = String::new();
+            match compression {
+                Compression::Gzip(_) => flate2::read::GzDecoder::new(file)
+                    .read_to_string(&mut decoded)
+                    .unwrap(),
+                _ => zstd::Decoder::new(file)
+                    .unwrap()
+                    .read_to_string(&mut decoded)
+                    .unwrap(),
+            };
+            assert_eq!(decoded, text, "{}", compression);
+        }
     }
 }
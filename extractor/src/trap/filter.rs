@@ -0,0 +1,241 @@
+//! Filtering of TRAP output, for debugging.
+//!
+//! A filter keeps the tuples of selected tables and/or the tuples about code
+//! on a range of lines, together with the label definitions they use. It works
+//! on the TRAP text this crate writes: one tuple or label definition per line.
+//!
+//! A tuple is about code outside the line range if one of its labels is a
+//! location outside the range (`locations_default`), or an entity whose
+//! location (`*_location` tables) is outside the range.
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+
+/// Table of locations: `locations_default(#loc, #file, start_line, start_col, end_line, end_col)`.
+const LOCATIONS_TABLE: &str = "locations_default";
+
+/// A line of TRAP text.
+enum TrapLine<'a> {
+    /// A label definition (`#1=*` or `#1=@"key"`)
+    LabelDef { label: &'a str },
+    /// A tuple
+    Tuple {
+        table: &'a str,
+        values: Vec<&'a str>,
+    },
+    /// A comment or blank line
+    Other,
+}
+
+/// Selects part of a TRAP file.
+#[derive(Debug, Clone, Default)]
+pub struct TrapFilter {
+    /// Tables to keep (None = all)
+    tables: Option<GlobSet>,
+    /// Lines to keep tuples about (1-based, inclusive; None = all)
+    lines: Option<(u64, u64)>,
+}
+
+impl TrapFilter {
+    /// Keep only tuples of tables matching one of these globs
+    /// (`solidity_*_def`, `files`).
+    pub fn with_tables(mut self, tables: &[String]) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for table in tables {
+            builder.add(Glob::new(table.trim())?);
+        }
+        self.tables = Some(builder.build()?);
+        Ok(self)
+    }
+
+    /// Keep only tuples about code overlapping lines `start..=end` (1-based).
+    pub fn with_lines(mut self, start: u64, end: u64) -> Self {
+        self.lines = Some((start, end));
+        self
+    }
+
+    /// Filter TRAP text.
+    pub fn apply(&self, trap: &str) -> String {
+        let lines: Vec<(&str, TrapLine)> =
+            trap.lines().map(|line| (line, parse_line(line))).collect();
+
+        // Locations, and then located entities, outside the line range
+        let mut excluded: HashSet<&str> = HashSet::new();
+        if let Some((start, end)) = self.lines {
+            for (_, line) in &lines {
+                if let TrapLine::Tuple { table, values } = line {
+                    if *table == LOCATIONS_TABLE && values.len() == 6 {
+                        let start_line = values[2].parse::<u64>().unwrap_or(0);
+                        let end_line = values[4].parse::<u64>().unwrap_or(0);
+                        if start_line > end || end_line < start {
+                            excluded.insert(values[0]);
+                        }
+                    }
+                }
+            }
+            for (_, line) in &lines {
+                if let TrapLine::Tuple { table, values } = line {
+                    if table.ends_with("_location")
+                        && values.len() == 2
+                        && excluded.contains(values[1])
+                    {
+                        excluded.insert(values[0]);
+                    }
+                }
+            }
+        }
+
+        // Tuples to keep, and the labels they use
+        let keep = |table: &str, values: &[&str]| {
+            self.tables
+                .as_ref()
+                .is_none_or(|tables| tables.is_match(table))
+                && !values.iter().any(|value| excluded.contains(value))
+        };
+        let mut used: HashSet<&str> = HashSet::new();
+        for (_, line) in &lines {
+            if let TrapLine::Tuple { table, values } = line {
+                if keep(table, values) {
+                    used.extend(values.iter().filter(|value| value.starts_with('#')));
+                }
+            }
+        }
+
+        let mut output = String::new();
+        for (text, line) in &lines {
+            let kept = match line {
+                TrapLine::LabelDef { label } => used.contains(label),
+                TrapLine::Tuple { table, values } => keep(table, values),
+                TrapLine::Other => text.starts_with("//"),
+            };
+            if kept {
+                output.push_str(text);
+                output.push('\n');
+            }
+        }
+        output
+    }
+}
+
+/// Parse a line range: `N` or `START-END` (1-based, inclusive).
+pub fn parse_line_range(s: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("Invalid line range: {}. Expected N or START-END", s);
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let start: u64 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u64 = end.trim().parse().map_err(|_| invalid())?;
+    if start == 0 || end < start {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+/// Parse a line of TRAP text.
+fn parse_line(line: &str) -> TrapLine<'_> {
+    if line.starts_with('#') {
+        if let Some((label, _)) = line.split_once('=') {
+            return TrapLine::LabelDef { label };
+        }
+    }
+    match line.split_once('(') {
+        Some((table, rest)) if !line.starts_with("//") && rest.ends_with(')') => TrapLine::Tuple {
+            table,
+            values: split_values(&rest[..rest.len() - 1]),
+        },
+        _ => TrapLine::Other,
+    }
+}
+
+/// Split the values of a tuple, keeping quoted strings (which may contain
+/// commas and doubled quotes) whole.
+fn split_values(values: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    for (i, c) in values.char_indices() {
+        match c {
+            // A doubled quote inside a string toggles twice
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                result.push(values[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !values.trim().is_empty() {
+        result.push(values[start..].trim());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRAP: &str = "\
+// CodeQL TRAP file generated by codeql-extractor-solidity
+
+#1=@\"A.sol\"
+files(#1, \"A.sol\")
+#2=*
+solidity_contract_declaration_def(#2)
+#3=*
+locations_default(#3, #1, 1, 1, 3, 1)
+solidity_ast_node_location(#2, #3)
+#4=*
+solidity_identifier_def(#4)
+#5=*
+locations_default(#5, #1, 5, 1, 5, 3)
+solidity_ast_node_location(#4, #5)
+solidity_tokeninfo(#4, 1, \"a, \"\"b\"\"\")
+solidity_ast_node_parent(#4, #2, 0)
+";
+
+    #[test]
+    fn test_filter_tables() {
+        let filter = TrapFilter::default()
+            .with_tables(&["solidity_*_def".to_string(), "files".to_string()])
+            .unwrap();
+        let output = filter.apply(TRAP);
+        assert!(output.starts_with("// CodeQL TRAP file"));
+        assert!(output.contains("files(#1, \"A.sol\")"));
+        assert!(output.contains("solidity_identifier_def(#4)"));
+        assert!(output.contains("#4=*"));
+        assert!(!output.contains("locations_default"));
+        // Unused label definitions are dropped
+        assert!(!output.contains("#3=*"));
+    }
+
+    #[test]
+    fn test_filter_lines() {
+        let output = TrapFilter::default().with_lines(1, 2).apply(TRAP);
+        assert!(output.contains("solidity_contract_declaration_def(#2)"));
+        assert!(output.contains("locations_default(#3, #1, 1, 1, 3, 1)"));
+        assert!(!output.contains("#4"));
+        assert!(!output.contains("#5"));
+
+        let output = TrapFilter::default().with_lines(5, 5).apply(TRAP);
+        assert!(output.contains("solidity_tokeninfo(#4, 1, \"a, \"\"b\"\"\")"));
+        // The contract is outside the range, so is its relation to the identifier
+        assert!(!output.contains("solidity_ast_node_parent"));
+        assert!(!output.contains("#3"));
+    }
+
+    #[test]
+    fn test_split_values() {
+        assert_eq!(
+            split_values("#1, \"a, \"\"b\"\"\", 3"),
+            vec!["#1", "\"a, \"\"b\"\"\"", "3"]
+        );
+        assert!(split_values("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_line_range() {
+        assert_eq!(parse_line_range("12"), Ok((12, 12)));
+        assert_eq!(parse_line_range("3-40"), Ok((3, 40)));
+        assert!(parse_line_range("0").is_err());
+        assert!(parse_line_range("9-3").is_err());
+        assert!(parse_line_range("a-b").is_err());
+    }
+}
@@ -5,9 +5,11 @@
 //! with no external dependencies.
 
 mod compression;
+mod filter;
 mod label;
 mod writer;
 
-pub use compression::Compression;
+pub use compression::{Compression, TrapFileWriter};
+pub use filter::{parse_line_range, TrapFilter};
 pub use label::{Label, LabelGenerator};
-pub use writer::{TrapValue, TrapWriter};
+pub use writer::{remove_trap_file, TrapValue, TrapWriter};
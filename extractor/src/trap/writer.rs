@@ -3,15 +3,23 @@
 //! TRAP (Tracing Data) files contain relational tuples that the CodeQL CLI
 //! imports into a database. This writer generates valid TRAP syntax.
 //!
+//! Tuples are written out as they are emitted, straight into the (possibly
+//! compressed) output, so memory use does not grow with the size of the file.
+//!
 //! IMPORTANT: Labels must be defined before they can be used in tuples.
-//! - Fresh labels: `#prefix_id = *`
-//! - Key labels: `@"content" = @"content"` (self-referential for files/folders)
-
-use super::{Compression, Label, LabelGenerator};
-use std::collections::{HashMap, HashSet};
-use std::fmt::Write as FmtWrite;
+//! Every label is defined at the moment it is created, so any label handed out
+//! by the writer is already defined when it is used:
+//! - Fresh labels: `#id=*`
+//! - Key labels: `#id=@"key"` (a numeric label naming a global key)
+
+use super::{Compression, Label, LabelGenerator, TrapFileWriter};
+use std::collections::HashMap;
+use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// First line of every TRAP file.
+const HEADER: &str = "// CodeQL TRAP file generated by codeql-extractor-solidity\n\n";
 
 /// A value in a TRAP tuple.
 #[derive(Debug, Clone)]
@@ -30,194 +38,188 @@ pub enum TrapValue {
 }
 
 impl TrapValue {
-    /// Format this value for TRAP output.
-    fn format(&self) -> String {
+    /// Write this value in TRAP syntax.
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
         match self {
-            TrapValue::Label(l) => l.to_string(),
-            TrapValue::String(s) => format!("\"{}\"", Self::escape_string(s)),
-            TrapValue::Int(i) => i.to_string(),
-            TrapValue::UInt(u) => u.to_string(),
-            TrapValue::Float(f) => f.to_string(),
+            TrapValue::Label(l) => out.write_all(l.as_str().as_bytes()),
+            TrapValue::String(s) => {
+                out.write_all(b"\"")?;
+                Self::write_escaped(s, out)?;
+                out.write_all(b"\"")
+            }
+            TrapValue::Int(i) => write!(out, "{}", i),
+            TrapValue::UInt(u) => write!(out, "{}", u),
+            TrapValue::Float(f) => write!(out, "{}", f),
         }
     }
 
-    /// Escape special characters in a string for TRAP format.
+    /// Write a string with special characters escaped for TRAP format.
     /// In TRAP, double quotes are escaped by doubling them (""),
     /// and backslashes are escaped by doubling them (\\).
-    fn escape_string(s: &str) -> String {
-        let mut result = String::with_capacity(s.len());
-        for c in s.chars() {
-            match c {
-                '"' => result.push_str("\"\""), // TRAP uses doubled quotes, not backslash
-                '\\' => result.push_str("\\\\"),
-                '\n' => result.push_str("\\n"),
-                '\r' => result.push_str("\\r"),
-                '\t' => result.push_str("\\t"),
-                '\0' => result.push_str("\\0"),
-                c if c.is_control() => {
-                    result.push_str(&format!("\\x{:02x}", c as u32));
-                }
-                c => result.push(c),
+    fn write_escaped(s: &str, out: &mut impl Write) -> io::Result<()> {
+        // Unescaped runs are written in one go
+        let mut run_start = 0;
+        for (i, c) in s.char_indices() {
+            let escaped = match c {
+                '"' => "\"\"", // TRAP uses doubled quotes, not backslash
+                '\\' => "\\\\",
+                '\n' => "\\n",
+                '\r' => "\\r",
+                '\t' => "\\t",
+                '\0' => "\\0",
+                c if c.is_control() => "",
+                _ => continue,
+            };
+            out.write_all(&s.as_bytes()[run_start..i])?;
+            if escaped.is_empty() {
+                write!(out, "\\x{:02x}", c as u32)?;
+            } else {
+                out.write_all(escaped.as_bytes())?;
             }
+            run_start = i + c.len_utf8();
         }
-        result
+        out.write_all(&s.as_bytes()[run_start..])
     }
 }
 
-/// A TRAP table entry (a single tuple).
-#[derive(Debug, Clone)]
-pub struct TrapEntry {
-    /// Table name
-    pub table: String,
-    /// Column values
-    pub values: Vec<TrapValue>,
+/// Where a writer's output goes.
+enum Sink {
+    /// Kept in memory, to be written out later (or inspected)
+    Memory(Vec<u8>),
+    /// Streamed to a TRAP file
+    File(TrapFileWriter),
 }
 
-impl TrapEntry {
-    /// Create a new TRAP entry.
-    pub fn new(table: impl Into<String>, values: Vec<TrapValue>) -> Self {
-        TrapEntry {
-            table: table.into(),
-            values,
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Memory(buffer) => buffer.write(buf),
+            Sink::File(writer) => writer.write(buf),
         }
     }
 
-    /// Format this entry as a TRAP line.
-    fn format(&self) -> String {
-        let values: Vec<String> = self.values.iter().map(|v| v.format()).collect();
-        format!("{}({})", self.table, values.join(", "))
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            Sink::Memory(buffer) => buffer.write_all(buf),
+            Sink::File(writer) => writer.write_all(buf),
+        }
     }
-}
 
-/// A label definition in TRAP format.
-#[derive(Debug, Clone)]
-pub enum LabelDef {
-    /// Fresh label: `#id=*`
-    Fresh(Label),
-    /// Key label: `#id=@"key"` (numeric label assigned to a key)
-    Key { numeric: Label, key: Label },
-}
-
-impl LabelDef {
-    /// Format this label definition for TRAP output.
-    /// Note: TRAP format requires no spaces around '=' in label definitions
-    fn format(&self) -> String {
+    fn flush(&mut self) -> io::Result<()> {
         match self {
-            LabelDef::Fresh(l) => format!("{}=*", l),
-            LabelDef::Key { numeric, key } => format!("{}={}", numeric, key),
+            Sink::Memory(_) => Ok(()),
+            Sink::File(writer) => writer.flush(),
         }
     }
 }
 
 /// TRAP file writer.
 ///
-/// Collects TRAP entries and writes them to a file with optional compression.
-/// Properly tracks label definitions to ensure they are defined before use.
-#[derive(Default)]
+/// A writer either streams into a TRAP file ([`TrapWriter::create`]) or keeps
+/// its (small) output in memory ([`TrapWriter::new`]). Write errors are
+/// remembered and reported by [`TrapWriter::finish`], so emitting never fails.
 pub struct TrapWriter {
     /// Label generator for this file
     labels: LabelGenerator,
-    /// Label definitions (must be written before tuples)
-    label_defs: Vec<LabelDef>,
-    /// Set of already-defined labels (to avoid duplicates)
-    defined_labels: HashSet<String>,
-    /// Collected entries
-    entries: Vec<TrapEntry>,
-    /// Comments to include in output
-    comments: Vec<String>,
-    /// Cache for string labels (deduplication)
+    /// Output
+    sink: Sink,
+    /// First write error, if any
+    error: Option<io::Error>,
+    /// Number of tuples written
+    entries: usize,
+    /// Numeric labels of the keys defined so far
     string_cache: HashMap<String, Label>,
 }
 
+impl Default for TrapWriter {
+    fn default() -> Self {
+        Self::with_sink(LabelGenerator::default(), Sink::Memory(Vec::new()))
+    }
+}
+
 impl TrapWriter {
-    /// Create a new TRAP writer for the given file.
+    /// Create a TRAP writer for the given file that keeps its output in memory.
     pub fn new(file_path: &str) -> Self {
-        TrapWriter {
-            labels: LabelGenerator::new(file_path),
-            label_defs: Vec::new(),
-            defined_labels: HashSet::new(),
-            entries: Vec::new(),
-            comments: Vec::new(),
+        Self::with_sink(LabelGenerator::new(file_path), Sink::Memory(Vec::new()))
+    }
+
+    /// Create a TRAP writer for the given file that streams into `path`.
+    ///
+    /// The compression's extension is added unless `path` already ends with it.
+    pub fn create(file_path: &str, path: &Path, compression: Compression) -> io::Result<Self> {
+        let writer = compression.create_writer(&trap_file_path(path, compression))?;
+        Ok(Self::with_sink(
+            LabelGenerator::new(file_path),
+            Sink::File(writer),
+        ))
+    }
+
+    /// Create a writer and write the file header.
+    fn with_sink(labels: LabelGenerator, sink: Sink) -> Self {
+        let mut writer = TrapWriter {
+            labels,
+            sink,
+            error: None,
+            entries: 0,
             string_cache: HashMap::new(),
-        }
+        };
+        writer.write(|out| out.write_all(HEADER.as_bytes()));
+        writer
     }
 
-    /// Get a reference to the label generator.
-    #[allow(dead_code)]
-    pub fn labels(&self) -> &LabelGenerator {
-        &self.labels
+    /// Write to the output unless an earlier write failed.
+    fn write(&mut self, write: impl FnOnce(&mut Sink) -> io::Result<()>) {
+        if self.error.is_none() {
+            if let Err(e) = write(&mut self.sink) {
+                self.error = Some(e);
+            }
+        }
     }
 
     /// Generate a fresh label and define it.
     pub fn fresh_label(&mut self) -> Label {
         let label = self.labels.fresh();
-        self.define_fresh_label(&label);
+        self.write(|out| writeln!(out, "{}=*", label));
         label
     }
 
-    /// Define a fresh label (ensures it's only defined once).
-    fn define_fresh_label(&mut self, label: &Label) {
-        let key = label.as_str().to_string();
-        if !self.defined_labels.contains(&key) {
-            self.defined_labels.insert(key);
-            self.label_defs.push(LabelDef::Fresh(label.clone()));
-        }
-    }
-
-    /// Define a key label (ensures it's only defined once).
+    /// Define a key label (only once per key).
     /// Creates a numeric label that references the key.
     fn define_key_label(&mut self, key_label: &Label) -> Label {
-        let key_str = key_label.as_str().to_string();
-        if let Some(existing) = self.string_cache.get(&key_str) {
+        if let Some(existing) = self.string_cache.get(key_label.as_str()) {
             return existing.clone();
         }
 
         // Create a fresh numeric label for this key
+        // Note: TRAP format requires no spaces around '=' in label definitions
         let numeric = self.labels.fresh();
-        self.label_defs.push(LabelDef::Key {
-            numeric: numeric.clone(),
-            key: key_label.clone(),
-        });
-        self.defined_labels.insert(key_str.clone());
-        self.string_cache.insert(key_str, numeric.clone());
+        self.write(|out| writeln!(out, "{}={}", numeric, key_label));
+        self.string_cache
+            .insert(key_label.as_str().to_string(), numeric.clone());
         numeric
     }
 
     /// Get or create a label for a string value.
     /// Uses caching to deduplicate identical strings.
-    #[allow(dead_code)]
     pub fn string_label(&mut self, s: &str) -> Label {
-        if let Some(label) = self.string_cache.get(s) {
-            return label.clone();
-        }
-
         let key_label = self.labels.key(s);
-
         self.define_key_label(&key_label)
     }
 
-    /// Add a comment to the output.
-    #[allow(dead_code)]
-    pub fn comment(&mut self, text: impl Into<String>) {
-        self.comments.push(text.into());
-    }
-
-    /// Emit a TRAP entry.
-    pub fn emit(&mut self, table: impl Into<String>, values: Vec<TrapValue>) {
-        self.entries.push(TrapEntry::new(table, values));
-    }
-
-    /// Emit a tuple with the given label as the first value.
-    #[allow(dead_code)]
-    pub fn emit_with_label(
-        &mut self,
-        table: impl Into<String>,
-        label: Label,
-        values: Vec<TrapValue>,
-    ) {
-        let mut all_values = vec![TrapValue::Label(label)];
-        all_values.extend(values);
-        self.emit(table, all_values);
+    /// Emit a TRAP tuple.
+    pub fn emit(&mut self, table: impl AsRef<str>, values: Vec<TrapValue>) {
+        self.entries += 1;
+        self.write(|out| {
+            out.write_all(table.as_ref().as_bytes())?;
+            out.write_all(b"(")?;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.write_all(b", ")?;
+                }
+                value.write_to(out)?;
+            }
+            out.write_all(b")\n")
+        });
     }
 
     /// Emit a file entry and return its label.
@@ -273,72 +275,82 @@ impl TrapWriter {
     }
 
     /// Get the number of entries written.
-    #[allow(dead_code)]
     pub fn entry_count(&self) -> usize {
-        self.entries.len()
+        self.entries
     }
 
-    /// Format all entries to a string.
+    /// Get the output of an in-memory writer (empty for a streaming writer).
     pub fn format(&self) -> String {
-        let mut output = String::new();
-
-        // Write header comment
-        writeln!(
-            output,
-            "// CodeQL TRAP file generated by codeql-extractor-solidity"
-        )
-        .unwrap();
-        writeln!(output).unwrap();
-
-        // Write user comments
-        for comment in &self.comments {
-            writeln!(output, "// {}", comment).unwrap();
-        }
-        if !self.comments.is_empty() {
-            writeln!(output).unwrap();
+        match &self.sink {
+            Sink::Memory(buffer) => String::from_utf8_lossy(buffer).into_owned(),
+            Sink::File(_) => String::new(),
         }
+    }
 
-        // Write label definitions FIRST (required before use in tuples)
-        if !self.label_defs.is_empty() {
-            writeln!(output, "// Label definitions").unwrap();
-            for label_def in &self.label_defs {
-                writeln!(output, "{}", label_def.format()).unwrap();
+    /// Write the output of an in-memory writer to a file with the specified
+    /// compression.
+    ///
+    /// The compression's extension is added unless `path` already ends with it.
+    pub fn write_to_file(&self, path: &Path, compression: Compression) -> io::Result<()> {
+        let mut writer = compression.create_writer(&trap_file_path(path, compression))?;
+        self.write_to(&mut writer)?;
+        writer.finish()
+    }
+
+    /// Write the output of an in-memory writer to a writer.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        match &self.sink {
+            Sink::Memory(buffer) => {
+                writer.write_all(buffer)?;
+                writer.flush()
             }
-            writeln!(output).unwrap();
+            Sink::File(_) => Err(io::Error::other("TRAP output was already streamed")),
         }
+    }
 
-        // Write tuple entries
-        for entry in &self.entries {
-            writeln!(output, "{}", entry.format()).unwrap();
+    /// Complete the output file, reporting the first write error.
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        match self.sink {
+            Sink::Memory(_) => Ok(()),
+            Sink::File(writer) => writer.finish(),
         }
-
-        output
     }
+}
 
-    /// Write to a file with the specified compression.
-    pub fn write_to_file(&self, path: &Path, compression: Compression) -> io::Result<()> {
-        let trap_path = path.with_extension(compression.extension().trim_start_matches('.'));
-        let mut writer = compression.create_writer(&trap_path)?;
-        writer.write_all(self.format().as_bytes())?;
-        writer.flush()?;
-        Ok(())
+/// Get the path of a TRAP file: `path` with the compression's extension.
+fn trap_file_path(path: &Path, compression: Compression) -> PathBuf {
+    if path.to_string_lossy().ends_with(compression.extension()) {
+        path.to_path_buf()
+    } else {
+        path.with_extension(compression.extension().trim_start_matches('.'))
     }
+}
 
-    /// Write to a writer.
-    #[allow(dead_code)]
-    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        writer.write_all(self.format().as_bytes())?;
-        writer.flush()
-    }
+/// Remove a partially written TRAP file, ignoring errors.
+pub fn remove_trap_file(path: &Path, compression: Compression) {
+    let _ = fs::remove_file(trap_file_path(path, compression));
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::ScratchDir;
+
+    /// Get the tuple lines (neither comments nor label definitions) of an output.
+    fn tuples(output: &str) -> Vec<&str> {
+        output
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with("//") && !line.contains('='))
+            .collect()
+    }
 
     #[test]
     fn test_trap_entry_format() {
-        let entry = TrapEntry::new(
+        let mut writer = TrapWriter::new("/test/file.sol");
+        writer.emit(
             "test_table",
             vec![
                 TrapValue::Label(Label::new("#1")),
@@ -346,17 +358,24 @@ mod tests {
                 TrapValue::Int(42),
             ],
         );
-        assert_eq!(entry.format(), "test_table(#1, \"hello\", 42)");
+        assert_eq!(
+            tuples(&writer.format()),
+            vec!["test_table(#1, \"hello\", 42)"]
+        );
     }
 
     #[test]
     fn test_string_escaping() {
-        let entry = TrapEntry::new(
+        let mut writer = TrapWriter::new("/test/file.sol");
+        writer.emit(
             "strings",
-            vec![TrapValue::String("hello\nworld\"test".to_string())],
+            vec![TrapValue::String("hello\nworld\"test\u{1}é".to_string())],
         );
         // TRAP uses doubled quotes for escaping, not backslash-quote
-        assert_eq!(entry.format(), "strings(\"hello\\nworld\"\"test\")");
+        assert_eq!(
+            tuples(&writer.format()),
+            vec!["strings(\"hello\\nworld\"\"test\\x01é\")"]
+        );
     }
 
     #[test]
@@ -399,6 +418,7 @@ mod tests {
         let file_def_pos = output.find(&format!("{}=@\"/test/file.sol\"", file_label));
         let loc_def_pos = output.find(&format!("{}=*", loc_label));
         let tuple_pos = output.find("files(");
+        let loc_tuple_pos = output.find("locations_default(");
 
         assert!(
             file_def_pos.is_some(),
@@ -414,8 +434,61 @@ mod tests {
             "File label definition must come before tuple"
         );
         assert!(
-            loc_def_pos.unwrap() < tuple_pos.unwrap(),
+            loc_def_pos.unwrap() < loc_tuple_pos.unwrap(),
             "Location label definition must come before tuple"
         );
+
+        // A key is only defined once
+        assert_eq!(writer.string_label("/test/file.sol"), file_label);
+        assert_eq!(writer.format().matches("=@\"/test/file.sol\"").count(), 1);
+    }
+
+    #[test]
+    fn test_streaming_matches_in_memory_output() {
+        let dir = ScratchDir::new("trap");
+        let emit = |writer: &mut TrapWriter| {
+            let file = writer.emit_file("a/B.sol");
+            for line in 1..100 {
+                writer.emit_location(&file, line, 1, line, 10);
+            }
+        };
+
+        let mut in_memory = TrapWriter::new("a/B.sol");
+        emit(&mut in_memory);
+        let mut streaming =
+            TrapWriter::create("a/B.sol", &dir.join("B"), Compression::None).unwrap();
+        emit(&mut streaming);
+        streaming.finish().unwrap();
+
+        let streamed = fs::read_to_string(dir.join("B.trap")).unwrap();
+        assert_eq!(streamed, in_memory.format());
+    }
+
+    #[test]
+    fn test_finished_compressed_files_are_complete() {
+        use std::io::Read;
+
+        let dir = ScratchDir::new("finish");
+        let mut expected = TrapWriter::new("a/B.sol");
+        expected.emit_file("a/B.sol");
+
+        for compression in [Compression::Gzip(9), Compression::Zstd(3)] {
+            let mut writer = TrapWriter::create("a/B.sol", &dir.join("B"), compression).unwrap();
+            writer.emit_file("a/B.sol");
+            writer.finish().unwrap();
+
+            let file = fs::File::open(trap_file_path(&dir.join("B"), compression)).unwrap();
+            let mut decoded = String::new();
+            match compression {
+                Compression::Gzip(_) => flate2::read::GzDecoder::new(file)
+                    .read_to_string(&mut decoded)
+                    .unwrap(),
+                _ => zstd::Decoder::new(file)
+                    .unwrap()
+                    .read_to_string(&mut decoded)
+                    .unwrap(),
+            };
+            assert_eq!(decoded, expected.format(), "{}", compression);
+        }
     }
 }
@@ -0,0 +1,16 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+contract Base {
+    function helper(uint256 x) internal pure returns (uint256) {
+        return x;
+    }
+
+    function helper(uint256 x, uint256 y) internal pure returns (uint256) {
+        return x + y;
+    }
+
+    function hook() internal virtual returns (uint256) {
+        return 1;
+    }
+}
@@ -0,0 +1,22 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+import "./Base.sol";
+
+contract Derived is Base {
+    function hook() internal virtual override returns (uint256) {
+        return super.hook();
+    }
+
+    function one() public pure returns (uint256) {
+        return helper(1);
+    }
+
+    function two() public pure returns (uint256) {
+        return helper(1, 2);
+    }
+
+    function local() public returns (uint256) {
+        return hook();
+    }
+}
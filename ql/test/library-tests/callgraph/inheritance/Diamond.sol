@@ -0,0 +1,17 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+contract A {
+    function f() public virtual {}
+}
+
+contract B is A {}
+
+contract C is A {
+    function f() public virtual override {}
+}
+
+contract D is B, C {}
+
+// B is A, so A must be listed first: the bases of F cannot be linearized
+contract F is B, A {}